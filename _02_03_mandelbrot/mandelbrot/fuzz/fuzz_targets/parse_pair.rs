@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mandelbrot::parse::{parse_complex, parse_pair};
+
+// Run with `cargo fuzz run parse_pair` from the mandelbrot directory.
+// The parsers must never panic, and error spans must always index the input safely.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data)
+    {
+        for result in [parse_pair::<f64>(s, 'x').map(|_| ()), parse_pair::<i32>(s, ',').map(|_| ()), parse_complex(s).map(|_| ())]
+        {
+            if let Err(err) = result
+            {
+                let _ = &s[err.span.clone()];
+                let _ = err.underline(s);
+            }
+        }
+    }
+});
@@ -0,0 +1,155 @@
+//! Adaptive anti-aliasing (`--aa adaptive`): extra samples only where they show.
+//!
+//! Supersampling every pixel (`--ssaa`) multiplies the work everywhere, though most of a typical image is smooth
+//! gradient that one sample already gets right. The adaptive pass starts from the render at one sample per pixel
+//! and looks at each pixel's 3x3 neighborhood: where the brightness varies by more than the threshold, the pixel
+//! is on an edge or a filament, and it is rendered again from GRID x GRID samples whose colors are averaged.
+//!
+//! The threshold is a variance of luma on a 0..1 scale, so 0.002 flags neighborhoods whose brightness spreads by
+//! about 4.5% either way. Each sample lies at a random spot in its own cell of a grid over the pixel (jittered
+//! sampling), which turns the stair steps a regular grid leaves on near-horizontal edges into fine noise. The
+//! jitter is seeded by the pixel's position, so a render comes out the same every time and on any number of threads.
+
+use crate::progress::Progress;
+use crate::{compare, mix64, paint_pixel, parallel_rows, Settings};
+use num::Complex;
+
+/// Samples along each side of a refined pixel.
+pub const GRID: usize = 4;
+
+/// The threshold when --aa-threshold isn't given.
+pub const DEFAULT_THRESHOLD: f64 = 0.002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Antialiasing
+{
+    None,     //One sample per pixel, or --ssaa's fixed grid.
+    Adaptive, //Extra samples where the neighborhood varies.
+}
+
+impl std::str::FromStr for Antialiasing
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "none" => Ok(Antialiasing::None),
+            "adaptive" => Ok(Antialiasing::Adaptive),
+            _ => Err(format!("unknown anti-aliasing '{}' (expected none or adaptive)", s)),
+        }
+    }
+}
+
+/// Which pixels of an image of size 'bounds' have a 3x3 neighborhood (clipped at the edges) whose luma varies by
+/// more than 'threshold'.
+pub fn flag(pixels: &[u8], bounds: (usize, usize), channels: usize, threshold: f64) -> Vec<bool>
+{
+    let luma: Vec<f64> = compare::luma(pixels, channels).into_iter().map(|value| value / 255.0).collect();
+    (0..bounds.0 * bounds.1).map(|i|
+    {
+        let (column, row) = (i % bounds.0, i / bounds.0);
+        let (mut sum, mut squares, mut n) = (0.0, 0.0, 0.0);
+        for y in row.saturating_sub(1)..(row + 2).min(bounds.1)
+        {
+            for x in column.saturating_sub(1)..(column + 2).min(bounds.0)
+            {
+                let value = luma[y * bounds.0 + x];
+                (sum, squares, n) = (sum + value, squares + value * value, n + 1.0);
+            }
+        }
+        let mean = sum / n;
+        squares / n - mean * mean > threshold
+    }).collect()
+}
+
+/// Render the pixels flag() picks out again with GRID x GRID jittered samples each, in place. 'pixels' holds the
+/// one-sample render of the view with these 'settings'. Returns how many pixels were refined, and advances
+/// 'progress' by a unit per row.
+pub fn refine(pixels: &mut [u8], bounds: (usize, usize), (upper_left, lower_right): (Complex<f64>, Complex<f64>),
+              settings: &Settings, threshold: f64, threads: usize, progress: &Progress) -> usize
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    let flags = flag(pixels, bounds, channels, threshold);
+    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    let spacing = width / bounds.0 as f64;
+    let samples = GRID * GRID;
+    parallel_rows(pixels, bounds.0 * channels, threads, progress, |top, row|
+    {
+        let mut sample = [0u8; 3];
+        for (column, pixel) in row.chunks_mut(channels).enumerate().filter(|&(column, _)| flags[top * bounds.0 + column])
+        {
+            let mut sums = [0usize; 3];
+            for k in 0..samples
+            {
+                //Two 32-bit uniform numbers from one hash place the sample within its cell.
+                let hash = mix64(((top * bounds.0 + column) * samples + k) as u64);
+                let jitter = ((hash & 0xffff_ffff) as f64 / 4294967296.0, (hash >> 32) as f64 / 4294967296.0);
+                let x = column as f64 + ((k % GRID) as f64 + jitter.0) / GRID as f64;
+                let y = top as f64 + ((k / GRID) as f64 + jitter.1) / GRID as f64;
+                let point = Complex{re: upper_left.re + x * width / bounds.0 as f64, im: upper_left.im - y * height / bounds.1 as f64};
+                let sample = &mut sample[..channels];
+                paint_pixel(point, spacing, settings, sample);
+                for (sum, &value) in sums.iter_mut().zip(sample.iter())
+                {
+                    *sum += value as usize;
+                }
+            }
+            for (value, sum) in pixel.iter_mut().zip(sums)
+            {
+                *value = ((sum + samples / 2) / samples) as u8;
+            }
+        }
+    });
+    flags.iter().filter(|&&flagged| flagged).count()
+}
+
+#[test]
+fn test_flag()
+{
+    //A vertical edge between black and white: only the columns next to it vary.
+    let bounds = (6, 4);
+    let pixels: Vec<u8> = (0..bounds.0 * bounds.1).map(|i| if i % bounds.0 < 3 { 0 } else { 255 }).collect();
+    let flags = flag(&pixels, bounds, 1, DEFAULT_THRESHOLD);
+    for (i, &flagged) in flags.iter().enumerate()
+    {
+        assert_eq!(flagged, i % bounds.0 == 2 || i % bounds.0 == 3, "pixel {}", i);
+    }
+    assert!(flag(&[40; 3 * 6 * 4], bounds, 3, DEFAULT_THRESHOLD).iter().all(|&flagged| !flagged));
+    assert_eq!("adaptive".parse(), Ok(Antialiasing::Adaptive));
+    assert!("fxaa".parse::<Antialiasing>().is_err());
+}
+
+#[test]
+fn test_refine()
+{
+    use crate::palette::Palette;
+    use crate::{render, render_rows};
+    let bounds = (48, 36);
+    let corners = (Complex{re: -2.0, im: 1.2}, Complex{re: 0.6, im: -0.75});
+    let settings = Settings{limit: 200, palette: Palette::builtin("classic"), ..Settings::default()};
+    let mut single = vec![0u8; bounds.0 * bounds.1 * 3];
+    render(&mut single, bounds, corners.0, corners.1, &settings);
+    let mut reference = vec![0u8; single.len()];
+    render(&mut reference, bounds, corners.0, corners.1, &Settings{ssaa: 8, ..settings.clone()});
+
+    let mut pixels = single.clone();
+    let refined = refine(&mut pixels, bounds, corners, &settings, DEFAULT_THRESHOLD, 3, &Progress::silent());
+    //Some of the image, not all of it, and only flagged pixels change.
+    assert!(refined > 0 && refined < bounds.0 * bounds.1 / 2, "{}", refined);
+    let flags = flag(&single, bounds, 3, DEFAULT_THRESHOLD);
+    for (i, flagged) in flags.into_iter().enumerate()
+    {
+        assert!(flagged || pixels[i * 3..i * 3 + 3] == single[i * 3..i * 3 + 3], "pixel {}", i);
+    }
+    //Closer to a heavily supersampled render than one sample per pixel was.
+    let error = |image: &[u8]| image.iter().zip(&reference).map(|(&a, &b)| (a as f64 - b as f64).abs()).sum::<f64>();
+    assert!(error(&pixels) < error(&single), "{} >= {}", error(&pixels), error(&single));
+    //Repeatable, whatever the threads.
+    let mut again = vec![0u8; single.len()];
+    render_rows(&mut again, bounds, corners.0, corners.1, &settings, 2, &Progress::silent());
+    refine(&mut again, bounds, corners, &settings, DEFAULT_THRESHOLD, 1, &Progress::silent());
+    assert_eq!(again, pixels);
+}
@@ -0,0 +1,297 @@
+//! Vector outlines of the set boundary, for cutting machines and design software.
+//!
+//! The region is sampled on the pixel-corner grid of a Viewport, marching squares turns the inside/outside
+//! samples into line segments, the segments are chained into polylines, and Douglas–Peucker simplification
+//! drops the points that do not change the shape by more than a tolerance. The result can be written as SVG
+//! (in pixel coordinates, optionally smoothed into cubic Béziers) or as GeoJSON (in complex-plane coordinates).
+
+use crate::viewport::Viewport;
+use num::Complex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// The file formats outlines can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlineFormat
+{
+    Svg,
+    GeoJson,
+}
+
+impl OutlineFormat
+{
+    /// The format for a file name's extension, or None if it is not one we know.
+    pub fn from_path(path: &Path) -> Option<OutlineFormat>
+    {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str()
+        {
+            "svg" => Some(OutlineFormat::Svg),
+            "geojson" | "json" => Some(OutlineFormat::GeoJson),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for OutlineFormat
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "svg" => Ok(OutlineFormat::Svg),
+            "geojson" => Ok(OutlineFormat::GeoJson),
+            _ => Err(format!("unknown outline format '{}' (expected svg or geojson)", s)),
+        }
+    }
+}
+
+/// One traced outline, as points in fractional pixel coordinates. Closed outlines repeat their first point at
+/// the end; open ones run into the edge of the viewport.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline
+{
+    pub points: Vec<(f64, f64)>,
+    pub closed: bool,
+}
+
+/// Trace the boundary of the region where 'inside' is true. For the Mandelbrot set, 'inside' is
+/// `|c| escape_time(c, limit).is_none()`.
+pub fn trace<F: Fn(Complex<f64>) -> bool>(view: &Viewport, inside: F) -> Vec<Outline>
+{
+    let (w, h) = view.bounds;
+    //Sample at the (w+1) x (h+1) pixel corners, so the outline covers the whole image.
+    let mut grid = vec![false; (w + 1) * (h + 1)];
+    for y in 0..=h
+    {
+        for x in 0..=w
+        {
+            grid[y * (w + 1) + x] = inside(view.pixel_to_point((x, y)));
+        }
+    }
+    let at = |x: usize, y: usize| grid[y * (w + 1) + x];
+
+    //Edge midpoints are named by doubled coordinates, so they are exact integers shared between cells.
+    let mut links: HashMap<(i64, i64), Vec<(i64, i64)>> = HashMap::new();
+    let mut link = |a: (i64, i64), b: (i64, i64)|
+    {
+        links.entry(a).or_default().push(b);
+        links.entry(b).or_default().push(a);
+    };
+    for y in 0..h
+    {
+        for x in 0..w
+        {
+            let (tl, tr, br, bl) = (at(x, y), at(x + 1, y), at(x + 1, y + 1), at(x, y + 1));
+            let (x2, y2) = (2 * x as i64, 2 * y as i64);
+            let top = (x2 + 1, y2);
+            let right = (x2 + 2, y2 + 1);
+            let bottom = (x2 + 1, y2 + 2);
+            let left = (x2, y2 + 1);
+            let mut crossed = Vec::with_capacity(4);
+            if tl != tr { crossed.push(top); }
+            if tr != br { crossed.push(right); }
+            if br != bl { crossed.push(bottom); }
+            if bl != tl { crossed.push(left); }
+            match crossed.len()
+            {
+                2 => link(crossed[0], crossed[1]),
+                //Saddle: two diagonal corners are inside. Cut each inside corner off on its own.
+                4 if tl => { link(top, left); link(bottom, right); }
+                4 => { link(top, right); link(bottom, left); }
+                _ => {}
+            }
+        }
+    }
+
+    //Walk the links into polylines. Every midpoint has at most two neighbours, so each walk is unambiguous;
+    //start open chains from their ends first so they are not split in the middle.
+    let mut keys: Vec<(i64, i64)> = links.keys().copied().collect();
+    keys.sort_by_key(|key| (links[key].len() != 1, *key));
+    let mut outlines = Vec::new();
+    for start in keys
+    {
+        if links[&start].is_empty()
+        {
+            continue;
+        }
+        let mut chain = vec![start];
+        let mut current = start;
+        while let Some(next) = links.get_mut(&current).and_then(|neighbours| neighbours.pop())
+        {
+            if let Some(back) = links.get_mut(&next)
+            {
+                if let Some(index) = back.iter().position(|&p| p == current)
+                {
+                    back.swap_remove(index);
+                }
+            }
+            chain.push(next);
+            current = next;
+        }
+        let closed = chain.len() > 2 && chain.first() == chain.last();
+        let points = chain.iter().map(|&(x2, y2)| (x2 as f64 / 2.0, y2 as f64 / 2.0)).collect();
+        outlines.push(Outline{points, closed});
+    }
+    outlines
+}
+
+/// Distance from 'p' to the segment from 'a' to 'b'.
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64
+{
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sqr = dx * dx + dy * dy;
+    let t = if length_sqr == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / length_sqr).clamp(0.0, 1.0) };
+    let (nx, ny) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+    (nx * nx + ny * ny).sqrt()
+}
+
+/// Douglas–Peucker simplification: keep the endpoints, and recursively keep the point farthest from the
+/// chord whenever it is more than 'tolerance' away. The first and last points are always kept, so closed
+/// outlines stay closed.
+pub fn simplify(points: &[(f64, f64)], tolerance: f64) -> Vec<(f64, f64)>
+{
+    if points.len() < 3
+    {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop()
+    {
+        let mut farthest = (0.0, first);
+        for i in first + 1..last
+        {
+            let d = segment_distance(points[i], points[first], points[last]);
+            if d > farthest.0
+            {
+                farthest = (d, i);
+            }
+        }
+        if farthest.0 > tolerance
+        {
+            keep[farthest.1] = true;
+            stack.push((first, farthest.1));
+            stack.push((farthest.1, last));
+        }
+    }
+    points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+/// An SVG document drawing the outlines as black strokes over an image-sized canvas. With 'smooth', each
+/// polyline becomes a chain of cubic Béziers through the same points (Catmull–Rom tangents).
+pub fn to_svg(outlines: &[Outline], bounds: (usize, usize), smooth: bool) -> String
+{
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#, bounds.0, bounds.1);
+    for outline in outlines
+    {
+        let p = &outline.points;
+        if p.len() < 2
+        {
+            continue;
+        }
+        let mut d = format!("M{:.2} {:.2}", p[0].0, p[0].1);
+        for i in 1..p.len()
+        {
+            if smooth
+            {
+                //Neighbours of the segment p[i-1]..p[i], wrapping around closed outlines.
+                let before = if i >= 2 { p[i - 2] } else if outline.closed { p[p.len() - 2] } else { p[i - 1] };
+                let after = if i + 1 < p.len() { p[i + 1] } else if outline.closed { p[1] } else { p[i] };
+                let c1 = (p[i - 1].0 + (p[i].0 - before.0) / 6.0, p[i - 1].1 + (p[i].1 - before.1) / 6.0);
+                let c2 = (p[i].0 - (after.0 - p[i - 1].0) / 6.0, p[i].1 - (after.1 - p[i - 1].1) / 6.0);
+                let _ = write!(d, " C{:.2} {:.2} {:.2} {:.2} {:.2} {:.2}", c1.0, c1.1, c2.0, c2.1, p[i].0, p[i].1);
+            }
+            else
+            {
+                let _ = write!(d, " L{:.2} {:.2}", p[i].0, p[i].1);
+            }
+        }
+        if outline.closed
+        {
+            d.push_str(" Z");
+        }
+        let _ = writeln!(svg, r#"  <path d="{}" fill="none" stroke="black" stroke-width="1"/>"#, d);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A GeoJSON FeatureCollection with one MultiLineString in complex-plane coordinates ([re, im] pairs).
+pub fn to_geojson(outlines: &[Outline], view: &Viewport) -> String
+{
+    let lines: Vec<String> = outlines.iter()
+        .map(|outline|
+        {
+            let points: Vec<String> = outline.points.iter()
+                .map(|&p| { let c = view.point_at(p); format!("[{:e},{:e}]", c.re, c.im) })
+                .collect();
+            format!("[{}]", points.join(","))
+        })
+        .collect();
+    format!("{{\"type\":\"FeatureCollection\",\"features\":[{{\"type\":\"Feature\",\"properties\":{{}},\
+             \"geometry\":{{\"type\":\"MultiLineString\",\"coordinates\":[{}]}}}}]}}\n", lines.join(","))
+}
+
+#[cfg(test)]
+fn unit_disk_view() -> Viewport
+{
+    Viewport::from_corners((40, 40), Complex{re: -2.0, im: 2.0}, Complex{re: 2.0, im: -2.0})
+}
+
+#[test]
+fn test_trace_disk_gives_one_closed_outline()
+{
+    let view = unit_disk_view();
+    let outlines = trace(&view, |c| c.norm() < 1.0);
+    assert_eq!(outlines.len(), 1);
+    assert!(outlines[0].closed);
+    for &p in &outlines[0].points
+    {
+        //Every vertex lies within a pixel (0.1 units here) of the unit circle.
+        assert!((view.point_at(p).norm() - 1.0).abs() < 0.1);
+    }
+}
+
+#[test]
+fn test_trace_open_outline_at_the_edge()
+{
+    let view = unit_disk_view();
+    let outlines = trace(&view, |c| c.re < 0.05);
+    assert_eq!(outlines.len(), 1);
+    assert!(!outlines[0].closed);
+    assert_eq!(outlines[0].points.len(), 41);
+}
+
+#[test]
+fn test_simplify()
+{
+    let line: Vec<(f64, f64)> = (0..=10).map(|i| (i as f64, 0.0)).collect();
+    assert_eq!(simplify(&line, 0.1), vec![(0.0, 0.0), (10.0, 0.0)]);
+    let corner = [(0.0, 0.0), (1.0, 0.05), (2.0, 0.0), (2.0, 1.0), (2.0, 2.0)];
+    assert_eq!(simplify(&corner, 0.1), vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0)]);
+    assert_eq!(simplify(&corner, 0.01).len(), 4);
+}
+
+#[test]
+fn test_svg_and_geojson_output()
+{
+    let view = unit_disk_view();
+    let outlines: Vec<Outline> = trace(&view, |c| c.norm() < 1.0).into_iter()
+        .map(|o| Outline{points: simplify(&o.points, 0.5), ..o})
+        .collect();
+    let svg = to_svg(&outlines, view.bounds, false);
+    assert!(svg.starts_with("<svg") && svg.contains(" Z\"") && svg.trim_end().ends_with("</svg>"));
+    assert!(to_svg(&outlines, view.bounds, true).contains(" C"));
+    let json = to_geojson(&outlines, &view);
+    assert!(json.contains("\"MultiLineString\"") && json.matches('[').count() > 4);
+    assert_eq!(OutlineFormat::from_path(Path::new("cut/outline.GeoJSON")), Some(OutlineFormat::GeoJson));
+    assert_eq!(OutlineFormat::from_path(Path::new("outline.png")), None);
+    assert_eq!("svg".parse(), Ok(OutlineFormat::Svg));
+    assert!("dxf".parse::<OutlineFormat>().is_err());
+}
@@ -0,0 +1,254 @@
+//! The Buddhabrot: instead of coloring each c by how its orbit ends, pick many random c, and for each one that
+//! escapes, mark every point its orbit visited on the way out. The density of marks is the image.
+//!
+//! Samples are drawn in fixed-size batches, each with its own random sequence derived from the seed and the
+//! batch number. Threads take batches from a shared counter and add into their own histogram, and the histograms
+//! are summed at the end. Addition doesn't care about order, so the same seed gives the same image with any
+//! number of threads.
+//!
+//! A Nebulabrot is several Buddhabrots at once, one per color channel, each with its own iteration limit: an
+//! orbit is counted in every layer whose limit it escapes within. Histograms interleave the layers pixel by
+//! pixel, like the channels of the final image.
+
+use crate::{in_cardioid_or_bulb, mix64};
+use crate::palette::Palette;
+use crate::progress::Progress;
+use num::Complex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Samples per batch; small enough to balance the threads, large enough that handing out batches is cheap.
+const BATCH: u64 = 4096;
+
+/// Only orbits that escape within this square can be sampled; every escaping c with |c| <= 2 lies inside it.
+const SAMPLE_RADIUS: f64 = 2.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buddhabrot
+{
+    pub samples: u64,        //How many random c to try.
+    pub min_iter: usize,     //Orbits escaping in fewer iterations than this are not drawn.
+    pub limits: Vec<usize>,  //Iteration limit of each layer; orbits still going past it are left out of that layer.
+    pub seed: u64,
+}
+
+/// A xorshift64 generator, the same kind the parser's junk test uses. Plenty for scattering sample points.
+struct XorShift
+{
+    state: u64,
+}
+
+impl XorShift
+{
+    /// A generator for batch 'batch' of a run seeded with 'seed'. Mixing spreads neighbouring batch numbers over
+    /// unrelated states, and the low bit keeps it away from the all-zero state xorshift gets stuck in.
+    fn for_batch(seed: u64, batch: u64) -> XorShift
+    {
+        XorShift{state: mix64(seed.wrapping_add(batch.wrapping_mul(0x9e37_79b9_7f4a_7c15))) | 1}
+    }
+
+    /// A uniform float in -1..1.
+    fn next_signed(&mut self) -> f64
+    {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+/// The pixel a point of the plane falls in, if it is inside the view. The inverse of crate::pixel_to_point.
+fn point_to_pixel(bounds: (usize, usize), point: Complex<f64>, upper_left: Complex<f64>, lower_right: Complex<f64>) -> Option<usize>
+{
+    let x = (point.re - upper_left.re) / (lower_right.re - upper_left.re) * bounds.0 as f64;
+    let y = (upper_left.im - point.im) / (upper_left.im - lower_right.im) * bounds.1 as f64;
+    if x >= 0.0 && y >= 0.0 && x < bounds.0 as f64 && y < bounds.1 as f64
+    {
+        Some(y as usize * bounds.0 + x as usize)
+    }
+    else
+    {
+        None
+    }
+}
+
+/// The number of iterations c takes to escape, if it does within 'limit'.
+fn escape_count(c: Complex<f64>, limit: usize) -> Option<usize>
+{
+    let mut z = Complex{re: 0.0, im: 0.0};
+    for i in 0..limit
+    {
+        z = z * z + c;
+        if z.norm_sqr() > 4.0
+        {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Add the samples of one batch to 'histogram'.
+fn run_batch(histogram: &mut [u32], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+             settings: &Buddhabrot, batch: u64)
+{
+    let mut rng = XorShift::for_batch(settings.seed, batch);
+    let count = BATCH.min(settings.samples - batch * BATCH);
+    let layers = settings.limits.len();
+    let max_iter = settings.limits.iter().cloned().max().unwrap_or(0);
+    for _ in 0..count
+    {
+        let c = Complex{re: rng.next_signed() * SAMPLE_RADIUS, im: rng.next_signed() * SAMPLE_RADIUS};
+        //Those never escape, and they are a large share of the samples.
+        if in_cardioid_or_bulb(c)
+        {
+            continue;
+        }
+        let Some(escape) = escape_count(c, max_iter) else { continue };
+        if escape < settings.min_iter
+        {
+            continue;
+        }
+        //Run the orbit again, this time marking where it goes. The last point is already outside radius 2.
+        let mut z = Complex{re: 0.0, im: 0.0};
+        for _ in 0..escape
+        {
+            z = z * z + c;
+            if let Some(index) = point_to_pixel(bounds, z, upper_left, lower_right)
+            {
+                for (layer, &limit) in settings.limits.iter().enumerate()
+                {
+                    if escape <= limit
+                    {
+                        histogram[index * layers + layer] = histogram[index * layers + layer].saturating_add(1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Count, for every pixel of the view and every layer, how many escaping orbits pass through it.
+/// 'progress' advances by samples.
+pub fn accumulate(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Buddhabrot,
+                  threads: usize, progress: &Progress) -> Vec<u32>
+{
+    let batches = settings.samples.div_ceil(BATCH);
+    let next_batch = AtomicU64::new(0);
+    let histograms: Vec<Vec<u32>> = std::thread::scope(|spawner|
+    {
+        let workers: Vec<_> = (0..threads.max(1)).map(|_| spawner.spawn(||
+        {
+            let mut histogram = vec![0u32; bounds.0 * bounds.1 * settings.limits.len()];
+            loop
+            {
+                let batch = next_batch.fetch_add(1, Ordering::Relaxed);
+                if batch >= batches
+                {
+                    break;
+                }
+                run_batch(&mut histogram, bounds, upper_left, lower_right, settings, batch);
+                progress.advance(BATCH.min(settings.samples - batch * BATCH));
+            }
+            histogram
+        })).collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+    let mut total = vec![0u32; bounds.0 * bounds.1 * settings.limits.len()];
+    for histogram in histograms
+    {
+        for (sum, count) in total.iter_mut().zip(histogram)
+        {
+            *sum = sum.saturating_add(count);
+        }
+    }
+    total
+}
+
+/// Turn orbit counts ('layers' interleaved layers) into brightness 0..1. Each layer is scaled on its own so the
+/// busiest 0.1% of its lit pixels saturate (a handful of very hot pixels would otherwise leave everything else
+/// dark), and then raised to 1/gamma to bring out the faint outer orbits.
+pub fn tone_map(histogram: &[u32], layers: usize, gamma: f64) -> Vec<f64>
+{
+    let mut brightness = vec![0.0; histogram.len()];
+    for layer in 0..layers
+    {
+        let counts = || histogram.iter().skip(layer).step_by(layers);
+        let mut lit: Vec<u32> = counts().cloned().filter(|&count| count > 0).collect();
+        if lit.is_empty()
+        {
+            continue;
+        }
+        let rank = (lit.len() - 1) - (lit.len() - 1) / 1000;
+        let (_, &mut white, _) = lit.select_nth_unstable(rank);
+        for (b, &count) in brightness.iter_mut().skip(layer).step_by(layers).zip(counts())
+        {
+            *b = (count as f64 / white as f64).min(1.0).powf(1.0 / gamma);
+        }
+    }
+    brightness
+}
+
+/// Pixels for tone-mapped brightness values: a byte per value (gray for one layer, RGB for three), or palette
+/// colors for a single layer.
+pub fn colorize(brightness: &[f64], palette: Option<&Palette>) -> Vec<u8>
+{
+    match palette
+    {
+        None => brightness.iter().map(|&b| (b * 255.0).round() as u8).collect(),
+        Some(palette) => brightness.iter().flat_map(|&b| palette.color(b)).collect(),
+    }
+}
+
+#[test]
+fn test_point_to_pixel()
+{
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.0}, Complex{re: 2.0, im: -1.0});
+    for (column, row) in [(0, 0), (3, 1), (7, 3)]
+    {
+        let corner = crate::pixel_to_point((8, 4), (column, row), upper_left, lower_right);
+        let inside = corner + Complex{re: 0.01, im: -0.01};
+        assert_eq!(point_to_pixel((8, 4), inside, upper_left, lower_right), Some(row * 8 + column));
+    }
+    assert_eq!(point_to_pixel((8, 4), Complex{re: 2.5, im: 0.0}, upper_left, lower_right), None);
+    assert_eq!(point_to_pixel((8, 4), Complex{re: 0.0, im: -1.0}, upper_left, lower_right), None);
+}
+
+#[test]
+fn test_accumulate_is_independent_of_threads()
+{
+    let settings = Buddhabrot{samples: 20_000, min_iter: 5, limits: vec![200], seed: 7};
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.5}, Complex{re: 1.0, im: -1.5});
+    let one = accumulate((24, 24), upper_left, lower_right, &settings, 1, &Progress::silent());
+    let three = accumulate((24, 24), upper_left, lower_right, &settings, 3, &Progress::silent());
+    assert_eq!(one, three);
+    assert!(one.iter().any(|&count| count > 0));
+    //The image is symmetric about the real axis on average; rows 0 and 23 see similar traffic.
+    let row = |y: usize| one[y * 24..(y + 1) * 24].iter().map(|&c| c as f64).sum::<f64>();
+    assert!((row(2) - row(21)).abs() < 0.25 * (row(2) + row(21)));
+    //Another seed gives a different sample of the same density.
+    assert_ne!(accumulate((24, 24), upper_left, lower_right, &Buddhabrot{seed: 8, ..settings.clone()}, 1, &Progress::silent()), one);
+    //Layers: the layer with the higher limit sees every orbit the lower one does, and more. The 200 layer
+    //matches the single-layer render.
+    let layered = accumulate((24, 24), upper_left, lower_right, &Buddhabrot{limits: vec![20, 200], ..settings}, 2, &Progress::silent());
+    assert!(layered.chunks(2).all(|pixel| pixel[0] <= pixel[1]));
+    assert!(layered.chunks(2).any(|pixel| pixel[0] < pixel[1]));
+    assert_eq!(layered.iter().skip(1).step_by(2).cloned().collect::<Vec<u32>>(), one);
+}
+
+#[test]
+fn test_tone_map()
+{
+    let mut histogram = vec![0u32; 2000];
+    for (i, count) in histogram.iter_mut().enumerate().skip(1000)
+    {
+        *count = i as u32 - 999;
+    }
+    histogram[0] = 1_000_000; //One hot pixel doesn't set the scale.
+    let brightness = tone_map(&histogram, 1, 1.0);
+    assert_eq!(brightness[1], 0.0);
+    assert_eq!(brightness[0], 1.0);
+    assert!(brightness[1999] == 1.0 && brightness[1500] > 0.49 && brightness[1500] < 0.51);
+    assert_eq!(tone_map(&[0, 4], 1, 2.0), vec![0.0, 1.0]);
+    assert_eq!(tone_map(&[0, 0], 1, 2.0), vec![0.0, 0.0]);
+    //Layers are scaled separately.
+    assert_eq!(tone_map(&[1, 100, 4, 0, 2, 50], 2, 1.0), vec![0.25, 1.0, 1.0, 0.0, 0.5, 0.5]);
+}
@@ -0,0 +1,93 @@
+//! Image quality metrics, for checking that cheaper settings still give the same picture.
+//!
+//! PSNR (peak signal-to-noise ratio) is the mean squared difference over every channel of every pixel, in decibels
+//! below the largest possible difference: 10·log10(255² / MSE). Identical images score infinity, and past about
+//! 40 dB the differences are hard to see. It treats every pixel alike, so a little noise everywhere scores the
+//! same as one badly wrong region.
+//!
+//! SSIM (structural similarity, Wang et al. 2004) compares the brightness, contrast and correlation of the two
+//! images' luma in small windows and averages the scores, which tracks what people notice more closely: 1 means
+//! identical, and above about 0.98 the images usually look the same.
+
+/// Side of the square SSIM windows, in pixels; windows overlap by half.
+const WINDOW: usize = 8;
+
+/// The PSNR of 'b' against 'a' in dB; infinite when they are equal. Both must be the same length.
+pub fn psnr(a: &[u8], b: &[u8]) -> f64
+{
+    assert_eq!(a.len(), b.len(), "images differ in size");
+    let squared: f64 = a.iter().zip(b).map(|(&x, &y)| (x as f64 - y as f64).powi(2)).sum();
+    let mse = squared / a.len().max(1) as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// The brightness of each pixel (Rec. 601 weights for RGB), 0..255.
+pub fn luma(pixels: &[u8], channels: usize) -> Vec<f64>
+{
+    match channels
+    {
+        1 => pixels.iter().map(|&v| v as f64).collect(),
+        _ => pixels.chunks(channels).map(|p| 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64).collect(),
+    }
+}
+
+/// The mean SSIM of 'b' against 'a', images of size 'bounds' with 'channels' channels, compared by luma.
+/// Images smaller than a window are compared as one window.
+pub fn ssim(a: &[u8], b: &[u8], bounds: (usize, usize), channels: usize) -> f64
+{
+    assert_eq!(a.len(), b.len(), "images differ in size");
+    let (x, y) = (luma(a, channels), luma(b, channels));
+    //The constants keep flat, dark windows from dividing by almost nothing.
+    let c1 = (0.01 * 255.0f64).powi(2);
+    let c2 = (0.03 * 255.0f64).powi(2);
+    let size = (WINDOW.min(bounds.0), WINDOW.min(bounds.1));
+    let starts = |length: usize, window: usize| (0..=length.saturating_sub(window)).step_by((window / 2).max(1));
+    let (mut total, mut windows) = (0.0, 0);
+    for top in starts(bounds.1, size.1)
+    {
+        for left in starts(bounds.0, size.0)
+        {
+            let indices = || (top..top + size.1).flat_map(move |row| (left..left + size.0).map(move |column| row * bounds.0 + column));
+            let n = (size.0 * size.1) as f64;
+            let mean_x = indices().map(|i| x[i]).sum::<f64>() / n;
+            let mean_y = indices().map(|i| y[i]).sum::<f64>() / n;
+            let (mut var_x, mut var_y, mut covariance) = (0.0, 0.0, 0.0);
+            for i in indices()
+            {
+                var_x += (x[i] - mean_x).powi(2);
+                var_y += (y[i] - mean_y).powi(2);
+                covariance += (x[i] - mean_x) * (y[i] - mean_y);
+            }
+            let (var_x, var_y, covariance) = (var_x / n, var_y / n, covariance / n);
+            total += (2.0 * mean_x * mean_y + c1) * (2.0 * covariance + c2)
+                / ((mean_x * mean_x + mean_y * mean_y + c1) * (var_x + var_y + c2));
+            windows += 1;
+        }
+    }
+    if windows == 0 { 1.0 } else { total / windows as f64 }
+}
+
+#[test]
+fn test_psnr()
+{
+    let a = [10u8, 20, 30, 40];
+    assert_eq!(psnr(&a, &a), f64::INFINITY);
+    //Every value off by 1: MSE 1, so 20·log10(255) = 48.13 dB.
+    assert!((psnr(&a, &[11, 21, 31, 41]) - 48.13).abs() < 0.01);
+    assert!(psnr(&a, &[0, 0, 0, 0]) < psnr(&a, &[11, 21, 31, 41]));
+}
+
+#[test]
+fn test_ssim()
+{
+    let bounds = (24, 16);
+    let image: Vec<u8> = (0..bounds.0 * bounds.1 * 3).map(|i| ((i / 3 % bounds.0) * 10 + (i / 3 / bounds.0) * 3) as u8).collect();
+    assert!((ssim(&image, &image, bounds, 3) - 1.0).abs() < 1e-12);
+    let noisy: Vec<u8> = image.iter().enumerate().map(|(i, &v)| v.saturating_add((i * 7919 % 13) as u8)).collect();
+    let inverted: Vec<u8> = image.iter().map(|&v| 255 - v).collect();
+    let (slight, opposite) = (ssim(&image, &noisy, bounds, 3), ssim(&image, &inverted, bounds, 3));
+    assert!(slight > 0.8 && slight < 1.0, "{}", slight);
+    assert!(opposite < 0.0, "{}", opposite);
+    assert_eq!(ssim(&[5, 6], &[5, 6], (2, 1), 1), 1.0);
+    assert!((luma(&[255, 255, 255], 3)[0] - 255.0).abs() < 1e-9);
+}
@@ -0,0 +1,313 @@
+//! Config files: the settings of a render kept in a `mandelbrot.toml` and loaded with `--config FILE`, so a
+//! poster doesn't need ten flags retyped each time.
+//!
+//! The file is a flat list of TOML `key = value` lines. Keys are option names without their dashes
+//! (`limit = 1000` is `--limit 1000`, `print-link = true` is `--print-link`), plus four for the positional
+//! arguments: `output`, `size`, `upper_left` and `lower_right`. Values may be strings, numbers, booleans or
+//! one-line arrays; an array is joined with commas (`center = [-0.75, 0.1]`), or with an x for `size`.
+//! `help` and `config` only make sense on the command line, and are errors in a file.
+//!
+//! Numbers are passed on as written, so coordinates keep every digit even past what f64 holds. Only this much of
+//! TOML is understood: tables, multi-line strings and dates are rejected with the line they are on.
+//!
+//! Layering works through the command line itself: the file's options are placed before the real ones, and as
+//! the last of a repeated option wins, flags typed on the command line override the file; a switch the file turns
+//! on (`quiet = true`) is turned off again with `--quiet=false`. Positional arguments on the command line take the
+//! first places (FILE, then PIXELS, ...) and the file fills in the rest.
+
+use std::fs;
+use std::path::Path;
+
+/// The keys that stand for positional arguments, in order.
+const POSITIONAL: [&str; 4] = ["output", "size", "upper_left", "lower_right"];
+
+/// Options that only mean something typed on the command line.
+const COMMAND_LINE_ONLY: [&str; 2] = ["help", "config"];
+
+/// What `mandelbrot config init` writes.
+pub const TEMPLATE: &str = r#"# Settings for `mandelbrot --config mandelbrot.toml`.
+#
+# Every key is optional, and flags on the command line override the values here. Any other option can be set
+# by its name without the dashes, e.g. coloring = "smooth" or print-link = true.
+
+# The image to write (relative to the directory mandelbrot runs in) and its size in pixels.
+output = "mandelbrot.png"
+size = "1920x1080"
+
+# The view, by its corners...
+upper_left = "-2.5,1.2"
+lower_right = "1,-1.2"
+# ...or by a center and zoom (zoom 1 is 4 units wide), or a named location; leave the corners out then.
+# Long coordinates keep every digit, quoted or not.
+# center = "-0.7453,0.1127"
+# zoom = 200
+# location = "seahorse"
+
+# Coloring.
+# palette = "classic"
+# coloring = "smooth"
+
+# The iteration limit.
+limit = 1000
+
+# Threads to render with (default: one per CPU).
+# threads = 8
+"#;
+
+/// A config file turned into command-line form.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Config
+{
+    pub options: Vec<String>,            //Options and their values, e.g. ["--limit", "1000", "--smooth"].
+    pub positional: [Option<String>; 4], //FILE, PIXELS, UPPERLEFT, LOWERRIGHT, where given.
+}
+
+impl Config
+{
+    /// Read and parse a config file.
+    pub fn load(path: &Path) -> Result<Config, String>
+    {
+        let text = fs::read_to_string(path).map_err(|err| format!("reading {}: {}", path.display(), err))?;
+        Config::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Parse the text of a config file.
+    pub fn parse(text: &str) -> Result<Config, String>
+    {
+        let mut config = Config::default();
+        let mut seen: Vec<String> = Vec::new();
+        for (index, line) in text.lines().enumerate()
+        {
+            let in_line = |err: String| format!("line {}: {}", index + 1, err);
+            let line = strip_comment(line).trim();
+            if line.is_empty()
+            {
+                continue;
+            }
+            if line.starts_with('[')
+            {
+                return Err(in_line("tables aren't supported; put every key at the top level".to_string()));
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| in_line("expected key = value".to_string()))?;
+            let key = key.trim();
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(in_line(format!("invalid key '{}'", key)));
+            }
+            if seen.iter().any(|other| other == key)
+            {
+                return Err(in_line(format!("{} is set twice", key)));
+            }
+            if COMMAND_LINE_ONLY.contains(&key)
+            {
+                return Err(in_line(format!("--{} can only be given on the command line", key)));
+            }
+            seen.push(key.to_string());
+            let (value, rest) = parse_value(value.trim()).map_err(in_line)?;
+            if !rest.trim().is_empty()
+            {
+                return Err(in_line(format!("unexpected '{}' after the value", rest.trim())));
+            }
+            let separator = if key == "size" { "x" } else { "," };
+            let flag = format!("--{}", key.replace('_', "-"));
+            match (POSITIONAL.iter().position(|&name| name == key), value)
+            {
+                (Some(_), Value::Boolean(_)) => return Err(in_line(format!("{} can't be true or false", key))),
+                (Some(place), value) => config.positional[place] = Some(value.to_arg(separator).map_err(in_line)?),
+                (None, Value::Boolean(true)) => config.options.push(flag),
+                (None, Value::Boolean(false)) => {}
+                (None, value) => config.options.extend([flag, value.to_arg(separator).map_err(in_line)?]),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The positional arguments: those from the command line first, then the file's for the places after them,
+    /// up to 'expected' in all.
+    pub fn fill(&self, mut positional: Vec<String>, expected: usize) -> Vec<String>
+    {
+        for place in positional.len()..expected.min(POSITIONAL.len())
+        {
+            match &self.positional[place]
+            {
+                Some(value) => positional.push(value.clone()),
+                None => break,
+            }
+        }
+        positional
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value
+{
+    Text(String),
+    Number(String), //As written, less any _ digit separators.
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value
+{
+    /// The value as a command-line argument.
+    fn to_arg(&self, separator: &str) -> Result<String, String>
+    {
+        match self
+        {
+            Value::Text(text) | Value::Number(text) => Ok(text.clone()),
+            Value::Array(items) => items.iter()
+                .map(|item| match item
+                {
+                    Value::Text(text) | Value::Number(text) => Ok(text.clone()),
+                    _ => Err("arrays may only hold strings and numbers".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(|items| items.join(separator)),
+            Value::Boolean(_) => Err("expected a string or number".to_string()),
+        }
+    }
+}
+
+/// The line up to a '#' that isn't inside a string.
+fn strip_comment(line: &str) -> &str
+{
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices()
+    {
+        match quote
+        {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// Parse the value at the start of 's', returning it and the rest of 's'.
+fn parse_value(s: &str) -> Result<(Value, &str), String>
+{
+    if s.starts_with("\"\"\"") || s.starts_with("'''")
+    {
+        return Err("multi-line strings aren't supported".to_string());
+    }
+    if let Some(rest) = s.strip_prefix('"')
+    {
+        let mut text = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next()
+        {
+            match c
+            {
+                '"' => return Ok((Value::Text(text), &rest[i + 1..])),
+                '\\' => text.push(match chars.next().map(|(_, c)| c)
+                {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('u') =>
+                    {
+                        let digits: String = (0..4).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                        u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\u{}", digits))?
+                    }
+                    other => return Err(format!("unknown escape \\{}", other.map_or(String::new(), String::from))),
+                }),
+                _ => text.push(c),
+            }
+        }
+        return Err("unterminated string".to_string());
+    }
+    if let Some(rest) = s.strip_prefix('\'')
+    {
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        return Ok((Value::Text(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[')
+    {
+        let mut items = Vec::new();
+        loop
+        {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']')
+            {
+                return Ok((Value::Array(items), after));
+            }
+            if rest.is_empty()
+            {
+                return Err("arrays must close on the line they open".to_string());
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            let after = after.trim_start();
+            rest = match after.strip_prefix(',')
+            {
+                Some(after) => after,
+                None if after.is_empty() || after.starts_with(']') => after,
+                None => return Err("expected , or ] in array".to_string()),
+            };
+        }
+    }
+    let end = s.find([',', ']', ' ', '\t']).unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    match word
+    {
+        "true" => Ok((Value::Boolean(true), rest)),
+        "false" => Ok((Value::Boolean(false), rest)),
+        _ =>
+        {
+            //Checked loosely, since a number may be beyond f64 (zoom = 1e400); the option parses it properly.
+            let number = word.replace('_', "");
+            if !(number.chars().any(|c| c.is_ascii_digit()) && number.chars().all(|c| c.is_ascii_digit() || "+-.eE".contains(c)))
+            {
+                return Err(format!("'{}' is not a string, number or boolean (strings need quotes)", word));
+            }
+            Ok((Value::Number(number), rest))
+        }
+    }
+}
+
+#[test]
+fn test_parse()
+{
+    let config = Config::parse(TEMPLATE).unwrap();
+    assert_eq!(config.positional, [Some("mandelbrot.png".to_string()), Some("1920x1080".to_string()),
+                                   Some("-2.5,1.2".to_string()), Some("1,-1.2".to_string())]);
+    assert_eq!(config.options, ["--limit", "1000"]);
+
+    let text = "# A comment\nsize = [800, 600]\ncenter = [-0.7766105925997018565640395025529947493282, 0.1346] # M(23,2)\n\
+                zoom = 1_000_000\nprecision = 1e400\nprint_link = true\nqr = false\npalette = 'fire'\ngrain-seed = 7\nnote = \"a # b \\\"c\\\"\"\n";
+    let config = Config::parse(text).unwrap();
+    assert_eq!(config.positional[1].as_deref(), Some("800x600"));
+    assert_eq!(config.options, ["--center", "-0.7766105925997018565640395025529947493282,0.1346", "--zoom", "1000000", "--precision", "1e400",
+                                "--print-link", "--palette", "fire", "--grain-seed", "7", "--note", "a # b \"c\""]);
+
+    for (bad, message) in [("limit 5", "line 1: expected key"), ("a = 1\na = 2", "line 2: a is set twice"),
+                           ("[view]", "tables"), ("palette = fire", "strings need quotes"), ("x = \"open", "unterminated"),
+                           ("x = [1, 2", "close"), ("output = true", "can't be"), ("x = [1 2]", "expected , or ]"),
+                           ("x = 1 2", "unexpected '2'"), ("x = nan", "not a string"), ("x = \"\"\"", "multi-line"),
+                           ("config = \"other.toml\"", "line 1: --config can only be given on the command line"),
+                           ("help = true", "--help can only")]
+    {
+        let err = Config::parse(bad).unwrap_err();
+        assert!(err.contains(message), "{}: {}", bad, err);
+    }
+}
+
+#[test]
+fn test_fill()
+{
+    let config = Config::parse("output = \"a.png\"\nsize = \"10x10\"\nupper_left = \"-2,1\"\nlower_right = \"1,-1\"").unwrap();
+    let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(config.fill(vec![], 4), strings(&["a.png", "10x10", "-2,1", "1,-1"]));
+    assert_eq!(config.fill(strings(&["b.png"]), 4), strings(&["b.png", "10x10", "-2,1", "1,-1"]));
+    //With --center only FILE and PIXELS are wanted.
+    assert_eq!(config.fill(vec![], 2), strings(&["a.png", "10x10"]));
+    assert_eq!(Config::default().fill(strings(&["c.png"]), 4), strings(&["c.png"]));
+}
@@ -0,0 +1,224 @@
+//! Where the program keeps its own files: a config directory for things the user sets up (palettes so far) and a
+//! cache directory for things it can recreate, which `mandelbrot cache clean` prunes.
+//!
+//! The locations follow each platform's convention:
+//!
+//! ```text
+//!            config                                        cache
+//! Linux      $XDG_CONFIG_HOME/mandelbrot                   $XDG_CACHE_HOME/mandelbrot
+//!            (default ~/.config/mandelbrot)                (default ~/.cache/mandelbrot)
+//! macOS      ~/Library/Application Support/mandelbrot      ~/Library/Caches/mandelbrot
+//! Windows    %APPDATA%\mandelbrot\config                   %LOCALAPPDATA%\mandelbrot\cache
+//! ```
+//!
+//! --config-dir and --cache-dir replace them. Nothing is created until something is written there.
+//!
+//! Cleaning deletes files recursively, so it only touches a directory create_cache() made: one holding a
+//! CACHEDIR.TAG (the format backup tools already skip) that names this program. A mistyped --cache-dir pointing
+//! at a home directory is refused rather than emptied.
+
+use crate::palette::Palette;
+use crate::palette_file;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const APP: &str = "mandelbrot";
+
+/// The marker file create_cache() writes, and what it holds.
+const TAG: &str = "CACHEDIR.TAG";
+const TAG_TEXT: &str = "Signature: 8a477f597d28d172789f06886806bc55\n\
+                        # This file is a cache directory tag created by mandelbrot.\n\
+                        # For information about cache directory tags, see https://bford.info/cachedir/\n";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs
+{
+    pub config: Option<PathBuf>, //None when the environment doesn't say where home is.
+    pub cache: Option<PathBuf>,
+}
+
+impl Dirs
+{
+    /// The directories for this platform and environment.
+    pub fn from_env() -> Dirs
+    {
+        Dirs::locate(env::consts::OS, |name| env::var_os(name).map(PathBuf::from).filter(|path| path.is_absolute()))
+    }
+
+    /// The directories for operating system 'os' (as in std::env::consts::OS), with environment variables looked
+    /// up by 'var'. Relative paths in XDG variables are to be ignored, which 'var' is expected to do.
+    pub fn locate(os: &str, var: impl Fn(&str) -> Option<PathBuf>) -> Dirs
+    {
+        let home = var("HOME");
+        let (config, cache) = match os
+        {
+            "windows" => (var("APPDATA").map(|dir| dir.join(APP).join("config")),
+                          var("LOCALAPPDATA").map(|dir| dir.join(APP).join("cache"))),
+            "macos" => (home.as_ref().map(|home| home.join("Library/Application Support").join(APP)),
+                        home.as_ref().map(|home| home.join("Library/Caches").join(APP))),
+            _ => (var("XDG_CONFIG_HOME").or(home.as_ref().map(|home| home.join(".config"))).map(|dir| dir.join(APP)),
+                  var("XDG_CACHE_HOME").or(home.as_ref().map(|home| home.join(".cache"))).map(|dir| dir.join(APP))),
+        };
+        Dirs{config, cache}
+    }
+
+    /// The directory user palettes are looked up in.
+    pub fn palettes(&self) -> Option<PathBuf>
+    {
+        self.config.as_ref().map(|config| config.join("palettes"))
+    }
+
+    /// A palette by name: a built-in one, or a file NAME.csv, NAME.json, NAME.ggr or NAME.ugr (or .txt) in the
+    /// palettes directory. Ok(None) means there is no such palette; Err is a palette file that failed to load.
+    pub fn find_palette(&self, name: &str) -> Result<Option<Palette>, String>
+    {
+        if let Some(palette) = Palette::builtin(name)
+        {
+            return Ok(Some(palette));
+        }
+        //Names come from the command line and share links; keep them from reaching outside the directory.
+        let Some(dir) = self.palettes().filter(|_| !name.is_empty() && !name.contains(['/', '\\']) && name != "..") else
+        {
+            return Ok(None);
+        };
+        for extension in ["csv", "txt", "json", "ggr", "ugr"]
+        {
+            let path = dir.join(format!("{}.{}", name, extension));
+            if path.is_file()
+            {
+                return palette_file::load(&path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// What cache clean removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cleaned
+{
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Create the cache directory 'dir' if need be, tagged as ours so that clean() will work on it. Whatever writes
+/// to the cache goes through this first.
+pub fn create_cache(dir: &Path) -> io::Result<()>
+{
+    fs::create_dir_all(dir)?;
+    if !is_cache(dir)
+    {
+        fs::write(dir.join(TAG), TAG_TEXT)?;
+    }
+    Ok(())
+}
+
+/// Whether 'dir' holds the tag create_cache() writes.
+fn is_cache(dir: &Path) -> bool
+{
+    fs::read_to_string(dir.join(TAG)).is_ok_and(|text| text == TAG_TEXT)
+}
+
+/// Delete every file under 'dir' last modified more than 'age' before 'now', and any directories that leaves
+/// empty (but not 'dir' itself or its tag). A missing 'dir' is an empty cache, not an error; a directory
+/// create_cache() didn't make is an error, and nothing in it is touched.
+pub fn clean(dir: &Path, age: Duration, now: SystemTime) -> io::Result<Cleaned>
+{
+    let mut cleaned = Cleaned::default();
+    if !dir.exists()
+    {
+        return Ok(cleaned);
+    }
+    if !is_cache(dir)
+    {
+        let message = format!("not a mandelbrot cache directory (it has no {} from mandelbrot), so nothing was removed", TAG);
+        return Err(io::Error::other(message));
+    }
+    clean_into(dir, now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH), true, &mut cleaned)?;
+    Ok(cleaned)
+}
+
+/// The recursive part of clean(), with the cutoff time worked out. 'top' is whether 'dir' is the cache itself.
+fn clean_into(dir: &Path, cutoff: SystemTime, top: bool, cleaned: &mut Cleaned) -> io::Result<()>
+{
+    for entry in fs::read_dir(dir)?
+    {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if top && entry.file_name() == TAG
+        {
+            continue;
+        }
+        if metadata.is_dir()
+        {
+            clean_into(&entry.path(), cutoff, false, cleaned)?;
+            //Fails harmlessly when something newer is still inside.
+            let _ = fs::remove_dir(entry.path());
+        }
+        else if metadata.modified()? < cutoff
+        {
+            fs::remove_file(entry.path())?;
+            cleaned.files += 1;
+            cleaned.bytes += metadata.len();
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_locate()
+{
+    let env = |vars: &'static [(&'static str, &'static str)]| move |name: &str|
+        vars.iter().find(|(key, _)| *key == name).map(|(_, value)| PathBuf::from(value));
+    let linux = Dirs::locate("linux", env(&[("HOME", "/home/ann")]));
+    assert_eq!(linux, Dirs{config: Some("/home/ann/.config/mandelbrot".into()), cache: Some("/home/ann/.cache/mandelbrot".into())});
+    let xdg = Dirs::locate("linux", env(&[("HOME", "/home/ann"), ("XDG_CACHE_HOME", "/tmp/c")]));
+    assert_eq!(xdg.cache, Some("/tmp/c/mandelbrot".into()));
+    assert_eq!(xdg.config, linux.config);
+    let mac = Dirs::locate("macos", env(&[("HOME", "/Users/ann")]));
+    assert_eq!(mac.cache, Some("/Users/ann/Library/Caches/mandelbrot".into()));
+    let windows = Dirs::locate("windows", env(&[("APPDATA", "C:/Roaming"), ("HOME", "/ignored")]));
+    assert_eq!(windows, Dirs{config: Some("C:/Roaming/mandelbrot/config".into()), cache: None});
+    assert_eq!(Dirs::locate("freebsd", env(&[])), Dirs{config: None, cache: None});
+}
+
+#[test]
+fn test_find_palette_and_clean()
+{
+    let root = env::temp_dir().join(format!("mandelbrot-dirs-test-{}", std::process::id()));
+    let dirs = Dirs{config: Some(root.join("config")), cache: Some(root.join("cache"))};
+    fs::create_dir_all(dirs.palettes().unwrap()).unwrap();
+    fs::write(dirs.palettes().unwrap().join("sunset.csv"), "255,0,0\n0,0,255\n").unwrap();
+    fs::write(dirs.palettes().unwrap().join("broken.json"), "[").unwrap();
+    assert_eq!(dirs.find_palette("sunset").unwrap().unwrap().color(0.0), [255, 0, 0]);
+    assert_eq!(dirs.find_palette("fire"), Ok(Palette::builtin("fire")));
+    assert_eq!(dirs.find_palette("missing"), Ok(None));
+    assert_eq!(dirs.find_palette("../palettes/sunset"), Ok(None));
+    assert!(dirs.find_palette("broken").is_err());
+
+    let cache = dirs.cache.clone().unwrap();
+    create_cache(&cache).unwrap();
+    create_cache(&cache).unwrap();
+    fs::create_dir_all(cache.join("tiles")).unwrap();
+    fs::write(cache.join("tiles/a.png"), [0u8; 10]).unwrap();
+    fs::write(cache.join("b.png"), [0u8; 5]).unwrap();
+    let now = SystemTime::now();
+    assert_eq!(clean(&cache, Duration::from_secs(3600), now).unwrap(), Cleaned::default());
+    //An hour from now, everything written above is more than 30 minutes old.
+    let later = now + Duration::from_secs(3600);
+    assert_eq!(clean(&cache, Duration::from_secs(1800), later).unwrap(), Cleaned{files: 2, bytes: 15});
+    assert!(cache.is_dir() && !cache.join("tiles").exists() && is_cache(&cache));
+    assert_eq!(clean(&root.join("nowhere"), Duration::ZERO, now).unwrap(), Cleaned::default());
+    //A directory create_cache() didn't make, like a home directory given by mistake, is left alone.
+    let home = root.join("home");
+    fs::create_dir_all(home.join("documents")).unwrap();
+    fs::write(home.join("documents/thesis.tex"), [0u8; 7]).unwrap();
+    assert!(clean(&home, Duration::ZERO, later).unwrap_err().to_string().contains("CACHEDIR.TAG"));
+    fs::write(home.join(TAG), "Signature: 8a477f597d28d172789f06886806bc55\n# Some other program's cache.\n").unwrap();
+    assert!(clean(&home, Duration::ZERO, later).is_err());
+    assert!(home.join("documents/thesis.tex").is_file());
+    fs::remove_dir_all(&root).unwrap();
+}
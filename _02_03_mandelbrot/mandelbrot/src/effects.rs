@@ -0,0 +1,330 @@
+//! Post-processing passes over a finished image.
+//!
+//! Depth of field treats the distance estimate as depth: pixels whose distance from the set is near a chosen
+//! focal distance stay sharp, and the blur grows the further (in ratio) a pixel's distance is from it. The blur
+//! radius changes from pixel to pixel, so it is a box blur read from a summed-area table, which costs the same
+//! for every radius.
+//!
+//! Bloom makes bright parts of the image glow: everything brighter than a threshold is blurred and added back on
+//! top. The whole pass works in floating point and rounds to bytes once at the end, so a wide, faint glow
+//! doesn't lose its tail to rounding at each step.
+//!
+//! Grain and vignette are finishing touches. Grain noise is a hash of the seed and the pixel position, so a frame
+//! rendered twice (or with a different thread count) gets exactly the same grain, and an animation can vary it
+//! per frame by varying the seed.
+
+/// Per-channel running sums: entry (x, y) holds the sum of all pixels above and to the left of pixel (x, y).
+struct SummedArea
+{
+    width: usize,
+    channels: usize,
+    sums: Vec<u64>,
+}
+
+impl SummedArea
+{
+    fn new(pixels: &[u8], bounds: (usize, usize), channels: usize) -> SummedArea
+    {
+        let width = bounds.0 + 1;
+        let mut sums = vec![0u64; width * (bounds.1 + 1) * channels];
+        for y in 0..bounds.1
+        {
+            for x in 0..bounds.0
+            {
+                for c in 0..channels
+                {
+                    let here = pixels[(y * bounds.0 + x) * channels + c] as u64;
+                    let above = sums[(y * width + x + 1) * channels + c];
+                    let left = sums[((y + 1) * width + x) * channels + c];
+                    let diagonal = sums[(y * width + x) * channels + c];
+                    sums[((y + 1) * width + x + 1) * channels + c] = here + above + left - diagonal;
+                }
+            }
+        }
+        SummedArea{width, channels, sums}
+    }
+
+    /// The average of channel 'c' over the pixels with x0 <= x < x1 and y0 <= y < y1.
+    fn average(&self, (x0, y0): (usize, usize), (x1, y1): (usize, usize), c: usize) -> f64
+    {
+        let at = |x: usize, y: usize| self.sums[(y * self.width + x) * self.channels + c];
+        let total = at(x1, y1) + at(x0, y0) - at(x1, y0) - at(x0, y1);
+        total as f64 / ((x1 - x0) * (y1 - y0)) as f64
+    }
+}
+
+/// The blur radius in pixels for a pixel 'distance' pixels from the set, with 'focus' the in-focus distance.
+/// It grows with the logarithm of the ratio between the two and reaches 'max_radius' at a ratio of 16.
+pub fn blur_radius(distance: f64, focus: f64, max_radius: f64) -> f64
+{
+    let defocus = ((1.0 + distance) / (1.0 + focus)).log2().abs() / 4.0;
+    max_radius * defocus.min(1.0)
+}
+
+/// Blur 'pixels' by a different amount at each pixel, from 'distances' (one per pixel, as distance_map returns).
+pub fn depth_of_field(pixels: &[u8], bounds: (usize, usize), channels: usize, distances: &[f64], focus: f64,
+                      max_radius: f64) -> Vec<u8>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels && distances.len() == bounds.0 * bounds.1);
+    let table = SummedArea::new(pixels, bounds, channels);
+    let mut out = vec![0; pixels.len()];
+    for y in 0..bounds.1
+    {
+        for x in 0..bounds.0
+        {
+            let r = blur_radius(distances[y * bounds.0 + x], focus, max_radius).round() as usize;
+            let corner0 = (x.saturating_sub(r), y.saturating_sub(r));
+            let corner1 = ((x + r + 1).min(bounds.0), (y + r + 1).min(bounds.1));
+            for c in 0..channels
+            {
+                out[(y * bounds.0 + x) * channels + c] = table.average(corner0, corner1, c).round() as u8;
+            }
+        }
+    }
+    out
+}
+
+/// Settings for the bloom pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bloom
+{
+    pub threshold: f64, //Brightness (0..1) above which pixels glow.
+    pub radius: f64,    //How far the glow spreads, in pixels.
+    pub intensity: f64, //How strongly the glow is added back; 1 adds it at full strength.
+}
+
+/// Average each pixel with its neighbours up to 'radius' away along one axis ('stride' floats apart, 'n' of them
+/// per line), clipping the window at the ends. A running sum keeps it linear in the image size.
+fn box_blur_line(line: &mut [f64], scratch: &mut [f64], n: usize, stride: usize, radius: usize)
+{
+    let mut sum = 0.0;
+    for i in 0..radius.min(n)
+    {
+        sum += line[i * stride];
+    }
+    for (i, out) in scratch.iter_mut().enumerate().take(n)
+    {
+        if i + radius < n
+        {
+            sum += line[(i + radius) * stride];
+        }
+        if i > radius
+        {
+            sum -= line[(i - radius - 1) * stride];
+        }
+        let count = (i + radius + 1).min(n) - i.saturating_sub(radius);
+        *out = sum / count as f64;
+    }
+    for i in 0..n
+    {
+        line[i * stride] = scratch[i];
+    }
+}
+
+/// Blur a float image in place. Three passes of a box blur approximate a Gaussian with the given radius.
+fn blur(image: &mut [f64], bounds: (usize, usize), channels: usize, radius: f64)
+{
+    let box_radius = (radius / 3.0).round() as usize;
+    if box_radius == 0
+    {
+        return;
+    }
+    let (w, h) = bounds;
+    let mut scratch = vec![0.0; w.max(h)];
+    for _ in 0..3
+    {
+        for y in 0..h
+        {
+            for c in 0..channels
+            {
+                box_blur_line(&mut image[y * w * channels + c..], &mut scratch, w, channels, box_radius);
+            }
+        }
+        for x in 0..w
+        {
+            for c in 0..channels
+            {
+                box_blur_line(&mut image[x * channels + c..], &mut scratch, h, w * channels, box_radius);
+            }
+        }
+    }
+}
+
+/// Add a glow around the bright parts of 'pixels'. A pixel contributes in proportion to how far its brightest
+/// channel is above the threshold, so the glow fades in rather than switching on at a hard edge.
+pub fn bloom(pixels: &[u8], bounds: (usize, usize), channels: usize, settings: &Bloom) -> Vec<u8>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    let image: Vec<f64> = pixels.iter().map(|&p| p as f64 / 255.0).collect();
+    let mut glow = vec![0.0; image.len()];
+    let knee = (1.0 - settings.threshold).max(f64::EPSILON);
+    for (pixel, bright) in image.chunks(channels).zip(glow.chunks_mut(channels))
+    {
+        let brightness = pixel.iter().cloned().fold(0.0, f64::max);
+        let weight = ((brightness - settings.threshold) / knee).clamp(0.0, 1.0);
+        for (b, &p) in bright.iter_mut().zip(pixel)
+        {
+            *b = p * weight;
+        }
+    }
+    blur(&mut glow, bounds, channels, settings.radius);
+    image.iter().zip(&glow).map(|(&p, &g)| ((p + settings.intensity * g) * 255.0).round().min(255.0) as u8).collect()
+}
+
+/// How the vignette darkening follows the frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VignetteShape
+{
+    Circle,  //Round, whatever the aspect ratio: wide images darken more at the sides than at the top and bottom.
+    Ellipse, //Stretched to the frame, so all four edges darken alike.
+}
+
+impl std::str::FromStr for VignetteShape
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<VignetteShape, String>
+    {
+        match s
+        {
+            "circle" => Ok(VignetteShape::Circle),
+            "ellipse" => Ok(VignetteShape::Ellipse),
+            _ => Err(format!("unknown vignette shape '{}' (expected circle or ellipse)", s)),
+        }
+    }
+}
+
+/// The brightness factor at pixel (x, y): 1 at the center, falling smoothly to 1 - strength in the corners.
+pub fn vignette_factor(bounds: (usize, usize), (x, y): (usize, usize), strength: f64, shape: VignetteShape) -> f64
+{
+    let (half_w, half_h) = (bounds.0 as f64 / 2.0, bounds.1 as f64 / 2.0);
+    let (dx, dy) = (x as f64 + 0.5 - half_w, y as f64 + 0.5 - half_h);
+    //Squared distance from the center, scaled so that the corners are at 1.
+    let r2 = match shape
+    {
+        VignetteShape::Circle => (dx * dx + dy * dy) / (half_w * half_w + half_h * half_h),
+        VignetteShape::Ellipse => ((dx / half_w).powi(2) + (dy / half_h).powi(2)) / 2.0,
+    };
+    let falloff = r2 * r2 * (3.0 - 2.0 * r2); //Smoothstep in r^2: flat in the middle, steepest halfway out.
+    1.0 - strength * falloff.clamp(0.0, 1.0)
+}
+
+/// Darken the image towards the edges by up to 'strength' (0..1).
+pub fn vignette(pixels: &mut [u8], bounds: (usize, usize), channels: usize, strength: f64, shape: VignetteShape)
+{
+    for (i, pixel) in pixels.chunks_mut(channels).enumerate()
+    {
+        let factor = vignette_factor(bounds, (i % bounds.0, i / bounds.0), strength, shape);
+        for p in pixel
+        {
+            *p = (*p as f64 * factor).round() as u8;
+        }
+    }
+}
+
+/// Add monochrome film grain: every channel of a pixel moves by the same random amount, roughly normally
+/// distributed with a standard deviation of 'amount' (as a fraction of full brightness).
+pub fn grain(pixels: &mut [u8], channels: usize, amount: f64, seed: u64)
+{
+    let seed = crate::mix64(seed);
+    for (i, pixel) in pixels.chunks_mut(channels).enumerate()
+    {
+        //The sum of four uniform 16-bit values from one hash is close to a bell curve, with variance 4/12.
+        let hash = crate::mix64(seed ^ i as u64);
+        let sum: f64 = (0..4).map(|k| ((hash >> (16 * k)) & 0xffff) as f64 / 65535.0).sum();
+        let noise = (sum - 2.0) * 3f64.sqrt() * amount * 255.0;
+        for p in pixel
+        {
+            *p = (*p as f64 + noise).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+#[test]
+fn test_blur_radius()
+{
+    assert_eq!(blur_radius(3.0, 3.0, 8.0), 0.0);
+    assert_eq!(blur_radius(63.0, 3.0, 8.0), 8.0);  //Ratio 16.
+    assert_eq!(blur_radius(7.0, 3.0, 8.0), 2.0);   //Ratio 2.
+    assert_eq!(blur_radius(0.0, 3.0, 8.0), 4.0);   //Ratio 1/4.
+    assert_eq!(blur_radius(1e9, 3.0, 8.0), 8.0);
+}
+
+#[test]
+fn test_depth_of_field()
+{
+    //A one-pixel white line in a black 9x9 image, in focus on the left half and far out of focus on the right.
+    let bounds = (9, 9);
+    let pixels: Vec<u8> = (0..81).map(|i| if i / 9 == 4 { 255 } else { 0 }).collect();
+    let distances: Vec<f64> = (0..81).map(|i| if i % 9 < 4 { 10.0 } else { 1e6 }).collect();
+    let out = depth_of_field(&pixels, bounds, 1, &distances, 10.0, 1.0);
+    for y in 0..9
+    {
+        assert_eq!(&out[y * 9..y * 9 + 4], &pixels[y * 9..y * 9 + 4]);
+    }
+    assert_eq!(out[4 * 9 + 6], 85);      //Averaged over a 3x3 box: one bright row of three.
+    assert_eq!(out[3 * 9 + 6], 85);      //The glow spreads to the row above...
+    assert_eq!(out[2 * 9 + 6], 0);       //...but no further.
+    assert_eq!(out[4 * 9 + 8], 85);      //Edge boxes are clipped to the image, not padded with black.
+}
+
+#[test]
+fn test_bloom()
+{
+    //A bright dot on a dim background: the dot glows onto its neighbours, the background alone does not.
+    let bounds = (15, 15);
+    let mut pixels = vec![40u8; 225];
+    pixels[7 * 15 + 7] = 255;
+    let settings = Bloom{threshold: 0.5, radius: 6.0, intensity: 1.0};
+    let out = bloom(&pixels, bounds, 1, &settings);
+    assert_eq!(out[7 * 15 + 7], 255);
+    assert!(out[7 * 15 + 9] > 40);
+    assert!(out[7 * 15 + 8] >= out[7 * 15 + 9]);
+    assert_eq!(out[0], 40);
+    //Nothing above the threshold: the image comes back unchanged.
+    assert_eq!(bloom(&[40; 225], bounds, 1, &settings), vec![40; 225]);
+    //A blur this small is no blur at all, so the glow lands on the bright pixel itself: 200 + 0.25 * 200 * 200/255.
+    let tight = bloom(&[200, 0, 0, 0], (2, 2), 1, &Bloom{threshold: 0.0, radius: 1.0, intensity: 0.25});
+    assert_eq!(tight, vec![239, 0, 0, 0]);
+}
+
+#[test]
+fn test_vignette()
+{
+    let bounds = (40, 20);
+    for shape in [VignetteShape::Circle, VignetteShape::Ellipse]
+    {
+        assert!(vignette_factor(bounds, (20, 10), 0.6, shape) > 0.999);
+        assert!((vignette_factor(bounds, (0, 0), 0.6, shape) - 0.4).abs() < 0.05);
+    }
+    //The ellipse darkens the middle of every edge alike; the circle spares the near top and bottom edges.
+    let ellipse = |x, y| vignette_factor(bounds, (x, y), 0.6, VignetteShape::Ellipse);
+    let circle = |x, y| vignette_factor(bounds, (x, y), 0.6, VignetteShape::Circle);
+    assert!((ellipse(0, 10) - ellipse(20, 0)).abs() < 0.05);
+    assert!(circle(20, 0) > circle(0, 10) + 0.2);
+
+    let mut gray = vec![200u8; 800];
+    vignette(&mut gray, bounds, 1, 0.6, VignetteShape::Ellipse);
+    assert_eq!(gray[10 * 40 + 20], 200);
+    assert!(gray[0] < 90);
+}
+
+#[test]
+fn test_grain()
+{
+    let mut a = vec![128u8; 3 * 1000];
+    grain(&mut a, 3, 0.05, 42);
+    //Monochrome, centered on the original value, with about the requested spread (0.05 * 255 = 12.75).
+    assert!(a.chunks(3).all(|p| p[0] == p[1] && p[1] == p[2]));
+    let values: Vec<f64> = a.chunks(3).map(|p| p[0] as f64 - 128.0).collect();
+    let mean = values.iter().sum::<f64>() / 1000.0;
+    let sd = (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / 1000.0).sqrt();
+    assert!(mean.abs() < 1.5 && (sd - 12.75).abs() < 1.5, "mean {} sd {}", mean, sd);
+    //The same seed gives the same grain; another seed doesn't.
+    let mut b = vec![128u8; 3 * 1000];
+    grain(&mut b, 3, 0.05, 42);
+    assert_eq!(a, b);
+    let mut c = vec![128u8; 3 * 1000];
+    grain(&mut c, 3, 0.05, 43);
+    assert_ne!(a, c);
+}
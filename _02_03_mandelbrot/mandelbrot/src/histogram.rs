@@ -0,0 +1,188 @@
+//! Histogram coloring (`--coloring histogram`): each escaping pixel's color is the fraction of escaping pixels that
+//! escaped sooner, so the gradient is spread evenly over the pixels actually in the image. A view where nearly
+//! everything escapes between 300 and 320 iterations uses the whole palette, where the usual log scale over the
+//! iteration limit would show one flat color.
+//!
+//! Those fractions depend on every pixel, so the escape values are worked out twice instead of being kept: the first
+//! pass counts them into a histogram a strip of rows at a time, and the second works them out again to paint them.
+//! Only one strip's values are ever in memory, which for a gigapixel image is the difference between 16 bytes a
+//! pixel and a few megabytes in all. `--max-memory` sets how tall the strips can be (see strip_rows); the image
+//! itself still has to fit, since the PNG encoder takes it whole.
+//!
+//! Escape values are smooth counts, and the fraction is interpolated between whole counts, so there are no bands.
+
+use crate::progress::Progress;
+use crate::units::NumberFormat;
+use crate::{escape_value, lanes, paint_interior, parallel_rows, pixel_to_point, Coloring, Settings};
+use num::Complex;
+
+/// Rows per strip when there is no memory budget.
+pub const DEFAULT_STRIP_ROWS: usize = 64;
+
+/// Bytes of strip storage per pixel: one escape value.
+const BYTES_PER_VALUE: u64 = std::mem::size_of::<Option<f64>>() as u64;
+
+/// How many escaping pixels escaped within each whole count, turned into fractions of all escaping pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram
+{
+    below: Vec<f64>, //below[k]: the fraction of escaping pixels whose escape value is below k.
+}
+
+impl Histogram
+{
+    /// The histogram of escape values 'counts[k]' escaping within k to k + 1 iterations.
+    pub fn from_counts(counts: &[u64]) -> Histogram
+    {
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let mut below = Vec::with_capacity(counts.len() + 1);
+        let mut sum = 0;
+        below.push(0.0);
+        for &count in counts
+        {
+            sum += count;
+            below.push(sum as f64 / total);
+        }
+        Histogram{below}
+    }
+
+    /// Where 'value' falls among the escaping pixels, from 0 (the first to escape) to 1 (the last).
+    pub fn fraction(&self, value: f64) -> f64
+    {
+        let last = self.below.len() - 2;
+        let value = value.max(0.0);
+        let bin = (value as usize).min(last);
+        let within = (value - bin as f64).min(1.0);
+        self.below[bin] + (self.below[bin + 1] - self.below[bin]) * within
+    }
+}
+
+/// The rows per strip that keep the image, the histogram and one strip within 'budget' bytes, or an error saying
+/// what doesn't fit, its sizes written in 'format'. Without a budget, DEFAULT_STRIP_ROWS.
+pub fn strip_rows(bounds: (usize, usize), channels: usize, limit: usize, budget: Option<u64>, format: NumberFormat)
+                  -> Result<usize, String>
+{
+    let Some(budget) = budget else { return Ok(DEFAULT_STRIP_ROWS.min(bounds.1)) };
+    let image = (bounds.0 as u64).checked_mul(bounds.1 as u64).and_then(|pixels| pixels.checked_mul(channels as u64));
+    let fixed = image.and_then(|image| image.checked_add((limit as u64 + 1) * 8 * 2));
+    let row = (bounds.0 as u64).saturating_mul(BYTES_PER_VALUE);
+    let (Some(image), Some(fixed)) = (image, fixed)
+    else { return Err(format!("--max-memory {} is too small for a {}x{} image", format.bytes(budget), bounds.0, bounds.1)) };
+    if fixed.saturating_add(row) > budget
+    {
+        return Err(format!("--max-memory {} is too small: the image alone takes {}, and a strip of one row {} more",
+                           format.bytes(budget), format.bytes(image), format.bytes(row)));
+    }
+    Ok((((budget - fixed) / row) as usize).min(bounds.1))
+}
+
+/// The escape values of one row, as histogram coloring sees them.
+fn row_values(bounds: (usize, usize), top: usize, upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+              values: &mut [Option<f64>])
+{
+    let points: Vec<Complex<f64>> = (0..bounds.0).map(|column| pixel_to_point(bounds, (column, top), upper_left, lower_right)).collect();
+    if settings.uses_lanes()
+    {
+        values.copy_from_slice(&lanes::escape_values(&points, settings));
+    }
+    else
+    {
+        for (value, &point) in values.iter_mut().zip(&points)
+        {
+            *value = escape_value(point, settings);
+        }
+    }
+}
+
+/// The first pass: count the escape values of the image, 'strip_rows' rows at a time. Advances 'progress' by a
+/// unit per row.
+pub fn measure(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+               threads: usize, strip_rows: usize, progress: &Progress) -> Histogram
+{
+    let smooth = Settings{coloring: Coloring::Smooth, ..settings.clone()};
+    let bins = settings.limit.max(1);
+    let mut counts = vec![0u64; bins];
+    let mut strip = vec![None; bounds.0 * strip_rows.clamp(1, bounds.1.max(1))];
+    for first in (0..bounds.1).step_by(strip_rows.max(1))
+    {
+        let rows = strip_rows.max(1).min(bounds.1 - first);
+        let strip = &mut strip[..bounds.0 * rows];
+        parallel_rows(strip, bounds.0, threads, progress, |row, values|
+        {
+            row_values(bounds, first + row, upper_left, lower_right, &smooth, values);
+        });
+        for &value in strip.iter().flatten()
+        {
+            counts[(value.max(0.0) as usize).min(bins - 1)] += 1;
+        }
+    }
+    Histogram::from_counts(&counts)
+}
+
+/// The second pass: render the image into 'pixels', coloring escaping points by where they fall in 'histogram'.
+/// Advances 'progress' by a unit per row.
+pub fn render(pixels: &mut [u8], bounds: (usize, usize), (upper_left, lower_right): (Complex<f64>, Complex<f64>),
+              settings: &Settings, histogram: &Histogram, threads: usize, progress: &Progress)
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    let smooth = Settings{coloring: Coloring::Smooth, ..settings.clone()};
+    parallel_rows(pixels, bounds.0 * channels, threads, progress, |top, row|
+    {
+        let mut values = vec![None; bounds.0];
+        row_values(bounds, top, upper_left, lower_right, &smooth, &mut values);
+        for (column, (value, pixel)) in values.into_iter().zip(row.chunks_mut(channels)).enumerate()
+        {
+            match (value, &settings.palette)
+            {
+                (None, _) => paint_interior(pixel_to_point(bounds, (column, top), upper_left, lower_right), settings, pixel),
+                (Some(value), None) => pixel[0] = 255 - (histogram.fraction(value) * 255.0).round() as u8,
+                (Some(value), Some(palette)) => pixel.copy_from_slice(&palette.color(histogram.fraction(value))),
+            }
+        }
+    });
+}
+
+#[test]
+fn test_fraction()
+{
+    //Half the pixels escape within 0..1 iterations, none within 1..2, and the rest within 2..3.
+    let histogram = Histogram::from_counts(&[5, 0, 5]);
+    assert_eq!(histogram.fraction(0.0), 0.0);
+    assert_eq!(histogram.fraction(0.5), 0.25);
+    assert_eq!(histogram.fraction(1.5), 0.5);
+    assert_eq!(histogram.fraction(2.5), 0.75);
+    assert_eq!(histogram.fraction(99.0), 1.0);
+    assert_eq!(Histogram::from_counts(&[0, 0]).fraction(1.0), 0.0);
+}
+
+#[test]
+fn test_strip_rows()
+{
+    assert_eq!(strip_rows((100, 30), 3, 255, None, NumberFormat::HUMAN), Ok(30));
+    assert_eq!(strip_rows((100, 1000), 3, 255, None, NumberFormat::HUMAN), Ok(DEFAULT_STRIP_ROWS));
+    //300 kB of image, 4 kB of histogram, and 1.6 kB per row of strip.
+    assert_eq!(strip_rows((100, 1000), 3, 255, Some(300_000 + 4096 + 16_000), NumberFormat::HUMAN), Ok(10));
+    assert!(strip_rows((100, 1000), 3, 255, Some(300_000), NumberFormat::HUMAN).unwrap_err().contains("too small"));
+    assert!(strip_rows((usize::MAX, 3), 3, 255, Some(1 << 40), NumberFormat::HUMAN).unwrap_err().contains("too small"));
+}
+
+#[test]
+fn test_histogram_render()
+{
+    use crate::palette::Palette;
+    let settings = Settings{limit: 300, palette: Palette::builtin("classic"), coloring: Coloring::Histogram, ..Settings::default()};
+    let bounds = (60, 45);
+    let (upper_left, lower_right) = (Complex{re: -0.76, im: 0.12}, Complex{re: -0.73, im: 0.0975});
+    //However tall the strips, the histogram is the same.
+    let whole = measure(bounds, upper_left, lower_right, &settings, 2, bounds.1, &Progress::silent());
+    assert_eq!(measure(bounds, upper_left, lower_right, &settings, 3, 7, &Progress::silent()), whole);
+    let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+    render(&mut pixels, bounds, (upper_left, lower_right), &settings, &whole, 2, &Progress::silent());
+    //The gradient is spread over the pixels: both of its ends show up in the image.
+    let palette = settings.palette.as_ref().unwrap();
+    let (first, last) = (palette.color(0.0), palette.color(1.0));
+    let distance = |pixel: &[u8], color: [u8; 3]| pixel.iter().zip(color).map(|(&a, b)| (a as i32 - b as i32).abs()).sum::<i32>();
+    assert!(pixels.chunks(3).any(|pixel| distance(pixel, first) < 40));
+    assert!(pixels.chunks(3).any(|pixel| distance(pixel, last) < 40));
+}
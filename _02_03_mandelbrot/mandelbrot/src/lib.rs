@@ -0,0 +1,1213 @@
+use num::Complex;
+use newton::Newton;
+use palette::Palette;
+use progress::Progress;
+
+pub mod antialias;
+pub mod boundary;
+pub mod buddhabrot;
+pub mod compare;
+pub mod config;
+pub mod deep;
+pub mod dirs;
+pub mod effects;
+pub mod histogram;
+pub mod lanes;
+pub mod locations;
+pub mod newton;
+pub mod palette;
+pub mod perturbation;
+pub mod progress;
+pub mod palette_file;
+pub mod parse;
+pub mod png;
+pub mod preflight;
+pub mod qr;
+pub mod share;
+pub mod texture;
+pub mod tune;
+pub mod units;
+pub mod viewport;
+
+/// How an orbit z0, z1, z2, ... ended. Escape-time fractals only ever produce Escaped or MaxIter; convergent
+/// formulas (Newton and friends) settle on a root, and periodicity checks can prove an orbit Cycled.
+/// Coloring code matches on this instead of on a bare Option, so every mode handles every ending the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrbitOutcome
+{
+    Escaped(usize),                //Left the bailout circle after this many iterations.
+    ConvergedToRoot(usize, usize), //Converged to root number k (first field) after this many iterations (second).
+    Cycled(usize),                 //Fell into an attracting cycle with this period.
+    MaxIter,                       //Still undecided when the iteration limit ran out.
+}
+
+impl OrbitOutcome
+{
+    /// The escape count, for code that only cares about escape-time coloring.
+    pub fn escape_count(self) -> Option<usize>
+    {
+        match self
+        {
+            OrbitOutcome::Escaped(i) => Some(i),
+            _ => None,
+        }
+    }
+}
+
+/// Iterate z = z * z + c from z = 0 and report how the orbit ended, using at most 'limit' iterations.
+/// Points in the main cardioid or the period-2 bulb are reported as MaxIter without iterating at all.
+pub fn mandelbrot_orbit(c: Complex<f64>, limit: usize) -> OrbitOutcome
+{
+    if in_cardioid_or_bulb(c)
+    {
+        return OrbitOutcome::MaxIter;
+    }
+    orbit(Complex{re: 0.0, im: 0.0}, c, Formula::MANDELBROT, limit)
+}
+
+/// Whether c is in the main cardioid or the period-2 bulb of the classic set. Between them they cover most of
+/// the set's area, so in a typical view these points are a large share of those that would otherwise run all the
+/// way to the iteration limit. The cardioid is where the fixed point of z^2 + c attracts, which works out to
+/// q(q + x - 1/4) <= y^2/4 with q = (x - 1/4)^2 + y^2; the bulb is the disk of radius 1/4 around -1, where the
+/// 2-cycle attracts.
+pub fn in_cardioid_or_bulb(c: Complex<f64>) -> bool
+{
+    let q = (c.re - 0.25) * (c.re - 0.25) + c.im * c.im;
+    q * (q + (c.re - 0.25)) <= 0.25 * c.im * c.im || (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 0.0625
+}
+
+#[test]
+fn test_in_cardioid_or_bulb()
+{
+    assert!(in_cardioid_or_bulb(Complex{re: 0.0, im: 0.0}));
+    assert!(in_cardioid_or_bulb(Complex{re: -1.0, im: 0.1}));
+    assert!(in_cardioid_or_bulb(Complex{re: 0.24, im: 0.0}));
+    assert!(in_cardioid_or_bulb(Complex{re: -0.74, im: 0.0}));
+    assert!(!in_cardioid_or_bulb(Complex{re: 0.26, im: 0.0}));
+    assert!(!in_cardioid_or_bulb(Complex{re: -1.3, im: 0.0}));
+    assert!(!in_cardioid_or_bulb(Complex{re: -0.75, im: 0.1})); //Beside the point where the two touch.
+    //Nothing the test accepts ever escapes.
+    for k in 0..2000
+    {
+        let c = Complex{re: -1.3 + 1.6 * (k % 50) as f64 / 50.0, im: 0.7 * (k / 50) as f64 / 40.0};
+        if in_cardioid_or_bulb(c)
+        {
+            assert_eq!(orbit(Complex{re: 0.0, im: 0.0}, c, Formula::MANDELBROT, 500).escape_count(), None, "{}", c);
+        }
+    }
+}
+
+/// z raised to 'power', taking the fast exact routes for whole powers (a single multiplication for the classic set).
+/// Fractional powers use the principal branch, which cuts the plane along the negative real axis.
+pub fn pow(z: Complex<f64>, power: f64) -> Complex<f64>
+{
+    if power == 2.0
+    {
+        z * z
+    }
+    else if power.fract() == 0.0 && power.abs() <= i32::MAX as f64
+    {
+        z.powi(power as i32)
+    }
+    else
+    {
+        z.powf(power)
+    }
+}
+
+/// The family of escape-time fractals. Each one folds z in some way before raising it to the power, so a new
+/// conjugate or absolute-value variant only needs a variant here, its name, and its fold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fractal
+{
+    Mandelbrot, //z = z^d + c
+    Tricorn,    //z = conj(z)^d + c, also called the Mandelbar set.
+}
+
+impl Fractal
+{
+    pub const ALL: [Fractal; 2] = [Fractal::Mandelbrot, Fractal::Tricorn];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Fractal::Mandelbrot => "mandelbrot",
+            Fractal::Tricorn => "tricorn",
+        }
+    }
+
+    /// What happens to z before it is raised to the power.
+    pub fn fold(self, z: Complex<f64>) -> Complex<f64>
+    {
+        match self
+        {
+            Fractal::Mandelbrot => z,
+            Fractal::Tricorn => z.conj(),
+        }
+    }
+
+    /// How fold() carries a small change 'dz' at z along, so derivatives (for distance estimates and cycle
+    /// multipliers) can be chained through the fold.
+    pub fn fold_derivative(self, _z: Complex<f64>, dz: Complex<f64>) -> Complex<f64>
+    {
+        match self
+        {
+            Fractal::Mandelbrot => dz,
+            Fractal::Tricorn => dz.conj(),
+        }
+    }
+}
+
+impl std::str::FromStr for Fractal
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Fractal, String>
+    {
+        Fractal::ALL.iter().copied().find(|fractal| fractal.name() == s).ok_or_else(||
+        {
+            let names: Vec<&str> = Fractal::ALL.iter().map(|fractal| fractal.name()).collect();
+            format!("unknown fractal '{}' (expected {})", s, names.join(" or "))
+        })
+    }
+}
+
+/// The map iterated for each point: z = fold(z)^power + c.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Formula
+{
+    pub fractal: Fractal,
+    pub power: f64,
+}
+
+impl Formula
+{
+    pub const MANDELBROT: Formula = Formula{fractal: Fractal::Mandelbrot, power: 2.0};
+
+    pub fn step(&self, z: Complex<f64>, c: Complex<f64>) -> Complex<f64>
+    {
+        pow(self.fractal.fold(z), self.power) + c
+    }
+
+    /// The change in step(z, c) caused by a small change 'dz' in z.
+    pub fn derivative(&self, z: Complex<f64>, dz: Complex<f64>) -> Complex<f64>
+    {
+        let folded = self.fractal.fold(z);
+        pow(folded, self.power - 1.0) * self.power * self.fractal.fold_derivative(z, dz)
+    }
+}
+
+/// Two orbit points closer than this (squared distance) count as the same point for cycle detection.
+/// Small enough that an orbit still on its way out never trips it by accident.
+pub(crate) const CYCLE_EPSILON: f64 = 1e-20;
+
+/// Brent's cycle detection: remember one orbit point, and compare every later point with it. The remembered point
+/// is replaced after 1, 2, 4, 8, ... steps, so once the orbit has settled into a cycle, some window is at least as
+/// long as the cycle and catches the return. It needs no memory beyond the one point, and orbits that settle
+/// quickly (the bulk of the set's interior) stop long before the iteration limit.
+struct CycleCheck
+{
+    saved: Complex<f64>,
+    steps: usize,  //Steps since 'saved' was taken.
+    window: usize, //Steps until it is replaced.
+}
+
+impl CycleCheck
+{
+    fn new(z0: Complex<f64>) -> CycleCheck
+    {
+        CycleCheck{saved: z0, steps: 0, window: 1}
+    }
+
+    /// Look at the next orbit point. Returns the number of steps since the remembered point if z is back at it:
+    /// the cycle's period, or (for a cycle still converging when first caught) a multiple of it.
+    fn returned(&mut self, z: Complex<f64>) -> Option<usize>
+    {
+        self.steps += 1;
+        if (z - self.saved).norm_sqr() < CYCLE_EPSILON
+        {
+            return Some(self.steps);
+        }
+        if self.steps == self.window
+        {
+            self.saved = z;
+            self.steps = 0;
+            self.window *= 2;
+        }
+        None
+    }
+}
+
+/// Iterate z = fold(z)^power + c (see Formula) from an arbitrary starting point z0. The Mandelbrot set starts every
+/// orbit at 0 and varies c per pixel; a Julia set fixes c and varies z0 per pixel. Powers other than 2 give the
+/// Multibrot sets. Orbits that fall into a cycle (see CycleCheck) stop early as Cycled; they will never escape.
+pub fn orbit(z0: Complex<f64>, c: Complex<f64>, formula: Formula, limit: usize) -> OrbitOutcome
+{
+    let mut z = z0;
+    let mut cycle = CycleCheck::new(z0);
+    for i in 0..limit
+    {
+        if z.norm_sqr() > 4.0 //norm_sqr is a method that calculated magnitude of the complex number.
+        {
+            return OrbitOutcome::Escaped(i);
+        }
+        z = formula.step(z, c);
+        if let Some(period) = cycle.returned(z)
+        {
+            return OrbitOutcome::Cycled(period);
+        }
+    }
+    OrbitOutcome::MaxIter //If z is in the Mand.-set, the limit runs out.
+}
+
+/// The following function does this: Try to determine is 'c' is in the Mandelbrot set, using at most 'limit'
+/// iterations to decide.
+/// If 'C' is not a member, return some(i) where 'i' is the number of iterations it took for 'c' to leave the circle of radius 2 centered
+/// on the origin. If 'c' seems to be a member (more precisely, if we reached the iteration limit without being able to prove that 'c'
+/// is not a member), return None.
+/// Option is an enumerated type (enum), because its definition enumerates several variants that a value could be: it is either Some(v) where v is of type T
+/// or None. enum Option<T> {None, Some(T),}
+pub fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> //Option<usize>: Returns Some(iteration_count) if c escapes within iteration_count iterations.
+//Returns None if c remains bounded for the full limit iterations.
+//usize is a built-in integer type that represents a size or index in memory. It is an unsigned integer type whose size
+// depends on the architecture of the machine on which the program is running: On a 64-bit architecture, usize is 64 bits (8 bytes).
+// On a 32-bit architecture, usize is 32 bits (4 bytes).
+{
+    mandelbrot_orbit(c, limit).escape_count()
+}
+
+#[test]
+fn test_escape_time()
+{
+    assert_eq!(escape_time(Complex{re: 0.0, im: 0.0}, 1000), None);
+    assert_eq!(escape_time(Complex{re: 3.0, im: 0.0}, 1000), Some(1));
+    assert_eq!(mandelbrot_orbit(Complex{re: -1.0, im: 0.0}, 50), OrbitOutcome::MaxIter);
+    assert_eq!(mandelbrot_orbit(Complex{re: 0.5, im: 0.5}, 50), OrbitOutcome::Escaped(5));
+}
+
+#[test]
+fn test_orbit_cycles()
+{
+    let zero = Complex{re: 0.0, im: 0.0};
+    let period = |c: Complex<f64>| match orbit(zero, c, Formula::MANDELBROT, 10_000)
+    {
+        OrbitOutcome::Cycled(period) => period,
+        outcome => panic!("{} did not cycle: {:?}", c, outcome),
+    };
+    assert_eq!(period(Complex{re: -1.0, im: 0.0}), 2);   //0, -1, 0, -1, ... exactly.
+    assert_eq!(period(Complex{re: -0.1, im: 0.0}), 1);   //Converges to a fixed point.
+    assert_eq!(period(Complex{re: -0.1226, im: 0.7449}) % 3, 0); //In the period-3 bulb.
+    assert_eq!(period(Complex{re: -1.7549, im: 0.0}) % 3, 0);    //The period-3 minibrot on the real axis.
+    //Points that escape, however slowly, are never mistaken for cycles.
+    assert!(orbit(zero, Complex{re: 0.2501, im: 0.0}, Formula::MANDELBROT, 10_000).escape_count().is_some());
+    assert!(orbit(zero, Complex{re: -0.75, im: 0.01}, Formula::MANDELBROT, 10_000).escape_count().is_some());
+}
+
+/// Like escape_time, but returns a continuous ("smooth") iteration count instead of a whole number, which
+/// removes the visible bands between neighbouring counts. This uses the normalized iteration count
+/// i + 1 - log2(log2(|z|)): it measures how far past the bailout circle the orbit jumped on its last step.
+/// The bailout radius is raised from 2 to 256 so that |z| is large enough for the formula to be accurate.
+pub fn escape_time_smooth(c: Complex<f64>, limit: usize) -> Option<f64>
+{
+    escape_time_smooth_from(Complex{re: 0.0, im: 0.0}, c, Formula::MANDELBROT, limit)
+}
+
+/// escape_time_smooth for an orbit of 'formula' starting at z0 (see orbit()). Far from the set each step raises
+/// |z| to the power, so log|z| grows by that factor per iteration, and the fractional part of the count takes its
+/// logarithm to base 'power' instead of base 2.
+pub fn escape_time_smooth_from(z0: Complex<f64>, c: Complex<f64>, formula: Formula, limit: usize) -> Option<f64>
+{
+    let mut z = z0;
+    let mut cycle = CycleCheck::new(z0);
+    for i in 0..limit
+    {
+        if z.norm_sqr() > 256.0 * 256.0
+        {
+            return Some((i as f64 + 1.0 - z.norm().log2().ln() / formula.power.ln()).max(0.0));
+        }
+        z = formula.step(z, c);
+        if cycle.returned(z).is_some()
+        {
+            return None;
+        }
+    }
+    None
+}
+
+#[test]
+fn test_escape_time_smooth()
+{
+    assert_eq!(escape_time_smooth(Complex{re: -1.0, im: 0.0}, 100), None);
+    //Points just inside and just outside an integer band boundary get close smooth values.
+    let a = escape_time_smooth(Complex{re: 0.3, im: 0.6}, 1000).unwrap();
+    let b = escape_time_smooth(Complex{re: 0.3, im: 0.6 + 1e-7}, 1000).unwrap();
+    assert!((a - b).abs() < 1e-3);
+    //The smooth count tracks the integer count to within the few extra steps the larger bailout takes.
+    let count = escape_time(Complex{re: 0.3, im: 0.6}, 1000).unwrap() as f64;
+    assert!(a > count - 1.0 && a < count + 4.0);
+}
+
+#[test]
+fn test_multibrot_power()
+{
+    let zero = Complex{re: 0.0, im: 0.0};
+    let cubic = Formula{power: 3.0, ..Formula::MANDELBROT};
+    //z^3 + c with c = 1: 0, 1, 2, 9.
+    assert_eq!(orbit(zero, Complex{re: 1.0, im: 0.0}, cubic, 50), OrbitOutcome::Escaped(3));
+    //c = -1 is inside the Mandelbrot set but escapes the cubic one: 0, -1, -2, -9.
+    assert_eq!(orbit(zero, Complex{re: -1.0, im: 0.0}, cubic, 50), OrbitOutcome::Escaped(3));
+    assert_eq!(orbit(zero, Complex{re: 0.0, im: 0.5}, cubic, 50), OrbitOutcome::MaxIter);
+    //Whole and fractional powers agree where the principal branch does not matter.
+    let z = Complex{re: 0.6, im: 0.3};
+    assert!((pow(z, 4.0) - pow(z, 4.0 + 1e-12)).norm() < 1e-9);
+    //The smooth count stays continuous with the adjusted logarithm base.
+    let a = escape_time_smooth_from(zero, Complex{re: 0.4, im: 0.7}, cubic, 1000).unwrap();
+    let b = escape_time_smooth_from(zero, Complex{re: 0.4, im: 0.7 + 1e-7}, cubic, 1000).unwrap();
+    assert!((a - b).abs() < 1e-3);
+}
+
+#[test]
+fn test_tricorn()
+{
+    let zero = Complex{re: 0.0, im: 0.0};
+    let tricorn = Formula{fractal: Fractal::Tricorn, ..Formula::MANDELBROT};
+    //On the real axis conj does nothing, so the Tricorn agrees with the Mandelbrot set there.
+    assert_eq!(orbit(zero, Complex{re: -1.0, im: 0.0}, tricorn, 100), OrbitOutcome::Cycled(2));
+    assert_eq!(orbit(zero, Complex{re: 0.5, im: 0.0}, tricorn, 100), mandelbrot_orbit(Complex{re: 0.5, im: 0.0}, 100));
+    //Off it they differ: for c = i the Mandelbrot orbit cycles (0, i, -1 + i, -i, -1 + i, ...) but the Tricorn's
+    //escapes (0, i, -1 + i, 3i, ...).
+    assert_eq!(mandelbrot_orbit(Complex{re: 0.0, im: 1.0}, 100), OrbitOutcome::Cycled(2));
+    assert_eq!(orbit(zero, Complex{re: 0.0, im: 1.0}, tricorn, 100), OrbitOutcome::Escaped(3));
+    //The Tricorn is symmetric under rotation by a third of a turn.
+    let c = Complex{re: 0.2, im: 0.3};
+    let turn = Complex::from_polar(1.0, std::f64::consts::TAU / 3.0);
+    assert_eq!(orbit(zero, c, tricorn, 200).escape_count().is_some(), orbit(zero, c * turn, tricorn, 200).escape_count().is_some());
+    assert_eq!("tricorn".parse::<Fractal>(), Ok(Fractal::Tricorn));
+    assert!("burning".parse::<Fractal>().unwrap_err().contains("mandelbrot or tricorn"));
+}
+
+/// The following functions maps pixels to complex numbers.
+/// The Mandelbrot set's mathematical definition works in the continuous space of the complex plane.
+/// Example: The point 𝑐 = −0.5 + 0.5𝑖 is a point in the complex plane, not a pixel.
+/// Mapping Is the Bridge To compute whether a pixel should be part of the Mandelbrot set visualization:
+/// We first map it to its corresponding complex number using pixel_to_point.
+/// Then, we test the complex number using the Mandelbrot iterative algorithm.
+/// The Complex Plane: We define a rectangular region of the complex plane to visualize, such as:
+/// Upper-left corner:  (-2.0 + 1.0i) Lower-right corner: (1.0 - 1.0i)
+/// This region corresponds to the part of the Mandelbrot set we want to compute.
+/// Corresponding Coloring: Once each pixel is mapped to a complex number, the Mandelbrot algorithm determines:
+/// Whether the number belongs to the Mandelbrot set (color it black). How quickly it escapes the set (color it based on escape speed).
+pub fn pixel_to_point(bounds: (usize, usize), pixel: (usize, usize),
+upper_left: Complex<f64>, lower_right: Complex<f64>) -> Complex<f64>
+//bounds: (usize, usize): The width and height of the image in pixels (e.g., bounds = (800, 600) for an 800×600 image).
+// pixel: (usize, usize): The pixel's 2D coordinates in the image (e.g., (400, 300))
+{
+    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+
+    Complex
+    {
+        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
+        //pixel.0: The horizontal pixel index
+        //pixel.0 as f64 ensures the horizontal pixel index is treated as a floating-point number.
+        //The calculation scales pixel.0 (from 0 to bounds.0) to the corresponding range in the real axis of the complex plane (upper_left.re to lower_right.re).
+        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
+        //why subtraction here? Pixel rows count downwards from the top of the image, while the imaginary axis grows upwards.
+    }
+}
+
+/// The point of sample 'sample' of 'pixel' when each pixel is split into a 'grid' x 'grid' grid of samples: the
+/// center of that cell of the grid, so the samples cover the pixel evenly. pixel_to_point(bounds, pixel, ...) is the
+/// pixel's top-left corner, where cell (0, 0) of a one-cell grid would be its center.
+pub fn subpixel_to_point(bounds: (usize, usize), pixel: (usize, usize), sample: (usize, usize), grid: usize,
+                         upper_left: Complex<f64>, lower_right: Complex<f64>) -> Complex<f64>
+{
+    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    let offset = |index: usize| (index as f64 + 0.5) / grid as f64;
+    Complex
+    {
+        re: upper_left.re + (pixel.0 as f64 + offset(sample.0)) * width / bounds.0 as f64,
+        im: upper_left.im - (pixel.1 as f64 + offset(sample.1)) * height / bounds.1 as f64,
+    }
+}
+
+#[test]
+fn test_pixel_to_point()
+{
+    assert_eq!(pixel_to_point((100, 200), (25, 175),
+                              Complex{re: -1.0, im:  1.0},
+                              Complex{re:  1.0, im: -1.0}),
+               Complex{re: -0.5, im: -0.75});
+    //Pixels are 0.02 x 0.01; a 2x2 grid puts samples a quarter of the way in from each side.
+    assert_eq!(subpixel_to_point((100, 200), (25, 175), (1, 0), 2, Complex{re: -1.0, im: 1.0}, Complex{re: 1.0, im: -1.0}),
+               Complex{re: -0.485, im: -0.7525});
+}
+
+/// The knobs that control how a render is computed and shaded, independent of where in the plane it is and how
+/// many pixels it has. The command-line flags fill this in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings
+{
+    pub limit: usize,             //Iteration limit.
+    pub coloring: Coloring,       //What the exterior color is based on.
+    pub palette: Option<Palette>, //Color gradient; None renders grayscale.
+    pub julia: Option<Complex<f64>>, //Render the Julia set for this c instead of the Mandelbrot set.
+    pub interior: Interior,          //How to color points inside the set.
+    pub power: f64,                  //Exponent d in z = z^d + c; 2 is the Mandelbrot set, others are Multibrots.
+    pub fractal: Fractal,            //Which escape-time formula to iterate.
+    pub line_art: Option<f64>,       //Draw the boundary as black strokes this many pixels wide on white instead.
+    pub newton: Option<Newton>,      //Render the Newton fractal of this polynomial instead of an escape-time set.
+    pub early_out: bool,             //Skip iterating points in the main cardioid and period-2 bulb.
+    pub simd: bool,                  //Iterate LANES pixels at a time where possible (see the lanes module).
+    pub ssaa: usize,                 //Average an ssaa x ssaa grid of samples per pixel; 1 takes one sample.
+}
+
+impl Default for Settings
+{
+    fn default() -> Settings
+    {
+        Settings{limit: 255, coloring: Coloring::EscapeTime, palette: None, julia: None, interior: Interior::Black, power: 2.0,
+                 fractal: Fractal::Mandelbrot, line_art: None, newton: None, early_out: true,
+                 simd: true, ssaa: 1}
+    }
+}
+
+impl Settings
+{
+    /// Whether pixel buffers for these settings hold one gray byte or three RGB bytes per pixel.
+    pub fn color_type(&self) -> png::ColorType
+    {
+        if (self.palette.is_some() || self.newton.is_some()) && self.line_art.is_none() { png::ColorType::Rgb } else { png::ColorType::Gray }
+    }
+
+    /// The map these settings iterate.
+    pub fn formula(&self) -> Formula
+    {
+        Formula{fractal: self.fractal, power: self.power}
+    }
+
+    /// The orbit's starting value z0 and constant c for a point of the image. In Julia mode the point is z0;
+    /// otherwise it is c and the orbit starts at 0.
+    pub fn orbit_start(&self, point: Complex<f64>) -> (Complex<f64>, Complex<f64>)
+    {
+        match self.julia
+        {
+            Some(c) => (point, c),
+            None => (Complex{re: 0.0, im: 0.0}, point),
+        }
+    }
+
+    /// Whether render() can use the lane-parallel loop: it does the classic z * z + c (or its Julia sets) with
+    /// escape-time or smooth coloring, and everything else falls back to the scalar path.
+    pub fn uses_lanes(&self) -> bool
+    {
+        self.simd && self.newton.is_none() && self.line_art.is_none() && self.formula() == Formula::MANDELBROT
+            && self.coloring != Coloring::Distance
+    }
+
+    /// Whether the deep-zoom paths (the deep and perturbation modules) can render these settings: the classic
+    /// formula or its Julia sets, escape-time or smooth coloring, black interiors, and one sample per pixel.
+    pub fn supports_deep(&self) -> bool
+    {
+        self.newton.is_none() && self.line_art.is_none() && self.formula() == Formula::MANDELBROT
+            && matches!(self.coloring, Coloring::EscapeTime | Coloring::Smooth) && self.interior == Interior::Black
+            && self.ssaa == 1
+    }
+
+    /// Whether the point is known to be in the set without iterating: it is in the classic set's main cardioid or
+    /// period-2 bulb (see in_cardioid_or_bulb) and the early-out check hasn't been turned off for benchmarking.
+    pub fn known_interior(&self, point: Complex<f64>) -> bool
+    {
+        self.early_out && self.julia.is_none() && self.formula() == Formula::MANDELBROT && in_cardioid_or_bulb(point)
+    }
+}
+
+/// What the color of points outside the set is based on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coloring
+{
+    EscapeTime, //Whole iteration counts, which show as bands.
+    Smooth,     //escape_time_smooth's continuous count.
+    Distance,   //The distance estimate, in pixels: thin filaments the escape count jumps over stay visible.
+    Histogram,  //Smooth counts spread evenly over the image's pixels (see the histogram module).
+}
+
+impl std::str::FromStr for Coloring
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Coloring, String>
+    {
+        match s
+        {
+            "escape" => Ok(Coloring::EscapeTime),
+            "smooth" => Ok(Coloring::Smooth),
+            "de" => Ok(Coloring::Distance),
+            "histogram" => Ok(Coloring::Histogram),
+            _ => Err(format!("unknown coloring '{}' (expected escape, smooth, de or histogram)", s)),
+        }
+    }
+}
+
+/// The escape value of one point: a whole or smooth iteration count, or None for points in the set.
+pub fn escape_value(point: Complex<f64>, settings: &Settings) -> Option<f64>
+{
+    if settings.known_interior(point)
+    {
+        return None;
+    }
+    let (z0, c) = settings.orbit_start(point);
+    if settings.coloring == Coloring::Smooth
+    {
+        escape_time_smooth_from(z0, c, settings.formula(), settings.limit)
+    }
+    else
+    {
+        orbit(z0, c, settings.formula(), settings.limit).escape_count().map(|count| count as f64)
+    }
+}
+
+#[test]
+fn test_escape_value_julia()
+{
+    //With c = 0 the Julia set is the closed unit disk.
+    let disk = Settings{julia: Some(Complex{re: 0.0, im: 0.0}), ..Settings::default()};
+    assert_eq!(escape_value(Complex{re: 0.0, im: 0.9}, &disk), None);
+    assert_eq!(escape_value(Complex{re: 1.5, im: 0.0}, &disk), Some(1.0));
+    //The same point as c escapes in the Mandelbrot set, but as z0 it is inside the Julia set for c = -1.
+    let basilica = Settings{julia: Some(Complex{re: -1.0, im: 0.0}), ..Settings::default()};
+    assert!(escape_value(Complex{re: 1.0, im: 0.0}, &Settings::default()).is_some());
+    assert_eq!(escape_value(Complex{re: 1.0, im: 0.0}, &basilica), None);
+}
+
+#[test]
+fn test_escape_value_early_out()
+{
+    //The shortcut changes how fast, never what: every value matches iterating the long way.
+    let fast = Settings{limit: 200, coloring: Coloring::Smooth, ..Settings::default()};
+    let slow = Settings{early_out: false, ..fast.clone()};
+    for k in 0..1000
+    {
+        let point = Complex{re: -2.0 + 2.5 * (k % 40) as f64 / 40.0, im: 1.2 * (k / 40) as f64 / 25.0};
+        assert_eq!(escape_value(point, &fast), escape_value(point, &slow), "{}", point);
+    }
+    assert!(fast.known_interior(Complex{re: 0.0, im: 0.0}) && !slow.known_interior(Complex{re: 0.0, im: 0.0}));
+    //It only applies to the classic set.
+    assert!(!Settings{julia: Some(Complex{re: 0.5, im: 0.5}), ..fast.clone()}.known_interior(Complex{re: 0.0, im: 0.0}));
+    assert!(!Settings{power: 3.0, ..fast}.known_interior(Complex{re: 0.3, im: 0.0}));
+}
+
+/// How points inside the set are colored. The textures come from the cycle multiplier (see interior_multiplier), which
+/// varies across the Mandelbrot set; every interior point of a Julia set shares one cycle, so there they give one flat color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interior
+{
+    Black,      //Flat black, the classic look.
+    Angle,      //Internal angle: the argument of the attracting cycle's multiplier, as a fraction of a turn.
+    Multiplier, //Magnitude of the multiplier: 0 at the center of a bulb, rising to 1 at its edge.
+}
+
+impl std::str::FromStr for Interior
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Interior, String>
+    {
+        match s
+        {
+            "black" => Ok(Interior::Black),
+            "angle" => Ok(Interior::Angle),
+            "multiplier" => Ok(Interior::Multiplier),
+            _ => Err(format!("unknown interior coloring '{}' (expected black, angle, or multiplier)", s)),
+        }
+    }
+}
+
+/// The multiplier of the attracting cycle an orbit settles into: the derivative of the p-times iterated map
+/// around one period of the cycle, 2z_1 * 2z_2 * ... * 2z_p for the classic set (see Formula::derivative for the
+/// general factors). Inside a bulb of the Mandelbrot set its magnitude is 0 at the bulb's center and 1 at its edge,
+/// and its argument is the internal angle, so mapping either one to a gradient shows the structure of the interior. Returns None if the orbit escapes, or has not settled onto a
+/// cycle (of period at most 'limit') after 'limit' iterations.
+pub fn interior_multiplier(z0: Complex<f64>, c: Complex<f64>, formula: Formula, limit: usize) -> Option<Complex<f64>>
+{
+    let mut z = z0;
+    for _ in 0..limit
+    {
+        if z.norm_sqr() > 4.0
+        {
+            return None;
+        }
+        z = formula.step(z, c);
+    }
+    //The period is the number of steps until the orbit comes back to where it settled.
+    let settled = z;
+    let mut multiplier = Complex{re: 1.0, im: 0.0};
+    for _ in 0..limit
+    {
+        multiplier = formula.derivative(z, multiplier);
+        z = formula.step(z, c);
+        if (z - settled).norm_sqr() < 1e-12
+        {
+            return Some(multiplier);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_interior_multiplier()
+{
+    let zero = Complex{re: 0.0, im: 0.0};
+    //Centers of bulbs have superattracting cycles.
+    assert_eq!(interior_multiplier(zero, zero, Formula::MANDELBROT, 100), Some(zero));
+    assert!(interior_multiplier(zero, Complex{re: -1.0, im: 0.0}, Formula::MANDELBROT, 100).unwrap().norm() < 1e-9);
+    //Points of the main cardioid are c = m/2 - m^2/4 for the multiplier m of their fixed point.
+    let m = Complex{re: 0.0, im: 0.5};
+    let multiplier = interior_multiplier(zero, m / 2.0 - m * m / 4.0, Formula::MANDELBROT, 1000).unwrap();
+    assert!((multiplier - m).norm() < 1e-5, "{}", multiplier);
+    assert_eq!(interior_multiplier(zero, Complex{re: 1.0, im: 0.0}, Formula::MANDELBROT, 100), None);
+}
+
+/// The position along the gradient (0..=1) of an interior point, or None to leave it black.
+fn interior_shade(point: Complex<f64>, settings: &Settings) -> Option<f64>
+{
+    if settings.interior == Interior::Black
+    {
+        return None;
+    }
+    let (z0, c) = settings.orbit_start(point);
+    let multiplier = interior_multiplier(z0, c, settings.formula(), settings.limit)?;
+    match settings.interior
+    {
+        Interior::Angle => Some((multiplier.arg() / std::f64::consts::TAU).rem_euclid(1.0)),
+        _ => Some(multiplier.norm().min(1.0)),
+    }
+}
+
+/// An estimate of the distance from 'point' to the set (in the same units as the complex plane), or None for
+/// points in the set. It comes from the Green's function G = lim log|z_n| / d^n: G / 2|G'| = |z| log|z| / 2|z'|,
+/// where z' is the derivative of the orbit with respect to the point (c, or z0 in Julia mode). For the classic
+/// set this never overestimates the true distance (the Koebe quarter theorem) and is usually within a factor of
+/// 2 or 3 of it, which is close enough to draw strokes of an even width; near cusps it underestimates more.
+pub fn distance_estimate(point: Complex<f64>, settings: &Settings) -> Option<f64>
+{
+    if settings.known_interior(point)
+    {
+        return None;
+    }
+    let (mut z, c) = settings.orbit_start(point);
+    let formula = settings.formula();
+    let (mut dz, dc) = if settings.julia.is_some() { (Complex{re: 1.0, im: 0.0}, 0.0) } else { (Complex{re: 0.0, im: 0.0}, 1.0) };
+    for _ in 0..settings.limit
+    {
+        //A large bailout makes the limit formula accurate.
+        if z.norm_sqr() > 1e12
+        {
+            let r = z.norm();
+            return Some(r * r.ln() / (2.0 * dz.norm()));
+        }
+        dz = formula.derivative(z, dz) + dc;
+        z = formula.step(z, c);
+    }
+    None
+}
+
+#[test]
+fn test_distance_estimate()
+{
+    let settings = Settings{limit: 1000, ..Settings::default()};
+    //On the real axis the set ends at -2 and 0.25.
+    for (point, distance) in [(-2.5, 0.5), (1.0, 0.75)]
+    {
+        let estimate = distance_estimate(Complex{re: point, im: 0.0}, &settings).unwrap();
+        assert!(estimate < distance && estimate > distance / 4.0, "{} -> {}", point, estimate);
+    }
+    assert!(distance_estimate(Complex{re: 0.3, im: 0.0}, &settings).unwrap() < 0.05); //Near the cusp at 0.25.
+    assert_eq!(distance_estimate(Complex{re: -1.0, im: 0.0}, &settings), None);
+    //The Julia set for c = 0 is the unit circle.
+    let disk = Settings{julia: Some(Complex{re: 0.0, im: 0.0}), ..settings};
+    let estimate = distance_estimate(Complex{re: 0.0, im: 2.0}, &disk).unwrap();
+    assert!(estimate > 0.25 && estimate < 4.0);
+}
+
+/// Supersampling grid for line art: each pixel is tested at LINE_ART_SAMPLES x LINE_ART_SAMPLES points.
+const LINE_ART_SAMPLES: usize = 4;
+
+/// Line art for the pixel whose upper-left corner is 'point' and whose width is 'spacing': black if most of its
+/// subsamples lie within half the stroke width of the set (and outside it), white otherwise. Testing subsamples
+/// against the distance estimate places the stroke edges with sub-pixel accuracy, and the majority vote keeps the
+/// output pure black and white for cutting and engraving.
+fn paint_line_art(point: Complex<f64>, spacing: f64, stroke: f64, settings: &Settings, pixel: &mut [u8])
+{
+    let reach = stroke * spacing / 2.0;
+    let mut hits = 0;
+    for j in 0..LINE_ART_SAMPLES
+    {
+        for i in 0..LINE_ART_SAMPLES
+        {
+            let offset = |k: usize| (k as f64 + 0.5) / LINE_ART_SAMPLES as f64 * spacing;
+            let sample = Complex{re: point.re + offset(i), im: point.im - offset(j)};
+            if distance_estimate(sample, settings).is_some_and(|distance| distance < reach)
+            {
+                hits += 1;
+            }
+        }
+    }
+    pixel.fill(if hits * 2 >= LINE_ART_SAMPLES * LINE_ART_SAMPLES { 0 } else { 255 });
+}
+
+/// Newton fractal coloring: the color of the root the point converges to, darkened by the number of steps it took
+/// on the same logarithmic scale palettes use for escape counts. Points that never settle are black.
+fn paint_newton(point: Complex<f64>, newton: &Newton, settings: &Settings, pixel: &mut [u8])
+{
+    match newton.orbit(point, settings.limit)
+    {
+        OrbitOutcome::ConvergedToRoot(k, steps) =>
+        {
+            let shade = 1.0 - (1.0 + steps as f64).ln() / (1.0 + settings.limit as f64).ln();
+            let color = newton::root_color(k, newton.roots.len(), settings.palette.as_ref());
+            for (p, c) in pixel.iter_mut().zip(color)
+            {
+                *p = (c as f64 * shade).round() as u8;
+            }
+        }
+        _ => pixel.fill(0),
+    }
+}
+
+/// Color a point in the set: black unless settings.interior picks a texture.
+pub(crate) fn paint_interior(point: Complex<f64>, settings: &Settings, pixel: &mut [u8])
+{
+    match (interior_shade(point, settings), &settings.palette)
+    {
+        (None, _) => pixel.fill(0),
+        (Some(t), None) => pixel[0] = (t * 255.0).round() as u8,
+        (Some(t), Some(palette)) => pixel.copy_from_slice(&palette.color(t)),
+    }
+}
+
+/// Distances (in pixels) at which distance coloring reaches the far end of the gradient.
+const DISTANCE_COLORING_RANGE: f64 = 256.0;
+
+/// Distance-estimate coloring for the pixel at 'point', 'spacing' wide. The distance in pixels goes through the
+/// same kind of logarithmic scale as escape counts, so everything within a fraction of a pixel of the set is
+/// nearly black (or the palette's start) however thin it is, and the far end is reached DISTANCE_COLORING_RANGE
+/// pixels out.
+fn paint_distance(point: Complex<f64>, spacing: f64, settings: &Settings, pixel: &mut [u8])
+{
+    let Some(distance) = distance_estimate(point, settings) else { return paint_interior(point, settings, pixel) };
+    let t = (1.0 + distance / spacing).ln() / (1.0 + DISTANCE_COLORING_RANGE).ln();
+    match &settings.palette
+    {
+        None => pixel[0] = (t.min(1.0) * 255.0).round() as u8,
+        Some(palette) => pixel.copy_from_slice(&palette.color(t)),
+    }
+}
+
+/// Write the color of one point into 'pixel' (one byte for grayscale, three for RGB).
+/// Points in the set are black unless settings.interior picks a texture. Without a palette, points that escape
+/// quickly are light, and the longer a point takes to escape, the darker it gets, with the gray levels spread
+/// evenly over 0..limit. With a palette, the escape value is looked up in the gradient.
+pub fn paint(point: Complex<f64>, settings: &Settings, pixel: &mut [u8])
+{
+    if let Some(newton) = &settings.newton
+    {
+        return paint_newton(point, newton, settings, pixel);
+    }
+    paint_escape_value(escape_value(point, settings), point, settings, pixel);
+}
+
+/// The second half of paint(): color a point whose escape value is already known.
+pub(crate) fn paint_escape_value(value: Option<f64>, point: Complex<f64>, settings: &Settings, pixel: &mut [u8])
+{
+    match (value, &settings.palette)
+    {
+        (None, _) => paint_interior(point, settings, pixel),
+        (Some(value), None) => pixel[0] = 255 - (value * 255.0 / settings.limit as f64).min(255.0) as u8,
+        (Some(value), Some(palette)) => pixel.copy_from_slice(&palette.color_for(value, settings.limit)),
+    }
+}
+
+/// Render a rectangle of the Mandelbrot set into a buffer of pixels.
+/// The 'bounds' argument gives the width and height of the buffer 'pixels', which holds one grayscale pixel per byte
+/// (or three bytes per pixel when settings.palette is set).
+/// The 'upper_left' and 'lower_right' arguments specify points on the complex plane corresponding to the upper-left
+/// and lower-right corners of the pixel buffer. Each pixel gets the color paint() computes for its point, or
+/// line art or distance coloring, which also need to know how wide a pixel is.
+pub fn render(pixels: &mut [u8], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings)
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    let spacing = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    if settings.ssaa > 1
+    {
+        return render_supersampled(pixels, bounds, upper_left, lower_right, settings);
+    }
+
+    for row in 0..bounds.1
+    {
+        if settings.uses_lanes()
+        {
+            let points: Vec<Complex<f64>> = (0..bounds.0).map(|column| pixel_to_point(bounds, (column, row), upper_left, lower_right)).collect();
+            for (column, value) in lanes::escape_values(&points, settings).into_iter().enumerate()
+            {
+                let index = (row * bounds.0 + column) * channels;
+                paint_escape_value(value, points[column], settings, &mut pixels[index..index + channels]);
+            }
+            continue;
+        }
+        for column in 0..bounds.0
+        {
+            let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
+            let index = (row * bounds.0 + column) * channels;
+            paint_pixel(point, spacing, settings, &mut pixels[index..index + channels]);
+        }
+    }
+}
+
+/// Color the point of a pixel 'spacing' wide as render() does.
+pub(crate) fn paint_pixel(point: Complex<f64>, spacing: f64, settings: &Settings, pixel: &mut [u8])
+{
+    match (settings.line_art, settings.coloring)
+    {
+        (Some(stroke), _) => paint_line_art(point, spacing, stroke, settings, pixel),
+        (None, Coloring::Distance) => paint_distance(point, spacing, settings, pixel),
+        (None, _) => paint(point, settings, pixel),
+    }
+}
+
+/// render() with settings.ssaa² samples per pixel (see subpixel_to_point), whose colors are averaged. Line art and
+/// distance coloring keep the width of a whole pixel, so strokes are as wide as without supersampling.
+fn render_supersampled(pixels: &mut [u8], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+                       settings: &Settings)
+{
+    let channels = settings.color_type().channels();
+    let spacing = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    let grid = settings.ssaa;
+    let samples = grid * grid;
+    let mut sample = [0u8; 3];
+    for (row, row_pixels) in pixels.chunks_mut(bounds.0 * channels).enumerate()
+    {
+        let points: Vec<Complex<f64>> = (0..bounds.0 * samples)
+            .map(|i| subpixel_to_point(bounds, (i / samples, row), (i % grid, i % samples / grid), grid, upper_left, lower_right))
+            .collect();
+        let values = settings.uses_lanes().then(|| lanes::escape_values(&points, settings));
+        for (column, pixel) in row_pixels.chunks_mut(channels).enumerate()
+        {
+            let mut sums = [0usize; 3];
+            for i in column * samples..(column + 1) * samples
+            {
+                let sample = &mut sample[..channels];
+                match &values
+                {
+                    Some(values) => paint_escape_value(values[i], points[i], settings, sample),
+                    None => paint_pixel(points[i], spacing, settings, sample),
+                }
+                for (sum, &value) in sums.iter_mut().zip(sample.iter())
+                {
+                    *sum += value as usize;
+                }
+            }
+            for (value, sum) in pixel.iter_mut().zip(sums)
+            {
+                *value = ((sum + samples / 2) / samples) as u8;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_render()
+{
+    let mut pixels = vec![7u8; 4 * 3];
+    render(&mut pixels, (4, 3), Complex{re: -2.0, im: 1.5}, Complex{re: 2.0, im: -1.5}, &Settings::default());
+    //Column 2 of the middle row is the origin, which is in the set; the corner pixel escapes at once.
+    assert_eq!(pixels[4 + 2], 0);
+    assert_eq!(pixels[0], 255 - escape_time(Complex{re: -2.0, im: 1.5}, 255).unwrap() as u8);
+
+    let settings = Settings{palette: Palette::builtin("fire"), ..Settings::default()};
+    let mut rgb = vec![7u8; 4 * 3 * 3];
+    render(&mut rgb, (4, 3), Complex{re: -2.0, im: 1.5}, Complex{re: 2.0, im: -1.5}, &settings);
+    assert_eq!(&rgb[(4 + 2) * 3..(4 + 3) * 3], &[0, 0, 0]);
+    let corner = escape_time(Complex{re: -2.0, im: 1.5}, 255).unwrap() as f64;
+    assert_eq!(&rgb[..3], &settings.palette.unwrap().color_for(corner, 255));
+
+    //Line art of the unit circle (the Julia set for c = 0): strokes only near the circle, white elsewhere.
+    let settings = Settings{julia: Some(Complex{re: 0.0, im: 0.0}), line_art: Some(2.0), ..Settings::default()};
+    let mut art = vec![7u8; 40 * 40];
+    render(&mut art, (40, 40), Complex{re: -2.0, im: 2.0}, Complex{re: 2.0, im: -2.0}, &settings);
+    assert!(art.iter().all(|&p| p == 0 || p == 255));
+    assert_eq!(art[20 * 40 + 20], 255); //Center of the disk.
+    assert_eq!(art[0], 255);            //Far outside.
+    assert_eq!(art[19 * 40 + 30], 0);   //Just outside the circle at (1.05, 0.05).
+
+    //Distance coloring of the same circle: black inside, dark next to it, lighter further out.
+    let settings = Settings{julia: Some(Complex{re: 0.0, im: 0.0}), coloring: Coloring::Distance, ..Settings::default()};
+    let mut shades = vec![7u8; 40 * 40];
+    render(&mut shades, (40, 40), Complex{re: -2.0, im: 2.0}, Complex{re: 2.0, im: -2.0}, &settings);
+    assert_eq!(shades[20 * 40 + 20], 0);
+    assert!(shades[19 * 40 + 30] < shades[19 * 40 + 35] && shades[19 * 40 + 35] < shades[0]);
+    assert!(shades[19 * 40 + 30] < 64);
+}
+
+/// Render with several threads by splitting the image into horizontal bands, one band per thread.
+/// Each band is an independent render() call over its own slice of 'pixels' and its own corner points, so the
+/// threads never touch the same memory. std::thread::scope lets the threads borrow 'pixels' because it joins
+/// them all before returning.
+pub fn render_parallel(pixels: &mut [u8], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+                       settings: &Settings, threads: usize, progress: &Progress)
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    let rows_per_band = bounds.1.div_ceil(threads.max(1)).max(1);
+    std::thread::scope(|spawner|
+    {
+        for (i, band) in pixels.chunks_mut(rows_per_band * bounds.0 * channels).enumerate()
+        {
+            let top = rows_per_band * i;
+            let height = band.len() / (bounds.0 * channels);
+            let band_bounds = (bounds.0, height);
+            let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
+            let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
+            spawner.spawn(move ||
+            {
+                render(band, band_bounds, band_upper_left, band_lower_right, settings);
+                progress.advance(height as u64);
+            });
+        }
+    });
+}
+
+#[test]
+fn test_render_supersampled()
+{
+    let bounds = (24, 18);
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.2}, Complex{re: 0.6, im: -1.2});
+    //A 2x2 grid samples the points of an image twice the size, shifted by a quarter of a pixel to the cells' centers.
+    let shift = Complex{re: 2.6 / bounds.0 as f64 / 4.0, im: -2.4 / bounds.1 as f64 / 4.0};
+    for base in [Settings{limit: 200, palette: Palette::builtin("classic"), ..Settings::default()},
+                 Settings{limit: 200, simd: false, ..Settings::default()}]
+    {
+        let channels = base.color_type().channels();
+        let large = (bounds.0 * 2, bounds.1 * 2);
+        let mut samples = vec![0u8; large.0 * large.1 * channels];
+        render(&mut samples, large, upper_left + shift, lower_right + shift, &base);
+        let expected: Vec<u8> = (0..bounds.0 * bounds.1 * channels).map(|i|
+        {
+            let (pixel, channel) = (i / channels, i % channels);
+            let (column, row) = (pixel % bounds.0, pixel / bounds.0);
+            let at = |x: usize, y: usize| samples[((row * 2 + y) * large.0 + column * 2 + x) * channels + channel] as usize;
+            ((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1) + 2) / 4) as u8
+        }).collect();
+        let settings = Settings{ssaa: 2, ..base};
+        let mut pixels = vec![0u8; expected.len()];
+        render(&mut pixels, bounds, upper_left, lower_right, &settings);
+        assert_eq!(pixels, expected);
+        //The same samples whichever renderer takes the rows.
+        let mut parallel = vec![0u8; pixels.len()];
+        render_rows(&mut parallel, bounds, upper_left, lower_right, &settings, 3, &Progress::silent());
+        assert_eq!(parallel, pixels);
+        render_parallel(&mut parallel, bounds, upper_left, lower_right, &settings, 4, &Progress::silent());
+        assert_eq!(parallel, pixels);
+    }
+}
+
+#[test]
+fn test_render_parallel_matches_render()
+{
+    let bounds = (37, 23);
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.2}, Complex{re: 0.6, im: -1.2});
+    let settings = Settings{limit: 300, coloring: Coloring::Smooth, palette: Palette::builtin("classic"), ..Settings::default()};
+    let mut expected = vec![0u8; bounds.0 * bounds.1 * 3];
+    render(&mut expected, bounds, upper_left, lower_right, &settings);
+    for threads in [1, 2, 5, 23, 64]
+    {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        render_parallel(&mut pixels, bounds, upper_left, lower_right, &settings, threads, &Progress::silent());
+        assert_eq!(pixels, expected, "threads = {}", threads);
+    }
+}
+
+/// Render with several threads that each take the next unrendered row whenever they finish one.
+/// Static bands leave threads idle once their cheap bands are done, while the band over the body of the set
+/// is still running; handing out single rows keeps every thread busy until the image is complete.
+pub fn render_rows(pixels: &mut [u8], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>,
+                   settings: &Settings, threads: usize, progress: &Progress)
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == bounds.0 * bounds.1 * channels);
+    parallel_rows(pixels, bounds.0 * channels, threads, progress, |top, row|
+    {
+        let row_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
+        let row_lower_right = pixel_to_point(bounds, (bounds.0, top + 1), upper_left, lower_right);
+        render(row, (bounds.0, 1), row_upper_left, row_lower_right, settings);
+    });
+}
+
+/// Run 'job' on every row of 'buffer' (rows of 'row_len' elements, passed with their row number) using 'threads'
+/// threads that each take the next unprocessed row whenever they finish one.
+pub(crate) fn parallel_rows<T: Send, F: Fn(usize, &mut [T]) + Sync>(buffer: &mut [T], row_len: usize, threads: usize,
+                                                                    progress: &Progress, job: F)
+{
+    //The iterator over (row number, row slice) pairs is the shared work queue; the Mutex hands out each row once.
+    let rows = std::sync::Mutex::new(buffer.chunks_mut(row_len.max(1)).enumerate());
+    std::thread::scope(|spawner|
+    {
+        for _ in 0..threads.max(1)
+        {
+            spawner.spawn(||
+            {
+                loop
+                {
+                    let next = rows.lock().unwrap().next();
+                    let Some((top, row)) = next else { break };
+                    job(top, row);
+                    progress.advance(1);
+                }
+            });
+        }
+    });
+}
+
+/// The distance from each pixel to the set, in pixels, from distance_estimate(); 0 for pixels in the set.
+/// Post-processing effects use this as a depth channel.
+pub fn distance_map(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+                    threads: usize, progress: &Progress) -> Vec<f64>
+{
+    let spacing = (lower_right.re - upper_left.re) / bounds.0 as f64;
+    let mut distances = vec![0.0; bounds.0 * bounds.1];
+    parallel_rows(&mut distances, bounds.0, threads, progress, |top, row|
+    {
+        for (column, distance) in row.iter_mut().enumerate()
+        {
+            let point = pixel_to_point(bounds, (column, top), upper_left, lower_right);
+            *distance = distance_estimate(point, settings).map_or(0.0, |d| d / spacing);
+        }
+    });
+    distances
+}
+
+#[test]
+fn test_distance_map()
+{
+    let settings = Settings{julia: Some(Complex{re: 0.0, im: 0.0}), ..Settings::default()};
+    //A 40x40 image of -2..2: 10 pixels per unit, unit circle as the set boundary.
+    let distances = distance_map((40, 40), Complex{re: -2.0, im: 2.0}, Complex{re: 2.0, im: -2.0}, &settings, 3, &Progress::silent());
+    assert_eq!(distances[20 * 40 + 20], 0.0);
+    let corner = distances[0]; //2.83 units from the origin, 18.3 pixels outside the circle.
+    assert!(corner > 18.3 / 4.0 && corner < 18.3, "{}", corner);
+}
+
+#[test]
+fn test_render_rows_matches_render()
+{
+    let bounds = (31, 17);
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.2}, Complex{re: 0.6, im: -1.2});
+    let settings = Settings{limit: 300, coloring: Coloring::Smooth, palette: Palette::builtin("classic"), ..Settings::default()};
+    let mut expected = vec![0u8; bounds.0 * bounds.1 * 3];
+    render(&mut expected, bounds, upper_left, lower_right, &settings);
+    for threads in [1, 3, 40]
+    {
+        let mut pixels = vec![0u8; bounds.0 * bounds.1 * 3];
+        render_rows(&mut pixels, bounds, upper_left, lower_right, &settings, threads, &Progress::silent());
+        assert_eq!(pixels, expected, "threads = {}", threads);
+    }
+}
+
+/// How the parallel renderer divides the image between threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler
+{
+    Bands,   //render_parallel: one fixed band per thread.
+    Dynamic, //render_rows: threads pull rows from a shared queue.
+}
+
+impl std::str::FromStr for Scheduler
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Scheduler, String>
+    {
+        match s
+        {
+            "bands" => Ok(Scheduler::Bands),
+            "dynamic" => Ok(Scheduler::Dynamic),
+            _ => Err(format!("unknown scheduler '{}' (expected bands or dynamic)", s)),
+        }
+    }
+}
+
+/// Write the buffer 'pixels', whose dimensions are given by 'bounds', to the file named 'filename' as a PNG with
+/// the given color type. The ? operator passes any I/O error (file not creatable, disk full, ...) back to the caller.
+pub fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize), color: png::ColorType) -> Result<(), std::io::Error>
+{
+    write_atomically(std::path::Path::new(filename), |output| png::encode(output, pixels, bounds, color))
+}
+
+/// Create or replace the file at 'path' with what 'write' writes, so that it is never seen half-written.
+/// The data goes to a temporary file next to it (a rename can't move files between filesystems), which is flushed
+/// to disk and then renamed over 'path' in one step. If anything fails, from a full disk to the process being
+/// killed mid-encode, 'path' keeps its old contents; the temporary file is removed on errors, and is at worst
+/// left behind as a hidden ".NAME.PID.tmp" after a crash.
+pub fn write_atomically(path: &std::path::Path, write: impl FnOnce(&mut std::io::BufWriter<std::fs::File>) -> std::io::Result<()>)
+                        -> std::io::Result<()>
+{
+    let name = path.file_name().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "not a file name"))?;
+    let temporary = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+    let result = (||
+    {
+        let mut output = std::io::BufWriter::new(std::fs::File::create(&temporary)?);
+        write(&mut output)?;
+        output.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        std::fs::rename(&temporary, path)
+    })();
+    if result.is_err()
+    {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    result
+}
+
+#[test]
+fn test_write_atomically()
+{
+    use std::io::Write;
+    let dir = std::env::temp_dir().join(format!("mandelbrot-atomic-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("out.txt");
+    write_atomically(&path, |output| output.write_all(b"first")).unwrap();
+    write_atomically(&path, |output| output.write_all(b"second")).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    //A write that fails part way leaves the old file alone, and no temporary file behind.
+    let err = write_atomically(&path, |output|
+    {
+        output.write_all(b"thi")?;
+        Err(std::io::Error::other("disk full"))
+    });
+    assert_eq!(err.unwrap_err().to_string(), "disk full");
+    assert_eq!(std::fs::read(&path).unwrap(), b"second");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    assert!(write_atomically(&dir.join("missing").join("out.txt"), |_| Ok(())).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The splitmix64 finalizer: scrambles a 64-bit number so that neighbouring inputs give unrelated outputs. Used to
+/// derive random streams and per-pixel noise from a seed without any state shared between threads.
+pub(crate) fn mix64(x: u64) -> u64
+{
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// The infinite loop the Mandelbrot set is defined by: square 'z', add 'c', repeat. escape_time above is the
+/// same loop with an iteration limit and an escape test bolted on.
+pub fn complex_square_add_loop(c:Complex<f64>)
+{
+    let mut z = Complex{re: 0.0, im : 0.0}; //makes a struct
+    loop //Creates an infinite loop. The body of the loop will execute indefinitely unless explicitly broken out of.
+    {
+        z = z * z + c;
+    }
+}
@@ -0,0 +1,66 @@
+//! Famous places in the Mandelbrot set, selectable by name (`--location seahorse`).
+//!
+//! Centers are kept as decimal strings rather than f64s so that points which reward deep zooms, such as the
+//! Misiurewicz point and the minibrot's nucleus, carry more digits than f64 can (see the deep module). Those
+//! two were computed with Newton's method at 80 digits and checked to have the stated orbit.
+
+/// A named view: where it is, how far in to start, and what to look for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location
+{
+    pub name: &'static str,
+    pub center: &'static str, //"RE,IM", as --center takes it.
+    pub zoom: f64,            //The default --zoom.
+    pub description: &'static str,
+}
+
+pub const LOCATIONS: &[Location] = &[
+    Location{name: "full", center: "-0.5,0", zoom: 1.3, description: "the whole set"},
+    Location{name: "seahorse", center: "-0.7453,0.1127", zoom: 200.0,
+             description: "Seahorse Valley, between the main cardioid and the period-2 bulb"},
+    Location{name: "elephant", center: "0.2925,0.016", zoom: 100.0,
+             description: "Elephant Valley, near the cusp of the main cardioid"},
+    Location{name: "misiurewicz",
+             center: "-0.7766105925997018565640395025529947493282,0.1346089616750281660567372702330578095412", zoom: 1000.0,
+             description: "a Misiurewicz point M(23,2): its orbit settles into a 2-cycle after 23 steps, and the \
+                           spirals around it repeat at every zoom"},
+    Location{name: "minibrot", center: "-1.7548776662466927600495088963585286918946,0", zoom: 120.0,
+             description: "the period-3 minibrot on the real axis (the \"airship\"), whose cusp is at -1.75"},
+    Location{name: "spiral", center: "-0.761574,-0.0847596", zoom: 800.0, description: "a double spiral in Seahorse Valley"},
+];
+
+/// The location called 'name'.
+pub fn find(name: &str) -> Option<&'static Location>
+{
+    LOCATIONS.iter().find(|location| location.name == name)
+}
+
+/// The names of all locations, for error messages.
+pub fn names() -> Vec<&'static str>
+{
+    LOCATIONS.iter().map(|location| location.name).collect()
+}
+
+#[test]
+fn test_locations()
+{
+    use crate::deep::parse_exact_complex;
+    use crate::{escape_value, Settings};
+    use num::Complex;
+    for location in LOCATIONS
+    {
+        assert!(parse_exact_complex(location.center).is_ok(), "{}", location.name);
+        assert!(location.zoom > 0.0);
+        assert_eq!(find(location.name), Some(location));
+    }
+    assert_eq!(find("nowhere"), None);
+    assert_eq!(names().len(), LOCATIONS.len());
+    //The minibrot's nucleus is in the set; a point just past its cusp is outside it.
+    let nucleus = Complex{re: -1.7548776662466927, im: 0.0};
+    assert_eq!(escape_value(nucleus, &Settings{limit: 10_000, ..Settings::default()}), None);
+    //The Misiurewicz point: z_25 comes back to z_23 (to f64 precision for the first steps).
+    let c = Complex{re: -0.7766105925997019, im: 0.1346089616750282};
+    let mut z = Complex{re: 0.0, im: 0.0};
+    let orbit: Vec<Complex<f64>> = (0..25).map(|_| { z = z * z + c; z }).collect();
+    assert!((orbit[24] - orbit[22]).norm() < 1e-6 && (orbit[21] - orbit[19]).norm() > 0.1);
+}
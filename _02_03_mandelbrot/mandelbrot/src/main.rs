@@ -1,136 +1,1112 @@
+use mandelbrot::antialias::{self, Antialiasing};
+use mandelbrot::boundary::{self, Outline, OutlineFormat};
+use mandelbrot::buddhabrot::{self, Buddhabrot};
+use mandelbrot::compare;
+use mandelbrot::config::{self, Config};
+use mandelbrot::deep::{self, parse_exact_complex, Algorithm, Decimal, DeepView};
+use mandelbrot::dirs::{self, Dirs};
+use mandelbrot::palette::Palette;
+use mandelbrot::png;
+use mandelbrot::palette_file;
+use mandelbrot::effects;
+use mandelbrot::histogram;
+use mandelbrot::locations;
+use mandelbrot::newton::{parse_polynomial, Newton};
+use mandelbrot::perturbation;
+use mandelbrot::preflight;
+use mandelbrot::progress::{Progress, ProgressFormat};
+use mandelbrot::parse::{parse_age, parse_bytes, parse_complex, parse_percent, parse_polar, parse_size, parse_tuple, ParseError};
+use mandelbrot::qr::{self, QrCode};
+use mandelbrot::share::{parse_share_link, ShareLink};
+use mandelbrot::texture::{self, TileMode};
+use mandelbrot::tune;
+use mandelbrot::units::NumberFormat;
+use mandelbrot::viewport::Viewport;
+use mandelbrot::{distance_map, escape_time, render_parallel, render_rows, write_atomically, write_image, Coloring, Fractal, Interior,
+                 Scheduler, Settings};
+use num::rational::BigRational;
+use num::traits::{One, ToPrimitive};
 use num::Complex;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::time::{Instant, SystemTime};
 
-/// The following function does this: Try to determine is 'c' is in the Mandelbrot set, using at most 'limit'
-/// iterations to decide.
-/// If 'C' is not a member, return some(i) where 'i' is the number of iterations it took for 'c' to leave the circle of radius 2 centered
-/// on the origin. If 'c' seems to be a member (more precisely, if we reached the iteration limit without being able to prove that 'c'
-/// is not a member), return None.
-/// Option is an enumerated type (enum), because its definition enumerates several variants that a value could be: it is either Some(v) where v is of type T
-/// or None. enum Option<T> {None, Some(T),}
-
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> //Option<usize>: Returns Some(iteration_count) if c escapes within iteration_count iterations.
-//Returns None if c remains bounded for the full limit iterations.
-///usize is a built-in integer type that represents a size or index in memory. It is an unsigned integer type whose size 
-/// depends on the architecture of the machine on which the program is running: On a 64-bit architecture, usize is 64 bits (8 bytes).
-/// On a 32-bit architecture, usize is 32 bits (4 bytes).
-{
-    let mut z = Complex{re: 0.0, im: 0.0};
-    for i in 0..limit
-    {
-        if z.norm_sqr() > 4.0 //norm_sqr is a method that calculated magnitude of the complex number.
-        {
-            return Some(i);
-        }
-        z = z * z + c;
-    }
-    None //If z is in the Mand.-set, None is returned.
-}
-
-/// The following lines handle the several CL arguments controlling the resolution of the image and parsing these arguments.
-/// Parse the string 's' as a coordinate pair.
-/// You can read the clause <T: FromStr> aloud as "For any type T that implements FromStr trait".
-/// This effectively lets us define an entire family of functions at once: parse_pair::<i32>, parse_pair::<f64>.
-/// <T>: Generics The angle brackets <...> declare that the function is generic over a type T.
-/// Generics allow the function to work with any type, as long as the type meets specific requirements (in this case, implementing the FromStr trait).
-/// : FromStr This is a trait bound, specifying that T must implement the FromStr trait. The FromStr trait provides functionality for parsing strings into values of a specific type.
-/// Option<(T, T)>: Return type: If parsing is successful, it returns Some((T, T)) (a tuple of two T values). Otherwise, it returns None.
-fn parse_pair<T: FromStr> (s: &str, separator: char) -> Option<(T, T)>
-{
-    match s.find(separator)//s.find(separator):The .find() method searches the string s for the first occurrence of the separator character.
-    //Returns an Option<usize>: Some(index): If the separator is found, index is the position of the separator in the string.
-    //None: If the separator is not found. T::from_str tries to parse two string slices in the tuple.
-    //The tuple (T::from_str(&s[..index]), T::from_str(&s[index + 1..])) is matched against the following patterns:
-    //(Ok(l), Ok(r)):Both parsing operations succeeded (Ok variant).
-    //The parsed values (l and r) are extracted and returned as a tuple inside Some.
-    //_ (Wildcard): Any other combination of results (e.g., one or both parsing attempts failed) matches this pattern. Returns None.
-    {
-        None => None, //If the result of s.find(separator) is None (separator not found), the match expression also returns None.
-        Some(index) => { //s[..index]: This is the substring from the start of s to (but not including) index
-            //s[index + 1..]: This is the substring from one character past index to the end of s
-            match(T::from_str(&s[..index]), T::from_str(&s[index + 1..]))
-            //T::from_str attempts to parse a string slice into a value of type T
+/// Parse one command-line argument, or print what is wrong with it (pointing at the bad part) and exit.
+fn parse_arg<T>(name: &str, value: &str, parser: fn(&str) -> Result<T, ParseError>) -> T
+{
+    match parser(value)
+    {
+        Ok(parsed) => parsed,
+        Err(err) =>
+        {
+            eprintln!("error parsing {}: {}", name, err);
+            eprintln!("{}", err.underline(value));
+            process::exit(1);
+        }
+    }
+}
+
+/// Print an error message and exit with a failure status.
+fn fail(message: &str) -> !
+{
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+/// The bytes in an image buffer of size 'bounds', or exit with an error if that doesn't fit in memory addresses.
+fn image_len(bounds: (usize, usize), channels: usize) -> usize
+{
+    bounds.0.checked_mul(bounds.1).and_then(|pixels| pixels.checked_mul(channels))
+        .unwrap_or_else(|| fail(&format!("a {}x{} image is too large to hold in memory", bounds.0, bounds.1)))
+}
+
+/// Print a usage message and exit: to stdout and successfully when it was asked for with --help, otherwise to
+/// stderr as an error.
+fn usage(help: bool, text: &str) -> !
+{
+    if help
+    {
+        println!("{}", text);
+        process::exit(0);
+    }
+    eprintln!("{}", text);
+    process::exit(1);
+}
+
+/// The subcommands and what each is for, as `mandelbrot help` lists them.
+const SUBCOMMANDS: &[(&str, &str)] = &[
+    ("render", "render an image; the default, so 'mandelbrot render ARGS' is 'mandelbrot ARGS'"),
+    ("julia", "render the Julia set for a constant: julia RE,IM [OPTIONS] FILE PIXELS"),
+    ("zoom", "render around a point: zoom RE,IM FACTOR [OPTIONS] FILE PIXELS"),
+    ("info", "describe the view a render command line would draw, without drawing it"),
+    ("bench", "time a render command line without writing the image (a standard view if given none)"),
+    ("tune", "suggest the cheapest --limit and --ssaa that still look right for a render command line"),
+    ("buddhabrot", "render the density of escaping orbits"),
+    ("trace-boundary", "write the outline of the set as SVG or GeoJSON, for cutters and design software"),
+    ("compare", "measure how close one image is to another, by PSNR and SSIM"),
+    ("config", "write a commented config file for --config"),
+    ("locations", "list the named places --location knows"),
+    ("cache", "delete old files from the cache directory"),
+    ("help", "show this list"),
+];
+
+/// `mandelbrot help`: the subcommands.
+fn help_main(program: &str) -> !
+{
+    let mut text = format!("Usage: {} [SUBCOMMAND] [OPTIONS] ARGUMENTS...\n\nSubcommands:\n", program);
+    let width = SUBCOMMANDS.iter().map(|(name, _)| name.len()).max().unwrap_or(0) + 2;
+    for (name, summary) in SUBCOMMANDS
+    {
+        text += &format!("  {:<width$}{}\n", name, summary, width = width);
+    }
+    text += "\nAny subcommand takes --machine-readable, for sizes, counts and speeds as plain numbers a script can read.\n";
+    text += &format!("Run '{} SUBCOMMAND --help' for the options of one.", program);
+    usage(true, &text)
+}
+
+/// The command line, with options taken out one at a time by the code that understands them.
+/// Options are `--name VALUE`, `--name=VALUE`, or `--name` switches (`--name=false` turns one off again, e.g. one a
+/// config file turned on); whatever is left over must be positional arguments. Negative numbers like -1.20,0.35 have a single dash, so they are never mistaken for options.
+struct Args
+{
+    rest: Vec<String>,
+}
+
+impl Args
+{
+    /// Remove `--name VALUE` or `--name=VALUE` and return VALUE. If the option is repeated, the last one wins.
+    fn value(&mut self, name: &str) -> Option<String>
+    {
+        let mut found = None;
+        let mut i = 0;
+        while i < self.rest.len()
+        {
+            if self.rest[i] == name
             {
-                (Ok(l), Ok(r)) => Some((l,r)),
-                _ => None
+                if i + 1 >= self.rest.len()
+                {
+                    fail(&format!("{} needs a value", name));
+                }
+                found = Some(self.rest.remove(i + 1));
+                self.rest.remove(i);
+            }
+            else if let Some(value) = self.rest[i].strip_prefix(name).and_then(|tail| tail.strip_prefix('='))
+            {
+                found = Some(value.to_string());
+                self.rest.remove(i);
+            }
+            else
+            {
+                i += 1;
             }
         }
+        found
+    }
+
+    /// Remove `--name VALUE` and parse VALUE with FromStr, exiting with a message if it does not parse.
+    fn parsed<T: FromStr>(&mut self, name: &str) -> Option<T>
+    {
+        self.value(name).map(|value| value.parse().unwrap_or_else(|_| fail(&format!("invalid value for {}: {}", name, value))))
+    }
+
+    /// Whether option 'name' is present, as `--name`, `--name VALUE` or `--name=VALUE`, leaving it in place.
+    fn has(&self, name: &str) -> bool
+    {
+        self.rest.iter().any(|arg| arg == name || arg.strip_prefix(name).is_some_and(|tail| tail.starts_with('=')))
+    }
+
+    /// The setting 'arg' gives switch 'name', if it is `--name`, `--name=true` or `--name=false`.
+    fn switch_value(arg: &str, name: &str) -> Option<bool>
+    {
+        match arg.strip_prefix(name)?
+        {
+            "" | "=true" => Some(true),
+            "=false" => Some(false),
+            tail => match tail.strip_prefix('=')
+            {
+                Some(value) => fail(&format!("invalid value for {}: {} (expected true or false)", name, value)),
+                None => None,
+            },
+        }
+    }
+
+    /// Remove switch 'name' and return its setting, if it was given at all. If it is repeated, the last one wins,
+    /// so `--quiet=false` on the command line overrides `quiet = true` in a config file.
+    fn toggle(&mut self, name: &str) -> Option<bool>
+    {
+        let mut found = None;
+        self.rest.retain(|arg| match Args::switch_value(arg, name)
+        {
+            Some(on) =>
+            {
+                found = Some(on);
+                false
+            }
+            None => true,
+        });
+        found
+    }
+
+    /// Remove switch 'name' and report whether it is on.
+    fn switch(&mut self, name: &str) -> bool
+    {
+        self.toggle(name).unwrap_or(false)
+    }
+
+    /// Whether switch 'name' is on, leaving it in place.
+    fn enabled(&self, name: &str) -> bool
+    {
+        self.rest.iter().rev().find_map(|arg| Args::switch_value(arg, name)).unwrap_or(false)
+    }
+
+    /// The positional arguments. Any option nobody asked for is an error rather than being silently ignored.
+    fn positional(self) -> Vec<String>
+    {
+        if let Some(unknown) = self.rest.iter().find(|arg| arg.starts_with("--"))
+        {
+            fail(&format!("unknown option {}", unknown));
+        }
+        self.rest
     }
 }
 
-//Test for parse_pair
-fn test_parse_pair()
+#[test]
+fn test_switch_overrides_config()
 {
-    assert_eq!(parse_pair::<i32>("",        ','), None);
-    assert_eq!(parse_pair::<i32>("10,",     ','), None);
-    assert_eq!(parse_pair::<i32>(",10",     ','), None);
-    assert_eq!(parse_pair::<i32>("10,20",   ','), Some((10, 20)));
-    assert_eq!(parse_pair::<i32>("10,20xy", ','), None);
-    assert_eq!(parse_pair::<f64>("0.5x",    'x'), None);
-    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+    //The file's options go first, as main() splices them in, so the command line's come last and win.
+    let config = Config::parse("quiet = true\nprint-link = true\nqr = false\n").unwrap();
+    let command_line = ["--quiet=false", "out.png", "--qr"];
+    let mut args = Args{rest: config.options.iter().map(String::as_str).chain(command_line).map(String::from).collect()};
+    assert!(!args.enabled("--quiet"));
+    assert_eq!(args.toggle("--quiet"), Some(false));
+    assert_eq!(args.toggle("--quiet"), None);
+    assert!(args.switch("--print-link") && args.switch("--qr") && !args.switch("--smooth"));
+    assert_eq!(args.positional(), ["out.png"]);
 }
 
-/// The following function uses the parse_pair function to parse a pair of floating point coordinates
-/// and return them as Complex<f64>. If parse_pair succeeds, destructure the tuple into re (real part) 
-/// and im (imaginary part). Construct a Complex<f64> number with Complex { re, im }. Return it wrapped in Some.
-fn parse_complex(s: &str) -> Option<Complex<f64>>
+/// The --threads option, defaulting to one thread per core.
+fn threads(args: &mut Args) -> usize
 {
-    match parse_pair(s, ',')
+    let threads = args.parsed::<usize>("--threads")
+        .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+    if threads == 0
     {
-        Some((re, im)) => Some(Complex{re, im}),
-        None => None
+        fail("--threads must be at least 1");
     }
+    threads
 }
-// Test for parse_complex
-#[test]
-fn test_parse_complex()
+
+/// What --machine-readable asks for: plain numbers for scripts, instead of scaled ones in the user's locale.
+fn numbers(machine_readable: bool) -> NumberFormat
 {
-    assert_eq!(parse_complex("1.25, -0.0625"), Some(Complex{re: 1.25, im: -0.0625}));
-    assert_eq!(parse_complex(",-0.0625"), None);
+    if machine_readable { NumberFormat::MACHINE } else { NumberFormat::from_env() }
 }
 
-/// The following functions maps pixels to complex numbers.
-/// The Mandelbrot set's mathematical definition works in the continuous space of the complex plane.
-/// Example: The point 𝑐 = −0.5 + 0.5𝑖 is a point in the complex plane, not a pixel. 
-/// Mapping Is the Bridge To compute whether a pixel should be part of the Mandelbrot set visualization:
-/// We first map it to its corresponding complex number using pixel_to_point.
-/// Then, we test the complex number using the Mandelbrot iterative algorithm.
-/// The Complex Plane: We define a rectangular region of the complex plane to visualize, such as:
-/// Upper-left corner:  (-2.0 + 1.0i) Lower-right corner: (1.0 - 1.0i)
-/// This region corresponds to the part of the Mandelbrot set we want to compute.
-/// Corresponding Coloring: Once each pixel is mapped to a complex number, the Mandelbrot algorithm determines:
-/// Whether the number belongs to the Mandelbrot set (color it black). How quickly it escapes the set (color it based on escape speed).
-fn pixel_to_point(bounds: (usize, usize), pixel: (usize, usize), 
-upper_left: Complex<f64>, lower_right: Complex<f64>) -> Complex<f64>
-//bounds: (usize, usize): The width and height of the image in pixels (e.g., bounds = (800, 600) for an 800×600 image).
-// pixel: (usize, usize): The pixel's 2D coordinates in the image (e.g., (400, 300))
+/// The progress reporter --progress asks for; by default a bar when stderr is a terminal and --quiet isn't given.
+fn progress(args: &mut Args, numbers: NumberFormat) -> Progress
 {
-    let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+    let quiet = args.switch("--quiet");
+    let format = match args.value("--progress")
+    {
+        Some(name) => name.parse::<ProgressFormat>().unwrap_or_else(|err| fail(&err)),
+        None if !quiet && io::stderr().is_terminal() => ProgressFormat::Bar,
+        None => ProgressFormat::None,
+    };
+    if quiet && format == ProgressFormat::Bar
+    {
+        fail("--quiet and --progress bar contradict each other");
+    }
+    Progress::new(format, numbers)
+}
 
-    Complex
+/// The config and cache directories, with --config-dir and --cache-dir in place of the platform defaults.
+fn dirs(args: &mut Args) -> Dirs
+{
+    let defaults = Dirs::from_env();
+    Dirs
     {
-        re: upper_left.re + pixel.0 as f64 * width  / bounds.0 as f64,
-        //pixel.0: The horizontal pixel index
-        //pixel.0 as f64 ensures the horizontal pixel index is treated as a floating-point number.
-        //The calculation scales pixel.0 (from 0 to bounds.0) to the corresponding range in the real axis of the complex plane (upper_left.re to lower_right.re).
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
-        //why subtraction here?
+        config: args.value("--config-dir").map(PathBuf::from).or(defaults.config),
+        cache: args.value("--cache-dir").map(PathBuf::from).or(defaults.cache),
     }
 }
 
-#[test]
-fn test_
+/// Look up --palette NAME among the built-in palettes, then the palettes directory of the config directory.
+fn named_palette(dirs: &Dirs, name: &str) -> Palette
+{
+    match dirs.find_palette(name)
+    {
+        Ok(Some(palette)) => palette,
+        Ok(None) => fail(&format!("unknown palette '{}' (built-in palettes: {}; others are looked up in {})", name,
+                                  Palette::builtin_names().join(", "),
+                                  dirs.palettes().map_or("no palettes directory".to_string(), |dir| dir.display().to_string()))),
+        Err(err) => fail(&err),
+    }
+}
 
-fn main() {
-    println!("Hello, world!");
+/// `mandelbrot cache clean --older-than AGE`: delete cached files that haven't been written for AGE.
+fn cache_main(program: &str, mut args: Args, numbers: NumberFormat)
+{
+    let help = args.switch("--help");
+    let dirs = dirs(&mut args);
+    let age = args.value("--older-than").map(|value| parse_arg("age (e.g. 30d)", &value, parse_age));
+    let args = args.positional();
+    let Some(age) = age.filter(|_| !help && args.len() == 1 && args[0] == "clean") else
+    {
+        usage(help, &format!("Usage: {0} cache clean --older-than AGE [--cache-dir DIR]\n\
+                              Example: {0} cache clean --older-than 30d   (AGE ends in s, m, h, d or w)", program));
+    };
+    let cache = dirs.cache.unwrap_or_else(|| fail("no cache directory could be found; pass --cache-dir"));
+    let cleaned = dirs::clean(&cache, age, SystemTime::now())
+        .unwrap_or_else(|err| fail(&format!("cleaning {}: {}", cache.display(), err)));
+    println!("removed {} from {} ({})", numbers.count(cleaned.files, "files"), cache.display(), numbers.bytes(cleaned.bytes));
+}
+
+/// `mandelbrot buddhabrot [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT`: render the orbit density of escaping points.
+fn buddhabrot_main(program: &str, mut args: Args, numbers: NumberFormat)
+{
+    let help = args.switch("--help");
+    let threads = threads(&mut args);
+    let progress = progress(&mut args, numbers);
+    let dirs = dirs(&mut args);
+    let [min_iter, max_iter] = args.value("--iterations")
+        .map(|value| parse_arg("iteration range (min,max)", &value, |s| parse_tuple::<usize, 2>(s, ',')))
+        .unwrap_or([20, 1000]);
+    if min_iter > max_iter || max_iter == 0
+    {
+        fail("--iterations needs min <= max, and max at least 1");
+    }
+    //--layers R,G,B makes a Nebulabrot: one layer per channel, each with its own iteration limit.
+    let limits = match args.value("--layers")
+    {
+        Some(value) =>
+        {
+            let layers = parse_arg("layer iteration limits (red,green,blue)", &value, |s| parse_tuple::<usize, 3>(s, ','));
+            if layers.iter().any(|&limit| limit < min_iter.max(1))
+            {
+                fail("--layers limits must be at least the minimum iteration count");
+            }
+            layers.to_vec()
+        }
+        None => vec![max_iter],
+    };
+    let seed = args.parsed::<u64>("--seed").unwrap_or(1);
+    let gamma = args.parsed::<f64>("--gamma").unwrap_or(2.0);
+    if !(gamma.is_finite() && gamma > 0.0)
+    {
+        fail("--gamma must be a positive number");
+    }
+    let palette = args.value("--palette").map(|name| named_palette(&dirs, &name));
+    if palette.is_some() && limits.len() > 1
+    {
+        fail("--palette colors a single layer; --layers already maps its layers to red, green and blue");
+    }
+    let samples = args.parsed::<u64>("--samples");
+    let args = args.positional();
+    if help || args.len() != 4
+    {
+        usage(help, &format!("Usage: {0} buddhabrot [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT\n\
+                              Example: {0} buddhabrot buddha.png 600x800 -1.5,-1.5 1.5,2\n\
+                              Options: --samples N (default 50 per pixel), --iterations MIN,MAX (default 20,1000), --seed N,\n\
+                              \x20        --layers RED_MAX,GREEN_MAX,BLUE_MAX (Nebulabrot, e.g. 500,5000,50000),\n\
+                              \x20        --gamma G (default 2), --palette NAME, --threads N, --progress none|bar|json, --quiet", program));
+    }
+
+    let bounds = parse_arg("image dimensions", &args[1], parse_size).pixels();
+    let upper_left = parse_arg("upper left corner point", &args[2], parse_complex);
+    let lower_right = parse_arg("lower right corner point", &args[3], parse_complex);
+    let layers = limits.len();
+    let color = if palette.is_some() || layers == 3 { png::ColorType::Rgb } else { png::ColorType::Gray };
+    preflight::check(&[(PathBuf::from(&args[0]), png::max_encoded_len(bounds, color))], numbers).unwrap_or_else(|err| fail(&err));
+    //Counts are u32s, one per pixel and layer.
+    let pixels = image_len(bounds, layers * 4) / (layers * 4);
+    let settings = Buddhabrot{samples: samples.unwrap_or(50u64.saturating_mul(pixels as u64)), min_iter, limits, seed};
+    progress.start("sample", "buddhabrot", settings.samples);
+    let histogram = buddhabrot::accumulate(bounds, upper_left, lower_right, &settings, threads, &progress);
+    progress.finish();
+    let pixels = buddhabrot::colorize(&buddhabrot::tone_map(&histogram, layers, gamma), palette.as_ref());
+    progress.start("write", "png", 1);
+    if let Err(err) = write_image(&args[0], &pixels, bounds, color)
+    {
+        fail(&format!("writing PNG file {}: {}", args[0], err));
+    }
+    progress.advance(1);
+    progress.finish();
+}
+
+/// `mandelbrot trace-boundary [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT`: write the outline of the set in the view,
+/// as an SVG in pixel coordinates or GeoJSON in complex-plane ones.
+fn trace_boundary_main(program: &str, mut args: Args)
+{
+    let help = args.switch("--help");
+    let format = args.value("--format").map(|name| name.parse::<OutlineFormat>().unwrap_or_else(|err| fail(&err)));
+    let tolerance = args.parsed::<f64>("--tolerance").unwrap_or(0.5);
+    let limit = args.parsed::<usize>("--limit").unwrap_or(Settings::default().limit);
+    let smooth = args.switch("--smooth");
+    let args = args.positional();
+    if help || args.len() != 4
+    {
+        usage(help, &format!("Usage: {0} trace-boundary [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT\n\
+                              Example: {0} trace-boundary outline.svg 1000x750 -2.2,1.2 1,-1.2 --tolerance 1\n\
+                              Options: --format svg|geojson (default: from FILE's extension, else svg),\n\
+                              \x20        --tolerance PX (how far simplified lines may stray; default 0.5),\n\
+                              \x20        --limit N (default 255), --smooth (SVG curves instead of straight lines)", program));
+    }
+    if !(tolerance.is_finite() && tolerance >= 0.0)
+    {
+        fail("--tolerance must be a number of pixels, 0 or more");
+    }
+    let path = Path::new(&args[0]);
+    let format = format.or_else(|| OutlineFormat::from_path(path)).unwrap_or(OutlineFormat::Svg);
+    if smooth && format != OutlineFormat::Svg
+    {
+        fail("--smooth only applies to SVG output");
+    }
+    let bounds = parse_arg("image dimensions", &args[1], parse_size).pixels();
+    let upper_left = parse_arg("upper left corner point", &args[2], parse_complex);
+    let lower_right = parse_arg("lower right corner point", &args[3], parse_complex);
+    let view = Viewport::from_corners(bounds, upper_left, lower_right);
+    let outlines: Vec<Outline> = boundary::trace(&view, |c| escape_time(c, limit).is_none()).into_iter()
+        .map(|outline| Outline{points: boundary::simplify(&outline.points, tolerance), ..outline})
+        .collect();
+    let text = match format
+    {
+        OutlineFormat::Svg => boundary::to_svg(&outlines, bounds, smooth),
+        OutlineFormat::GeoJson => boundary::to_geojson(&outlines, &view),
+    };
+    if let Err(err) = write_atomically(path, |output| output.write_all(text.as_bytes()))
+    {
+        fail(&format!("writing {}: {}", path.display(), err));
+    }
+}
+
+/// Read and decode a PNG file, or fail.
+fn read_png(path: &str) -> png::Image
+{
+    let file = fs::read(path).unwrap_or_else(|err| fail(&format!("reading {}: {}", path, err)));
+    png::decode(&file).unwrap_or_else(|err| fail(&format!("reading {}: {}", path, err)))
+}
+
+/// `mandelbrot compare [--min-psnr DB] [--min-ssim X] REFERENCE IMAGE`: print how close IMAGE is to REFERENCE,
+/// exiting with status 1 if it falls short of a threshold so scripts can stop on it.
+fn compare_main(program: &str, mut args: Args)
+{
+    let help = args.switch("--help");
+    let min_psnr = args.parsed::<f64>("--min-psnr");
+    let min_ssim = args.parsed::<f64>("--min-ssim");
+    let args = args.positional();
+    if help || args.len() != 2
+    {
+        usage(help, &format!("Usage: {0} compare [--min-psnr DB] [--min-ssim X] REFERENCE IMAGE\n\
+                              Example: {0} compare reference.png fast.png --min-ssim 0.98   (exits with 1 below it)", program));
+    }
+    let (reference, bounds, color) = read_png(&args[0]);
+    let (image, image_bounds, image_color) = read_png(&args[1]);
+    if (image_bounds, image_color) != (bounds, color)
+    {
+        fail(&format!("{} is {}x{} {:?} but {} is {}x{} {:?}", args[0], bounds.0, bounds.1, color,
+                      args[1], image_bounds.0, image_bounds.1, image_color));
+    }
+    let psnr = compare::psnr(&reference, &image);
+    let ssim = compare::ssim(&reference, &image, bounds, color.channels());
+    println!("psnr: {:.2} dB", psnr);
+    println!("ssim: {:.5}", ssim);
+    let mut failed = false;
+    if let Some(min) = min_psnr.filter(|&min| psnr < min)
+    {
+        eprintln!("fail: PSNR {:.2} dB is below {} dB", psnr, min);
+        failed = true;
+    }
+    if let Some(min) = min_ssim.filter(|&min| ssim < min)
+    {
+        eprintln!("fail: SSIM {:.5} is below {}", ssim, min);
+        failed = true;
+    }
+    if failed
+    {
+        process::exit(1);
+    }
+}
+
+/// `mandelbrot config init [FILE]`: write a commented config file to start from (mandelbrot.toml by default).
+fn config_main(program: &str, mut args: Args)
+{
+    let help = args.switch("--help");
+    let force = args.switch("--force");
+    let args = args.positional();
+    if help || args.is_empty() || args[0] != "init" || args.len() > 2
+    {
+        usage(help, &format!("Usage: {0} config init [FILE] [--force]   (FILE defaults to mandelbrot.toml)\n\
+                              Then: {0} --config mandelbrot.toml   (options on the command line override the file)", program));
+    }
+    let path = args.get(1).map_or("mandelbrot.toml", String::as_str);
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if force { options.create(true).truncate(true) } else { options.create_new(true) };
+    if let Err(err) = options.open(path).and_then(|mut file| file.write_all(config::TEMPLATE.as_bytes()))
+    {
+        let hint = if err.kind() == io::ErrorKind::AlreadyExists { " (--force overwrites it)" } else { "" };
+        fail(&format!("writing {}: {}{}", path, err, hint));
+    }
+    println!("wrote {}", path);
+}
+
+/// `mandelbrot locations [--thumbnails DIR]`: list the named locations, optionally writing DIR/NAME.png previews.
+fn locations_main(program: &str, mut args: Args)
+{
+    let help = args.switch("--help");
+    let thumbnails = args.value("--thumbnails");
+    let threads = threads(&mut args);
+    if help || !args.positional().is_empty()
+    {
+        usage(help, &format!("Usage: {} locations [--thumbnails DIR] [--threads N]", program));
+    }
+    let width = locations::LOCATIONS.iter().map(|location| location.name.len()).max().unwrap_or(0);
+    for location in locations::LOCATIONS
+    {
+        println!("{:width$}  --center {} --zoom {}", location.name, location.center, location.zoom, width = width);
+        println!("{:width$}  {}", "", location.description, width = width);
+    }
+    let Some(dir) = thumbnails else { return };
+    if let Err(err) = fs::create_dir_all(&dir)
+    {
+        fail(&format!("creating {}: {}", dir, err));
+    }
+    let bounds = (160, 120);
+    let settings = Settings{limit: 1000, coloring: Coloring::Smooth, palette: Palette::builtin("classic"), ..Settings::default()};
+    for location in locations::LOCATIONS
+    {
+        let center = parse_arg("location center", location.center, parse_complex);
+        let (upper_left, lower_right) = Viewport::from_zoom(bounds, center, location.zoom).corners();
+        let mut pixels = vec![0; bounds.0 * bounds.1 * 3];
+        render_rows(&mut pixels, bounds, upper_left, lower_right, &settings, threads, &Progress::silent());
+        let path = Path::new(&dir).join(format!("{}.png", location.name));
+        if let Err(err) = write_image(&path.to_string_lossy(), &pixels, bounds, settings.color_type())
+        {
+            fail(&format!("writing PNG file {}: {}", path.display(), err));
+        }
+    }
+}
+
+/// The rest of `mandelbrot tune`: probe the view, try the candidate limits and grids, and recommend the fastest
+/// pair that reaches 'target' SSIM against the reference, exiting with status 1 if none does.
+fn tune_main(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+             threads: usize, target: f64)
+{
+    let (probe_upper_left, probe_lower_right, size) = tune::probe(bounds, upper_left, lower_right, settings, threads);
+    let (limits, reference) = tune::candidate_limits(settings.limit);
+    println!("probe: {}x{} pixels from {} to {}, against a reference with --limit {} --ssaa {}", size.0, size.1,
+             probe_upper_left, probe_lower_right, reference, tune::SSAA[tune::SSAA.len() - 1]);
+    let trials = tune::sweep(size, probe_upper_left, probe_lower_right, settings, threads, &limits, reference);
+    println!("{:>8}  {:>4}  {:>7}  {:>9}  {:>8}", "limit", "ssaa", "SSIM", "PSNR", "time");
+    for trial in &trials
+    {
+        println!("{:>8}  {:>4}  {:>7.4}  {:>6.2} dB  {:>6.3} s", trial.limit, trial.ssaa, trial.ssim, trial.psnr, trial.seconds);
+    }
+    //The probe's time scaled up by area; rough, since the probe is the busiest part of the image.
+    let full = |trial: &tune::Trial| trial.seconds * (bounds.0 * bounds.1) as f64 / (size.0 * size.1) as f64;
+    match tune::recommend(&trials, target)
+    {
+        Some(best) => println!("recommended: --limit {} --ssaa {} (the fastest with SSIM >= {}, at {:.4}; roughly {:.1} s for the whole image)",
+                               best.limit, best.ssaa, target, best.ssim, full(best)),
+        None =>
+        {
+            let closest = trials.iter().max_by(|a, b| a.ssim.total_cmp(&b.ssim)).unwrap();
+            eprintln!("no candidate reached SSIM {}; the closest was --limit {} --ssaa {} at {:.4}", target, closest.limit,
+                      closest.ssaa, closest.ssim);
+            process::exit(1);
+        }
+    }
 }
 
+/// What the render command line is for, by subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode
+{
+    Render, //render, julia and zoom.
+    Info,
+    Bench,
+    Tune,
+}
 
-fn complex_square_add_loop(c:Complex<f64>)
+/// Take the 'count' arguments that come straight after the subcommand, which 'form' describes.
+fn leading_arguments(program: &str, args: &mut Args, form: &str, count: usize) -> Vec<String>
 {
-    let mut z = Complex{re: 0.0, im : 0.0}; //makes a struct 
-    loop //Creates an infinite loop. The body of the loop will execute indefinitely unless explicitly broken out of.
+    if args.rest.len() < count || args.rest[..count].iter().any(|arg| arg.starts_with("--"))
+    {
+        usage(false, &format!("Usage: {} {} [OPTIONS] FILE PIXELS   (see '{} render --help' for the options)", program, form, program));
+    }
+    args.rest.drain(..count).collect()
+}
+
+fn main() {
+    let mut argv = env::args();
+    let program = argv.next().unwrap_or_else(|| "mandelbrot".to_string());
+    let mut args = Args{rest: argv.collect()};
+    //Taken out before the subcommand is picked, so it may come first; a config file can set it as well (see below).
+    let machine_readable = args.toggle("--machine-readable");
+    let subcommand = match args.rest.first().map(String::as_str)
+    {
+        Some("--help" | "-h") => help_main(&program),
+        Some(name) if SUBCOMMANDS.iter().any(|&(known, _)| known == name) => args.rest.remove(0),
+        _ => "render".to_string(),
+    };
+    //The subcommands that render take the same command line, parsed below, and differ only at the end.
+    let mode = match subcommand.as_str()
+    {
+        "buddhabrot" => return buddhabrot_main(&program, args, numbers(machine_readable == Some(true))),
+        "cache" => return cache_main(&program, args, numbers(machine_readable == Some(true))),
+        "compare" => return compare_main(&program, args),
+        "config" => return config_main(&program, args),
+        "locations" => return locations_main(&program, args),
+        "trace-boundary" => return trace_boundary_main(&program, args),
+        "help" => help_main(&program),
+        "info" => Mode::Info,
+        "bench" => Mode::Bench,
+        "tune" => Mode::Tune,
+        _ => Mode::Render,
+    };
+    let help = args.switch("--help");
+    //julia and zoom are render with some options given as leading arguments.
+    let leading = match (subcommand.as_str(), help)
+    {
+        ("julia", false) => leading_arguments(&program, &mut args, "julia RE,IM", 1),
+        ("zoom", false) => leading_arguments(&program, &mut args, "zoom RE,IM FACTOR", 2),
+        _ => Vec::new(),
+    };
+
+    //The file's options go first so that the same options on the command line override them.
+    let mut config = args.value("--config")
+        .map(|path| Config::load(Path::new(&path)).unwrap_or_else(|err| fail(&err)))
+        .unwrap_or_default();
+    if mode == Mode::Bench
+    {
+        //Without a view of its own, bench times a standard one.
+        for (place, default) in config.positional.iter_mut().zip(["bench.png", "1000x750", "-2.2,1.2", "1,-1.2"])
+        {
+            place.get_or_insert_with(|| default.to_string());
+        }
+    }
+    args.rest.splice(0..0, config.options.iter().cloned());
+    match (subcommand.as_str(), leading.as_slice())
+    {
+        ("julia", [constant]) =>
+        {
+            //The whole Julia set, unless told where to look.
+            if !(args.has("--center") || args.has("--location") || args.has("--link"))
+            {
+                args.rest.extend(["--center".to_string(), "0,0".to_string()]);
+            }
+            args.rest.extend(["--julia".to_string(), constant.clone()]);
+        }
+        ("zoom", [center, zoom]) => args.rest.extend(["--center".to_string(), center.clone(), "--zoom".to_string(), zoom.clone()]),
+        _ => {}
+    }
+    //The command line's setting wins over the file's.
+    let file_machine_readable = args.toggle("--machine-readable");
+    let numbers = numbers(machine_readable.or(file_machine_readable) == Some(true));
+    let threads = threads(&mut args);
+    let quiet = args.enabled("--quiet");
+    let progress = progress(&mut args, numbers);
+    let dirs = dirs(&mut args);
+    let scheduler = args.value("--scheduler")
+        .map(|name| name.parse::<Scheduler>().unwrap_or_else(|err| fail(&err)))
+        .unwrap_or(Scheduler::Dynamic);
+    let link = args.value("--link").map(|value| parse_arg("share link", &value, parse_share_link));
+    //--center RE,IM --zoom FACTOR places the image instead of a corner pair; the corners follow from its size.
+    //--location NAME is a named center with its own default zoom.
+    let location = args.value("--location").map(|name| locations::find(&name).unwrap_or_else(||
+        fail(&format!("unknown location '{}' (known locations: {}; see '{} locations')", name, locations::names().join(", "), program))));
+    let center = match (args.value("--center"), location)
+    {
+        (Some(_), Some(_)) => fail("--center and --location cannot be used together"),
+        (center, location) => center.or(location.map(|location| location.center.to_string())),
+    };
+    let zoom = args.value("--zoom").or(location.map(|location| location.zoom.to_string()));
+    if center.is_some() && link.is_some()
+    {
+        fail("--center and --location cannot be used with --link");
+    }
+    if zoom.is_some() && center.is_none()
+    {
+        fail("--zoom needs --center or --location");
+    }
+    let pad = args.value("--pad").map(|value| parse_arg("padding (e.g. 10%)", &value, parse_percent));
+    if pad.is_some_and(|fraction| fraction <= -0.5)
+    {
+        fail("--pad must be more than -50%");
+    }
+    let limit = args.parsed::<usize>("--limit").or(link.as_ref().map(|link| link.max_iter)).unwrap_or(255);
+    if limit == 0
+    {
+        fail("--limit must be at least 1");
+    }
+    let palette_name = args.value("--palette");
+    let palette = match args.value("--palette-file")
+    {
+        Some(_) if palette_name.is_some() => fail("--palette and --palette-file cannot be used together"),
+        Some(path) => Some(palette_file::load(Path::new(&path)).unwrap_or_else(|err| fail(&err))),
+        None => palette_name.or(link.as_ref().and_then(|link| link.palette.clone())).map(|name| named_palette(&dirs, &name)),
+    };
+    let julia = match (args.value("--julia"), args.value("--julia-polar"))
+    {
+        (Some(_), Some(_)) => fail("--julia and --julia-polar cannot be used together"),
+        (Some(value), None) => Some(parse_arg("Julia constant", &value, parse_complex)),
+        (None, Some(value)) => Some(parse_arg("Julia constant (radius,degrees)", &value, parse_polar)),
+        (None, None) => link.as_ref().and_then(|link| link.julia),
+    };
+    let interior = args.value("--interior")
+        .map(|name| name.parse::<Interior>().unwrap_or_else(|err| fail(&err)))
+        .unwrap_or(Interior::Black);
+    let power = args.parsed::<f64>("--power").or(link.as_ref().map(|link| link.power)).unwrap_or(2.0);
+    if !(power.is_finite() && power > 1.0)
+    {
+        fail("--power must be greater than 1");
+    }
+    let fractal = args.value("--fractal")
+        .map(|name| name.parse::<Fractal>().unwrap_or_else(|err| fail(&err)))
+        .or(link.as_ref().map(|link| link.fractal))
+        .unwrap_or(Fractal::Mandelbrot);
+    let newton = args.value("--newton")
+        .map(|value| parse_arg("polynomial", &value, parse_polynomial))
+        .or(link.as_ref().and_then(|link| link.newton.clone()));
+    let coloring = match (args.value("--coloring"), args.switch("--smooth"))
+    {
+        (Some(_), true) => fail("--smooth is short for --coloring smooth; use one or the other"),
+        (Some(name), false) => name.parse::<Coloring>().unwrap_or_else(|err| fail(&err)),
+        (None, smooth) => if smooth { Coloring::Smooth } else { Coloring::EscapeTime },
+    };
+    let simd = match args.value("--simd").as_deref()
+    {
+        None | Some("on") => true,
+        Some("off") => false,
+        Some(other) => fail(&format!("unknown --simd setting '{}' (expected on or off)", other)),
+    };
+    let line_art = args.parsed::<f64>("--line-art");
+    if line_art.is_some_and(|width| !(width.is_finite() && width > 0.0))
+    {
+        fail("--line-art stroke width must be a positive number of pixels");
+    }
+    let ssaa = args.parsed::<usize>("--ssaa").unwrap_or(1);
+    if !(1..=16).contains(&ssaa)
+    {
+        fail("--ssaa must be between 1 and 16 (samples per pixel along each side)");
+    }
+    if ssaa > 1 && coloring == Coloring::Histogram
+    {
+        fail("--ssaa cannot be combined with --coloring histogram");
+    }
+    //Adaptive anti-aliasing refines edges after the render, with --aa-threshold or its default.
+    let antialiasing = args.value("--aa")
+        .map(|name| name.parse::<Antialiasing>().unwrap_or_else(|err| fail(&err)))
+        .unwrap_or(Antialiasing::None);
+    let aa_threshold = args.parsed::<f64>("--aa-threshold");
+    if aa_threshold.is_some_and(|threshold| !(threshold.is_finite() && threshold >= 0.0))
+    {
+        fail("--aa-threshold must be a variance of brightness of at least 0, e.g. 0.002");
+    }
+    let adaptive = match antialiasing
+    {
+        Antialiasing::Adaptive if ssaa > 1 => fail("--aa adaptive and --ssaa are alternatives; use one or the other"),
+        Antialiasing::Adaptive if coloring == Coloring::Histogram => fail("--aa adaptive cannot be combined with --coloring histogram"),
+        Antialiasing::Adaptive => Some(aa_threshold.unwrap_or(antialias::DEFAULT_THRESHOLD)),
+        Antialiasing::None if aa_threshold.is_some() => fail("--aa-threshold needs --aa adaptive"),
+        Antialiasing::None => None,
+    };
+    let settings = Settings{limit, coloring, palette, julia, interior, power, fractal, line_art,
+                            newton: newton.clone().map(Newton::new), early_out: !args.switch("--no-early-out"),
+                            simd, ssaa};
+    let target_ssim = args.parsed::<f64>("--target-ssim");
+    if target_ssim.is_some() && mode != Mode::Tune
+    {
+        fail("--target-ssim is an option of 'tune'");
+    }
+    let repeat = args.parsed::<usize>("--repeat");
+    if repeat.is_some() && mode != Mode::Bench
+    {
+        fail("--repeat is an option of 'bench'");
+    }
+    if repeat == Some(0)
+    {
+        fail("--repeat must be at least 1");
+    }
+    let max_memory = args.value("--max-memory").map(|value| parse_arg("memory size (e.g. 512M)", &value, parse_bytes));
+    let print_link = args.switch("--print-link");
+    let stamp_qr = args.switch("--qr");
+    let dof = args.value("--dof").map(|value| parse_arg("depth of field (focus,radius)", &value, |s| parse_tuple::<f64, 2>(s, ',')));
+    if dof.is_some_and(|[focus, radius]| !(focus >= 0.0 && radius >= 0.0 && radius.is_finite()))
+    {
+        fail("--dof focus distance and blur radius must not be negative");
+    }
+    if newton.is_some() && (julia.is_some() || power != 2.0 || fractal != Fractal::Mandelbrot || interior != Interior::Black
+                            || matches!(coloring, Coloring::Distance | Coloring::Histogram) || settings.line_art.is_some() || dof.is_some())
+    {
+        fail("--newton cannot be combined with --julia, --power, --fractal, --interior, --coloring de|histogram, --line-art or --dof");
+    }
+    //Views too deep for f64 are rendered in fixed point (see the deep module); --force-precision picks the
+    //algorithm instead of leaving it to the zoom, and --precision the number of bits.
+    let precision = args.parsed::<usize>("--precision");
+    if precision.is_some_and(|bits| !(32..=65536).contains(&bits))
+    {
+        fail("--precision must be between 32 and 65536 bits");
+    }
+    let forced = args.value("--force-precision").map(|name| name.parse::<Algorithm>().unwrap_or_else(|err| fail(&err)));
+    if forced == Some(Algorithm::F64) && precision.is_some()
+    {
+        fail("--precision sets the bits of the fixed-point algorithms; it can't be used with --force-precision f64");
+    }
+    let supports_deep = settings.supports_deep() && dof.is_none() && adaptive.is_none();
+    if (precision.is_some() || forced.is_some_and(|algorithm| algorithm != Algorithm::F64)) && !supports_deep
+    {
+        fail("--precision and --force-precision exact|perturbation|series cannot be combined with --newton, --power, \
+              --fractal, --interior, --coloring de|histogram, --line-art, --ssaa, --aa adaptive or --dof");
+    }
+    let bloom = args.value("--bloom").map(|value|
+    {
+        let [threshold, radius, intensity] = parse_arg("bloom (threshold,radius,intensity)", &value, |s| parse_tuple::<f64, 3>(s, ','));
+        if !((0.0..=1.0).contains(&threshold) && radius >= 0.0 && radius.is_finite() && intensity >= 0.0 && intensity.is_finite())
+        {
+            fail("--bloom threshold must be between 0 and 1, and radius and intensity must not be negative");
+        }
+        effects::Bloom{threshold, radius, intensity}
+    });
+    let vignette = args.parsed::<f64>("--vignette");
+    if vignette.is_some_and(|strength| !(0.0..=1.0).contains(&strength))
+    {
+        fail("--vignette strength must be between 0 and 1");
+    }
+    let vignette_shape = args.value("--vignette-shape")
+        .map(|name| name.parse::<effects::VignetteShape>().unwrap_or_else(|err| fail(&err)))
+        .unwrap_or(effects::VignetteShape::Ellipse);
+    let grain = args.parsed::<f64>("--grain");
+    if grain.is_some_and(|amount| !(amount.is_finite() && amount >= 0.0))
+    {
+        fail("--grain amount must not be negative");
+    }
+    let grain_seed = args.parsed::<u64>("--grain-seed").unwrap_or(0);
+    let tile = args.value("--tile").map(|name| name.parse::<TileMode>().unwrap_or_else(|err| fail(&err)));
+    let tile_preview = args.value("--tile-preview");
+    if tile.is_none() && tile_preview.is_some()
+    {
+        fail("--tile-preview needs --tile");
+    }
+    if tile.is_some() && stamp_qr
+    {
+        fail("--qr would break the seams of a --tile texture");
+    }
+    if tile.is_some() && vignette.is_some()
+    {
+        fail("--vignette would break the seams of a --tile texture");
+    }
+    let expected = if link.is_some() || center.is_some() { 2 } else { 4 };
+    let args = config.fill(args.positional(), expected);
+
+    if help || args.len() != expected
+    {
+        usage(help, &format!("\
+Usage: {0} [render] [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT
+       {0} [render] [OPTIONS] --center RE,IM [--zoom FACTOR] FILE PIXELS
+       {0} [render] [OPTIONS] --location NAME [--zoom FACTOR] FILE PIXELS   (see {0} locations)
+       {0} [render] [OPTIONS] --link mandel://RE/IM/ZOOM/MAXITER FILE PIXELS
+       {0} julia RE,IM [OPTIONS] FILE PIXELS   (the whole Julia set unless --center, --location or --link says where)
+       {0} zoom RE,IM FACTOR [OPTIONS] FILE PIXELS
+       {0} info|bench|tune RENDER_ARGUMENTS...   (bench without arguments times a standard view)
+       {0} help   (all subcommands)
+Example: {0} mandel.png 1000x750 -1.20,0.35 -1,0.20
+         {0} mandel.png 1000x750 --center -0.745,0.113 --zoom 400   (zoom 1 is 4 units wide)
+Options: --threads N, --scheduler dynamic|bands, --limit N, --coloring escape|smooth|de|histogram, --smooth,
+         --config FILE (options and arguments from a TOML file; see {0} config init),
+         --progress none|bar|json (default: a bar on a terminal; JSON lines on stderr give phase, done/total, ETA, backend),
+         --quiet (no progress bar or precision note),
+         --pad PERCENT (grow the view on every side, e.g. 10%),
+         --palette NAME, --palette-file PATH, --config-dir DIR (palettes/NAME.csv etc.), --cache-dir DIR,
+         --julia RE,IM, --julia-polar RADIUS,DEGREES, --interior black|angle|multiplier,
+         --max-memory BYTES (e.g. 512M; histogram coloring renders in strips to stay within it),
+         --fractal mandelbrot|tricorn, --power D, --line-art STROKE_PIXELS, --tile mirror|blend, --tile-preview FILE,
+         --newton POLYNOMIAL (e.g. 'z^3 - 1'), --no-early-out (iterate the main cardioid and bulb too),
+         --simd on|off (several pixels per step where the formula allows; default on),
+         --ssaa N (average N x N samples per pixel, e.g. 3 for smooth edges; N² times the work),
+         --aa none|adaptive, --aa-threshold X (resample only pixels whose neighborhood varies by more than X; default 0.002),
+         --force-precision f64|exact|perturbation|series (default: f64, or series for zooms too deep for it),
+         --precision BITS (fixed-point fraction bits; default: enough for the zoom),
+         --dof FOCUS_PIXELS,MAX_BLUR_PIXELS, --bloom THRESHOLD,RADIUS_PIXELS,INTENSITY,
+         --vignette STRENGTH, --vignette-shape circle|ellipse, --grain AMOUNT, --grain-seed N, --print-link, --qr
+bench:   --repeat N (default 3)
+tune:    --target-ssim X (default 0.99)", program));
+    }
+
+    let bounds = parse_arg("image dimensions", &args[1], parse_size).pixels();
+    let mut outputs = vec![(PathBuf::from(&args[0]), png::max_encoded_len(bounds, settings.color_type()))];
+    if let Some(preview) = &tile_preview
     {
-        z = z * z + c;
+        //The preview holds four copies of the image, so it has to fit in memory as well.
+        let doubled = (bounds.0.saturating_mul(2), bounds.1.saturating_mul(2));
+        image_len(doubled, settings.color_type().channels());
+        outputs.push((PathBuf::from(preview), png::max_encoded_len(doubled, settings.color_type())));
     }
-}
\ No newline at end of file
+    if mode == Mode::Render
+    {
+        preflight::check(&outputs, numbers).unwrap_or_else(|err| fail(&err));
+    }
+    //The view in f64, and its corners with every digit they were given. A share link's center and zoom are f64s
+    //to begin with, but even then the corners must be worked out exactly: at deep zooms f64 rounds both corners
+    //to the center.
+    let exact = |x: f64| BigRational::from_float(x).unwrap_or_default();
+    let (view, (exact_upper_left, exact_lower_right)) = match (&link, &center)
+    {
+        (Some(link), _) => (link.viewport(bounds),
+                            deep::corners_from_zoom(bounds, &Complex{re: exact(link.center.re), im: exact(link.center.im)}, &exact(link.zoom))),
+        (None, Some(center)) =>
+        {
+            let [Decimal(exact_zoom)] = zoom.as_ref()
+                .map_or([Decimal(BigRational::one())], |zoom| parse_arg("zoom", zoom, |s| parse_tuple(s, ',')));
+            let zoom = exact_zoom.to_f64().unwrap_or(f64::NAN);
+            if !(zoom.is_finite() && zoom > 0.0)
+            {
+                fail("--zoom must be a positive number");
+            }
+            let point = parse_arg("center point", center, parse_complex);
+            (Viewport::from_zoom(bounds, point, zoom),
+             deep::corners_from_zoom(bounds, &parse_arg("center point", center, parse_exact_complex), &exact_zoom))
+        }
+        (None, None) =>
+        {
+            let upper_left = parse_arg("upper left corner point", &args[2], parse_complex);
+            let lower_right = parse_arg("lower right corner point", &args[3], parse_complex);
+            (Viewport::from_corners(bounds, upper_left, lower_right),
+             (parse_arg("upper left corner point", &args[2], parse_exact_complex),
+              parse_arg("lower right corner point", &args[3], parse_exact_complex)))
+        }
+    };
+    let (view, (exact_upper_left, exact_lower_right)) = match pad
+    {
+        Some(fraction) => (view.padded(fraction), deep::pad_corners(&exact_upper_left, &exact_lower_right, fraction)),
+        None => (view, (exact_upper_left, exact_lower_right)),
+    };
+    let (upper_left, lower_right) = view.corners();
+
+    let channels = settings.color_type().channels();
+    let render_bounds = tile.map_or(bounds, |mode| texture::render_bounds(mode, bounds));
+
+    //Histogram coloring keeps one strip of escape values at a time, as many rows as --max-memory leaves room for.
+    let histogram = coloring == Coloring::Histogram && settings.newton.is_none() && settings.line_art.is_none();
+    let strip_rows = match max_memory
+    {
+        _ if histogram => histogram::strip_rows(render_bounds, channels, limit, max_memory, numbers).unwrap_or_else(|err| fail(&err)),
+        Some(budget) if image_len(render_bounds, channels) as u64 > budget =>
+        {
+            fail(&format!("the image takes {}, more than --max-memory {}",
+                          numbers.bytes(image_len(render_bounds, channels) as u64), numbers.bytes(budget)));
+        }
+        _ => 0,
+    };
+
+    let required = deep::required_bits(&exact_upper_left, &exact_lower_right, render_bounds);
+    let algorithm = match forced
+    {
+        Some(algorithm) => algorithm,
+        None if precision.is_some() => Algorithm::Series,
+        None if Algorithm::choose(required) != Algorithm::F64 && !supports_deep =>
+        {
+            eprintln!("warning: the view needs about {} bits, but only f64 can render these settings", required);
+            Algorithm::F64
+        }
+        None => Algorithm::choose(required),
+    };
+    let bits = precision.unwrap_or_else(|| Algorithm::working_bits(required));
+    match algorithm
+    {
+        _ if quiet => {}
+        Algorithm::F64 => eprintln!("precision: f64 (the view needs about {} bits)", required),
+        _ => eprintln!("precision: {}, {} bits (the view needs about {})", algorithm.name(), bits, required),
+    }
+    let palette = settings.palette.as_ref().map(|palette| palette.name.clone());
+    let share = ShareLink{julia, power, fractal, newton, ..ShareLink::from_viewport(&view, limit, palette)}.to_string();
+    match mode
+    {
+        Mode::Tune =>
+        {
+            if algorithm != Algorithm::F64 || !tune::supports(&settings) || adaptive.is_some()
+            {
+                fail("tune only handles views f64 can render, and not --newton, --line-art, --aa or --coloring de|histogram");
+            }
+            return tune_main(bounds, upper_left, lower_right, &settings, threads, target_ssim.unwrap_or(0.99));
+        }
+        Mode::Info =>
+        {
+            println!("center:      {}", view.center);
+            println!("zoom:        {:e} ({} x {} units)", 4.0 / view.width, view.width, view.height);
+            println!("corners:     {} to {}", upper_left, lower_right);
+            println!("pixel:       {:e} units", view.width / bounds.0 as f64);
+            println!("image:       {}x{} ({}), {}", bounds.0, bounds.1, numbers.count(image_len(bounds, 1) as u64, "px"), args[0]);
+            println!("output:      at most {}", numbers.bytes(png::max_encoded_len(bounds, settings.color_type())));
+            println!("link:        {}", share);
+            return;
+        }
+        Mode::Render | Mode::Bench => {}
+    }
+    if algorithm != Algorithm::F64 && link.is_none() && (print_link || stamp_qr)
+    {
+        eprintln!("warning: share links hold f64 coordinates, so this one only approximates the view");
+    }
+
+    let mut pixels = vec![0; image_len(render_bounds, channels)];
+    let deep_view = DeepView::from_corners(render_bounds, &exact_upper_left, &exact_lower_right, bits);
+    let backend = match algorithm
+    {
+        Algorithm::F64 if settings.uses_lanes() => "f64-simd",
+        _ => algorithm.name(),
+    };
+    let render = |pixels: &mut [u8]|
+    {
+        if histogram
+        {
+            progress.start_rows("histogram", backend, render_bounds.1, render_bounds.0);
+            let counts = histogram::measure(render_bounds, upper_left, lower_right, &settings, threads, strip_rows, &progress);
+            progress.finish();
+            progress.start_rows("render", backend, render_bounds.1, render_bounds.0);
+            histogram::render(pixels, render_bounds, (upper_left, lower_right), &settings, &counts, threads, &progress);
+            return progress.finish();
+        }
+        progress.start_rows("render", backend, render_bounds.1, render_bounds.0);
+        match (algorithm, scheduler)
+        {
+            (Algorithm::F64, Scheduler::Bands) => render_parallel(pixels, render_bounds, upper_left, lower_right, &settings, threads, &progress),
+            (Algorithm::F64, Scheduler::Dynamic) => render_rows(pixels, render_bounds, upper_left, lower_right, &settings, threads, &progress),
+            (Algorithm::Exact, _) => deep::render_rows(pixels, &deep_view, &settings, threads, &progress),
+            (Algorithm::Perturbation, _) => perturbation::render_rows(pixels, &deep_view, &settings, threads, false, &progress),
+            (Algorithm::Series, _) => perturbation::render_rows(pixels, &deep_view, &settings, threads, true, &progress),
+        }
+        progress.finish();
+        if let Some(threshold) = adaptive
+        {
+            progress.start_rows("antialias", "f64", render_bounds.1, render_bounds.0);
+            antialias::refine(pixels, render_bounds, (upper_left, lower_right), &settings, threshold, threads, &progress);
+            progress.finish();
+        }
+    };
+    if mode == Mode::Bench
+    {
+        //Only the render is timed; effects and encoding don't depend on the view.
+        let repeat = repeat.unwrap_or(3);
+        let mut best = f64::INFINITY;
+        for run in 1..=repeat
+        {
+            let start = Instant::now();
+            render(&mut pixels);
+            let seconds = start.elapsed().as_secs_f64();
+            println!("run {}/{}: {:.3} s", run, repeat, seconds);
+            best = best.min(seconds);
+        }
+        let rate = (render_bounds.0 * render_bounds.1) as f64 / best;
+        println!("best: {:.3} s, {}/s (backend {}, threads: {})", best, numbers.count(rate as u64, "px"), backend, threads);
+        return;
+    }
+    render(&mut pixels);
+
+    if let Some([focus, radius]) = dof
+    {
+        progress.start_rows("depth", "f64", render_bounds.1, render_bounds.0);
+        let distances = distance_map(render_bounds, upper_left, lower_right, &settings, threads, &progress);
+        progress.finish();
+        pixels = effects::depth_of_field(&pixels, render_bounds, channels, &distances, focus, radius);
+    }
+
+    if let Some(bloom) = &bloom
+    {
+        pixels = effects::bloom(&pixels, render_bounds, channels, bloom);
+    }
+
+    if let Some(mode) = tile
+    {
+        pixels = texture::make_tileable(mode, &pixels, bounds, channels);
+    }
+
+    if let Some(strength) = vignette
+    {
+        effects::vignette(&mut pixels, bounds, channels, strength, vignette_shape);
+    }
+    if let Some(amount) = grain
+    {
+        effects::grain(&mut pixels, channels, amount, grain_seed);
+    }
+
+    if let Some(preview) = &tile_preview
+    {
+        let tiled = texture::tile_preview(&pixels, bounds, channels);
+        if let Err(err) = write_image(preview, &tiled, (bounds.0 * 2, bounds.1 * 2), settings.color_type())
+        {
+            fail(&format!("writing PNG file {}: {}", preview, err));
+        }
+    }
+
+    if stamp_qr
+    {
+        //Bottom-right corner, about a fifth of the shorter side, and never below 2 pixels per module.
+        let code = QrCode::encode(share.as_bytes()).unwrap_or_else(|| fail("share link too long for a QR code"));
+        let modules = code.size + 8;
+        let scale = (bounds.0.min(bounds.1) / 5 / modules).max(2);
+        let extent = modules * scale;
+        if extent > bounds.0 || extent > bounds.1
+        {
+            fail(&format!("image too small for the QR code (needs at least {}x{})", extent, extent));
+        }
+        qr::stamp(&mut pixels, bounds, channels, &code, bounds.0 - extent, bounds.1 - extent, scale);
+    }
+
+    progress.start("write", "png", 1);
+    if let Err(err) = write_image(&args[0], &pixels, bounds, settings.color_type())
+    {
+        fail(&format!("writing PNG file {}: {}", args[0], err));
+    }
+    progress.advance(1);
+    progress.finish();
+
+    if print_link
+    {
+        println!("{}", share);
+    }
+}
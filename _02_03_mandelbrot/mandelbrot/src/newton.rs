@@ -0,0 +1,418 @@
+//! Newton fractals: run Newton's method z = z - p(z) / p'(z) from every point of the plane and color the point
+//! by the root of p it ends up at, darker the longer it takes to get there.
+//!
+//! Polynomials come from the command line in the usual notation:
+//!
+//! ```text
+//! poly  := ws* sign? term (ws* sign ws* term)* ws*
+//! sign  := '+' | '-'
+//! term  := coef ('*'? 'z' ('^' uint)?)? | 'z' ('^' uint)?
+//! coef  := number 'i'? | 'i'
+//! ```
+//!
+//! e.g. "z^3 - 1", "z^5 + 0.3z^2 - 1", "z^4 - 2i*z + 1". Terms of the same degree add up, so a complex
+//! coefficient is written as two terms ("z^3 + 2z + 0.5i*z"). The roots are found once, up front, with the
+//! Durand-Kerner method; each pixel's iteration then only has to say which of them it converged to.
+
+use crate::parse::{trim_span, ParseError, ParseErrorKind};
+use crate::OrbitOutcome;
+use num::Complex;
+use std::fmt;
+
+/// Highest degree the parser accepts. Newton fractals get noisy long before this.
+pub const MAX_DEGREE: usize = 64;
+
+/// A polynomial with complex coefficients; coefficients[k] multiplies z^k, and the last one is never zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial
+{
+    pub coefficients: Vec<Complex<f64>>,
+}
+
+impl Polynomial
+{
+    /// Build a polynomial from coefficients in increasing degree, dropping zero leading coefficients.
+    pub fn new(mut coefficients: Vec<Complex<f64>>) -> Polynomial
+    {
+        while coefficients.last().is_some_and(|c| c.norm_sqr() == 0.0)
+        {
+            coefficients.pop();
+        }
+        Polynomial{coefficients}
+    }
+
+    /// The degree, with the zero polynomial counted as degree 0.
+    pub fn degree(&self) -> usize
+    {
+        self.coefficients.len().saturating_sub(1)
+    }
+
+    /// p(z), by Horner's rule.
+    pub fn eval(&self, z: Complex<f64>) -> Complex<f64>
+    {
+        self.coefficients.iter().rev().fold(Complex{re: 0.0, im: 0.0}, |acc, &c| acc * z + c)
+    }
+
+    /// p'(z) as a polynomial.
+    pub fn derivative(&self) -> Polynomial
+    {
+        Polynomial::new(self.coefficients.iter().enumerate().skip(1).map(|(k, &c)| c * k as f64).collect())
+    }
+
+    /// All roots, with repeated roots listed once. Durand-Kerner refines every root at the same time, each one
+    /// pushed away from the current guesses for the others, starting from points spread around a circle.
+    pub fn roots(&self) -> Vec<Complex<f64>>
+    {
+        let n = self.degree();
+        if n == 0
+        {
+            return Vec::new();
+        }
+        let lead = self.coefficients[n];
+        let monic = |z: Complex<f64>| self.eval(z) / lead;
+        //Start on a circle that holds every root (Cauchy's bound), off the axes so symmetric polynomials don't stall.
+        let radius = 1.0 + self.coefficients[..n].iter().map(|c| (c / lead).norm()).fold(0.0, f64::max);
+        let mut roots: Vec<Complex<f64>> = (0..n)
+            .map(|k| Complex::from_polar(radius, 0.4 + std::f64::consts::TAU * k as f64 / n as f64))
+            .collect();
+        for _ in 0..500
+        {
+            let mut largest_step: f64 = 0.0;
+            for k in 0..n
+            {
+                let mut denominator = Complex{re: 1.0, im: 0.0};
+                for j in 0..n
+                {
+                    if j != k
+                    {
+                        denominator *= roots[k] - roots[j];
+                    }
+                }
+                let step = monic(roots[k]) / denominator;
+                if step.is_finite()
+                {
+                    roots[k] -= step;
+                    largest_step = largest_step.max(step.norm());
+                }
+            }
+            if largest_step < 1e-14 * radius
+            {
+                break;
+            }
+        }
+        //Repeated roots only converge to a few digits and come out as a small cluster; keep one of each.
+        let mut distinct: Vec<Complex<f64>> = Vec::new();
+        for root in roots
+        {
+            if distinct.iter().all(|r| (r - root).norm() > 1e-4 * radius)
+            {
+                distinct.push(root);
+            }
+        }
+        distinct
+    }
+}
+
+/// Write a coefficient and its power of z: the sign, the magnitude unless it is 1, and an 'i' for imaginary parts
+/// (with a '*' before the z, so "2i*z" doesn't read as a variable called iz).
+fn write_term(f: &mut fmt::Formatter, value: f64, imaginary: bool, power: usize, first: bool) -> fmt::Result
+{
+    if value < 0.0
+    {
+        write!(f, "-")?;
+    }
+    else if !first
+    {
+        write!(f, "+")?;
+    }
+    let magnitude = value.abs();
+    if magnitude != 1.0 || (power == 0 && !imaginary)
+    {
+        write!(f, "{}", magnitude)?;
+    }
+    if imaginary
+    {
+        write!(f, "i")?;
+    }
+    match power
+    {
+        0 => Ok(()),
+        _ => write!(f, "{}z{}", if imaginary { "*" } else { "" }, if power == 1 { String::new() } else { format!("^{}", power) }),
+    }
+}
+
+/// The compact form, e.g. "z^3-1" or "z^4-2i*z+1", which parse_polynomial reads back exactly.
+impl fmt::Display for Polynomial
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let mut first = true;
+        for (power, c) in self.coefficients.iter().enumerate().rev()
+        {
+            for (value, imaginary) in [(c.re, false), (c.im, true)]
+            {
+                if value != 0.0
+                {
+                    write_term(f, value, imaginary, power, first)?;
+                    first = false;
+                }
+            }
+        }
+        if first
+        {
+            write!(f, "0")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a polynomial in z, as described in the module documentation. It must have degree at least 1.
+pub fn parse_polynomial(s: &str) -> Result<Polynomial, ParseError>
+{
+    let bytes = s.as_bytes();
+    let mut coefficients = Vec::new();
+    let skip_space = |mut i: usize| { while i < bytes.len() && (bytes[i] == b' ' || bytes[i] == b'\t') { i += 1; } i };
+    let mut i = skip_space(0);
+    if i == bytes.len()
+    {
+        return Err(ParseError::new(ParseErrorKind::Empty, 0..s.len()));
+    }
+    let mut first = true;
+    while i < bytes.len()
+    {
+        //Sign: optional on the first term, required between terms.
+        let mut sign = 1.0;
+        if bytes[i] == b'+' || bytes[i] == b'-'
+        {
+            sign = if bytes[i] == b'-' { -1.0 } else { 1.0 };
+            i = skip_space(i + 1);
+        }
+        else if !first
+        {
+            return Err(ParseError::new(ParseErrorKind::Expected("'+' or '-' between terms"), i..i + 1));
+        }
+        first = false;
+        let term_start = i;
+
+        //Coefficient: a number, an 'i', or both.
+        let number_start = i;
+        while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.'
+                                  || ((bytes[i] == b'e' || bytes[i] == b'E') && i > number_start
+                                      && bytes.get(i + 1).is_some_and(|b| b.is_ascii_digit() || *b == b'-' || *b == b'+')))
+        {
+            i += if bytes[i] == b'e' || bytes[i] == b'E' { 2 } else { 1 };
+        }
+        let mut coefficient = if i > number_start
+        {
+            let span = number_start..i;
+            s[span.clone()].parse::<f64>().map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, span))?
+        }
+        else
+        {
+            1.0
+        };
+        let mut imaginary = false;
+        if bytes.get(i) == Some(&b'i')
+        {
+            imaginary = true;
+            i += 1;
+        }
+        let has_coefficient = i > number_start;
+        i = skip_space(i);
+        if has_coefficient && bytes.get(i) == Some(&b'*')
+        {
+            i = skip_space(i + 1);
+            if bytes.get(i) != Some(&b'z')
+            {
+                return Err(ParseError::new(ParseErrorKind::Expected("'z' after '*'"), i..(i + 1).min(s.len())));
+            }
+        }
+
+        //Power of z.
+        let mut power = 0;
+        if bytes.get(i) == Some(&b'z')
+        {
+            power = 1;
+            i = skip_space(i + 1);
+            if bytes.get(i) == Some(&b'^')
+            {
+                let digits = skip_space(i + 1);
+                let mut end = digits;
+                while end < bytes.len() && bytes[end].is_ascii_digit()
+                {
+                    end += 1;
+                }
+                if end == digits
+                {
+                    return Err(ParseError::new(ParseErrorKind::Expected("a whole-number exponent"), digits..(digits + 1).min(s.len())));
+                }
+                power = s[digits..end].parse().unwrap_or(usize::MAX);
+                if power > MAX_DEGREE
+                {
+                    return Err(ParseError::new(ParseErrorKind::OutOfRange, digits..end));
+                }
+                i = skip_space(end);
+            }
+        }
+        else if !has_coefficient
+        {
+            return Err(ParseError::new(ParseErrorKind::Expected("a term like 3z^2"), trim_span(s, term_start..(term_start + 1).min(s.len()))));
+        }
+
+        coefficient *= sign;
+        if coefficients.len() <= power
+        {
+            coefficients.resize(power + 1, Complex{re: 0.0, im: 0.0});
+        }
+        coefficients[power] += if imaginary { Complex{re: 0.0, im: coefficient} } else { Complex{re: coefficient, im: 0.0} };
+    }
+    let polynomial = Polynomial::new(coefficients);
+    if polynomial.degree() == 0
+    {
+        return Err(ParseError::new(ParseErrorKind::Expected("a polynomial in z of degree at least 1"), 0..s.len()));
+    }
+    Ok(polynomial)
+}
+
+/// A polynomial prepared for rendering: its derivative and its roots, worked out once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Newton
+{
+    pub polynomial: Polynomial,
+    pub derivative: Polynomial,
+    pub roots: Vec<Complex<f64>>,
+}
+
+impl Newton
+{
+    pub fn new(polynomial: Polynomial) -> Newton
+    {
+        Newton{derivative: polynomial.derivative(), roots: polynomial.roots(), polynomial}
+    }
+
+    /// Run Newton's method from z0 for at most 'limit' steps. Once a step is shorter than 1e-9 the orbit has
+    /// converged, and the outcome names the nearest root. Starting points that land where p'(z) = 0 (or that
+    /// wander off without settling) come back as MaxIter.
+    pub fn orbit(&self, z0: Complex<f64>, limit: usize) -> OrbitOutcome
+    {
+        let mut z = z0;
+        for i in 0..limit
+        {
+            let step = self.polynomial.eval(z) / self.derivative.eval(z);
+            if !step.is_finite()
+            {
+                return OrbitOutcome::MaxIter;
+            }
+            z -= step;
+            if step.norm_sqr() < 1e-18
+            {
+                let nearest = (0..self.roots.len())
+                    .min_by(|&a, &b| (self.roots[a] - z).norm_sqr().total_cmp(&(self.roots[b] - z).norm_sqr()));
+                return nearest.map_or(OrbitOutcome::MaxIter, |k| OrbitOutcome::ConvergedToRoot(k, i + 1));
+            }
+        }
+        OrbitOutcome::MaxIter
+    }
+}
+
+/// A fully saturated color at 'hue' (0..1 around the color wheel), for telling roots apart without a palette.
+fn hue_color(hue: f64) -> [u8; 3]
+{
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as usize
+    {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [(r * 255.0f64).round() as u8, (g * 255.0f64).round() as u8, (b * 255.0f64).round() as u8]
+}
+
+/// The base color of root 'k' of 'count': evenly spaced along the palette if there is one (skipping its dark
+/// start), otherwise evenly spaced hues.
+pub fn root_color(k: usize, count: usize, palette: Option<&crate::palette::Palette>) -> [u8; 3]
+{
+    match palette
+    {
+        Some(palette) => palette.color((k + 1) as f64 / count as f64),
+        None => hue_color(k as f64 / count as f64),
+    }
+}
+
+#[test]
+fn test_parse_polynomial()
+{
+    let one = Complex{re: 1.0, im: 0.0};
+    let zero = Complex{re: 0.0, im: 0.0};
+    assert_eq!(parse_polynomial("z^3 - 1"), Ok(Polynomial{coefficients: vec![-one, zero, zero, one]}));
+    assert_eq!(parse_polynomial(" -2.5z^2+z "), Ok(Polynomial{coefficients: vec![zero, one, -2.5 * one]}));
+    assert_eq!(parse_polynomial("z^2 + 3*z + 0.5i*z + z"),
+               Ok(Polynomial{coefficients: vec![zero, Complex{re: 4.0, im: 0.5}, one]}));
+    assert_eq!(parse_polynomial("1e-3z - i"), Ok(Polynomial{coefficients: vec![Complex{re: 0.0, im: -1.0}, 1e-3 * one]}));
+
+    assert_eq!(parse_polynomial("").unwrap_err().kind, ParseErrorKind::Empty);
+    assert_eq!(parse_polynomial("z^3 1").unwrap_err(), ParseError::new(ParseErrorKind::Expected("'+' or '-' between terms"), 4..5));
+    assert_eq!(parse_polynomial("z^ - 1").unwrap_err().kind, ParseErrorKind::Expected("a whole-number exponent"));
+    assert_eq!(parse_polynomial("z^99").unwrap_err(), ParseError::new(ParseErrorKind::OutOfRange, 2..4));
+    assert_eq!(parse_polynomial("z^2 + x").unwrap_err(), ParseError::new(ParseErrorKind::Expected("a term like 3z^2"), 6..7));
+    assert_eq!(parse_polynomial("z - z").unwrap_err().kind, ParseErrorKind::Expected("a polynomial in z of degree at least 1"));
+}
+
+#[test]
+fn test_polynomial_display_round_trip()
+{
+    for text in ["z^3-1", "z^4-2i*z+1", "-2.5z^2+z", "z^5+0.3z^2-1+0.25i", "i*z^2+z"]
+    {
+        let polynomial = parse_polynomial(text).unwrap();
+        assert_eq!(polynomial.to_string(), text);
+        assert_eq!(parse_polynomial(&polynomial.to_string()), Ok(polynomial));
+    }
+}
+
+#[test]
+fn test_roots()
+{
+    let roots = parse_polynomial("z^3 - 1").unwrap().roots();
+    assert_eq!(roots.len(), 3);
+    for k in 0..3
+    {
+        let expected = Complex::from_polar(1.0, std::f64::consts::TAU * k as f64 / 3.0);
+        assert!(roots.iter().any(|r| (r - expected).norm() < 1e-12), "missing cube root of unity {}", expected);
+    }
+    //(z - 1)^2 (z + 2) has a double root, which is listed once.
+    let roots = parse_polynomial("z^3 - 3z + 2").unwrap().roots();
+    assert_eq!(roots.len(), 2);
+    assert!(roots.iter().any(|r| (r - 1.0).norm() < 1e-4) && roots.iter().any(|r| (r + 2.0).norm() < 1e-9));
+}
+
+#[test]
+fn test_newton_orbit()
+{
+    let newton = Newton::new(parse_polynomial("z^3 - 1").unwrap());
+    let root_near = |z: Complex<f64>| match newton.orbit(z, 50)
+    {
+        OrbitOutcome::ConvergedToRoot(k, _) => Some(newton.roots[k]),
+        _ => None,
+    };
+    assert!((root_near(Complex{re: 2.0, im: 0.1}).unwrap() - 1.0).norm() < 1e-12);
+    assert!((root_near(Complex{re: -1.0, im: 2.0}).unwrap().arg() - std::f64::consts::TAU / 3.0).abs() < 1e-12);
+    assert_eq!(newton.orbit(Complex{re: 0.0, im: 0.0}, 50), OrbitOutcome::MaxIter); //p'(0) = 0.
+    //Starting right next to a root converges at once; far away takes longer.
+    let steps = |z| match newton.orbit(z, 50) { OrbitOutcome::ConvergedToRoot(_, i) => i, _ => 0 };
+    assert!(steps(Complex{re: 1.0 + 1e-12, im: 0.0}) <= 2);
+    assert!(steps(Complex{re: 10.0, im: 10.0}) > 5);
+}
+
+#[test]
+fn test_root_color()
+{
+    assert_eq!(root_color(0, 3, None), [255, 0, 0]);
+    assert_eq!(root_color(1, 3, None), [0, 255, 0]);
+    assert_eq!(root_color(2, 3, None), [0, 0, 255]);
+    let gray = crate::palette::Palette::builtin("gray").unwrap();
+    assert_eq!(root_color(1, 2, Some(&gray)), [255, 255, 255]);
+}
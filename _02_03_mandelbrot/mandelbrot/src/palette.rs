@@ -0,0 +1,88 @@
+//! Color gradients for turning escape values into RGB.
+//!
+//! A Palette is a list of color stops at positions 0.0..=1.0; colors in between are linear interpolations of
+//! the neighbouring stops. Escape values are mapped onto the gradient logarithmically, so the colors stay spread
+//! out whether the interesting detail is at 20 iterations or at 20,000.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette
+{
+    pub name: String,
+    pub stops: Vec<Stop>, //Sorted by position.
+}
+
+/// A color stop: position along the gradient and RGB color.
+pub type Stop = (f64, [u8; 3]);
+
+/// The built-in gradients, as (name, stops).
+const BUILTIN: &[(&str, &[Stop])] = &[
+    ("classic", &[(0.0, [0, 7, 100]), (0.16, [32, 107, 203]), (0.42, [237, 255, 255]), (0.6425, [255, 170, 0]),
+                  (0.8575, [0, 2, 0]), (1.0, [0, 7, 100])]),
+    ("fire", &[(0.0, [0, 0, 0]), (0.3, [128, 0, 0]), (0.55, [230, 60, 0]), (0.8, [255, 190, 30]), (1.0, [255, 255, 220])]),
+    ("ocean", &[(0.0, [0, 8, 30]), (0.35, [0, 60, 130]), (0.7, [0, 170, 200]), (1.0, [230, 255, 255])]),
+    ("viridis", &[(0.0, [68, 1, 84]), (0.25, [59, 82, 139]), (0.5, [33, 145, 140]), (0.75, [94, 201, 98]), (1.0, [253, 231, 37])]),
+    ("magma", &[(0.0, [0, 0, 4]), (0.25, [81, 18, 124]), (0.5, [183, 55, 121]), (0.75, [252, 137, 97]), (1.0, [252, 253, 191])]),
+    ("gray", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]),
+];
+
+impl Palette
+{
+    /// Look up a built-in palette by name.
+    pub fn builtin(name: &str) -> Option<Palette>
+    {
+        BUILTIN.iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(name, stops)| Palette{name: name.to_string(), stops: stops.to_vec()})
+    }
+
+    /// The names builtin() accepts, for help and error messages.
+    pub fn builtin_names() -> Vec<&'static str>
+    {
+        BUILTIN.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// The color at position 't' along the gradient; 't' is clamped to 0..=1.
+    pub fn color(&self, t: f64) -> [u8; 3]
+    {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let next = self.stops.partition_point(|&(position, _)| position < t).clamp(1, self.stops.len() - 1);
+        let ((p0, c0), (p1, c1)) = (self.stops[next - 1], self.stops[next]);
+        let f = if p1 > p0 { ((t - p0) / (p1 - p0)).clamp(0.0, 1.0) } else { 0.0 };
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+        [mix(c0[0], c1[0]), mix(c0[1], c1[1]), mix(c0[2], c1[2])]
+    }
+
+    /// The color for an escape value (a possibly smooth iteration count) out of 'limit' iterations.
+    pub fn color_for(&self, value: f64, limit: usize) -> [u8; 3]
+    {
+        self.color((1.0 + value.max(0.0)).ln() / (1.0 + limit as f64).ln())
+    }
+}
+
+#[test]
+fn test_builtin_palettes()
+{
+    for name in Palette::builtin_names()
+    {
+        let palette = Palette::builtin(name).unwrap();
+        assert!(palette.stops.len() >= 2);
+        assert!(palette.stops.windows(2).all(|pair| pair[0].0 <= pair[1].0), "{} stops out of order", name);
+        assert_eq!(palette.stops[0].0, 0.0);
+        assert_eq!(palette.stops[palette.stops.len() - 1].0, 1.0);
+    }
+    assert_eq!(Palette::builtin("nope"), None);
+}
+
+#[test]
+fn test_palette_color_interpolates()
+{
+    let gray = Palette::builtin("gray").unwrap();
+    assert_eq!(gray.color(0.0), [0, 0, 0]);
+    assert_eq!(gray.color(0.5), [128, 128, 128]);
+    assert_eq!(gray.color(1.0), [255, 255, 255]);
+    assert_eq!(gray.color(7.0), [255, 255, 255]);
+    let fire = Palette::builtin("fire").unwrap();
+    assert_eq!(fire.color(0.3), [128, 0, 0]);
+    assert_eq!(fire.color_for(0.0, 1000), [0, 0, 0]);
+    assert_eq!(fire.color_for(1000.0, 1000), [255, 255, 220]);
+}
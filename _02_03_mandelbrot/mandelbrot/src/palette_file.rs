@@ -0,0 +1,487 @@
+//! Loading user gradients from files, so artists are not limited to the built-in palettes.
+//!
+//! Four formats are understood, picked by file extension:
+//!
+//! * CSV (.csv, .txt): one stop per line, either `R,G,B` (stops evenly spaced) or `POSITION,R,G,B`, with
+//!   channels 0..=255 and positions 0..=1. Blank lines, `#` comments, and a header line are skipped.
+//! * JSON (.json): an array of stops, or an object with a "stops" array. A stop is `[R,G,B]`, `[POSITION,R,G,B]`,
+//!   a `"#rrggbb"` string, or `{"position": P, "color": C}` where C is either color form.
+//! * GIMP gradients (.ggr): each segment contributes its left, middle, and right colors. Curved blends and HSV
+//!   coloring are approximated by RGB interpolation between those three points.
+//! * UltraFractal gradients (.ugr): the first gradient in the file. Its 0..400 index range maps onto 0..1 and,
+//!   as in UltraFractal, the gradient wraps around from the last stop back to the first.
+//!
+//! The palette is named after the file stem, so `--print-link` links can refer to it by a path-safe name.
+
+use crate::palette::{Palette, Stop};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format
+{
+    Csv,
+    Json,
+    Ggr,
+    Ugr,
+}
+
+impl Format
+{
+    /// The format for a file name's extension, or None if it is not one we know.
+    pub fn from_path(path: &Path) -> Option<Format>
+    {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str()
+        {
+            "csv" | "txt" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ggr" => Some(Format::Ggr),
+            "ugr" => Some(Format::Ugr),
+            _ => None,
+        }
+    }
+}
+
+/// Read and parse a palette file. Errors are messages ready to show the user, prefixed with the path.
+pub fn load(path: &Path) -> Result<Palette, String>
+{
+    let format = Format::from_path(path)
+        .ok_or_else(|| format!("{}: unknown palette format (expected .csv, .txt, .json, .ggr, or .ugr)", path.display()))?;
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("custom");
+    parse(&text, format, name).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Parse the text of a palette file.
+pub fn parse(text: &str, format: Format, name: &str) -> Result<Palette, String>
+{
+    let stops = match format
+    {
+        Format::Csv => parse_csv(text)?,
+        Format::Json => parse_json(text)?,
+        Format::Ggr => parse_ggr(text)?,
+        Format::Ugr => parse_ugr(text)?,
+    };
+    finish(name, stops)
+}
+
+/// Check the positions, sort the stops, and make sure there are at least two of them.
+fn finish(name: &str, mut stops: Vec<Stop>) -> Result<Palette, String>
+{
+    if let Some((position, _)) = stops.iter().find(|(position, _)| !(0.0..=1.0).contains(position))
+    {
+        return Err(format!("stop position {} is outside 0..1", position));
+    }
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0)); //Stable, so stops sharing a position keep their order (a hard edge).
+    match stops.len()
+    {
+        0 => return Err("no color stops".to_string()),
+        1 => stops.push((1.0, stops[0].1)),
+        _ => {}
+    }
+    Ok(Palette{name: name.to_string(), stops})
+}
+
+fn channel(value: f64) -> Result<u8, String>
+{
+    if (0.0..=255.0).contains(&value) { Ok(value.round() as u8) } else { Err(format!("color channel {} is outside 0..255", value)) }
+}
+
+fn hex_color(s: &str) -> Result<[u8; 3], String>
+{
+    let digits = s.strip_prefix('#').filter(|digits| digits.len() == 6 && digits.is_ascii())
+        .ok_or_else(|| format!("expected a #rrggbb color, found \"{}\"", s))?;
+    let byte = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("invalid hex color \"{}\"", s));
+    Ok([byte(0)?, byte(2)?, byte(4)?])
+}
+
+/// Turn a list of optional positions and colors into stops, spacing the stops evenly when no positions were given.
+fn place(entries: Vec<(Option<f64>, [u8; 3])>) -> Result<Vec<Stop>, String>
+{
+    let positioned = entries.iter().filter(|(position, _)| position.is_some()).count();
+    if positioned != 0 && positioned != entries.len()
+    {
+        return Err("either every stop needs a position or none may have one".to_string());
+    }
+    let last = entries.len().saturating_sub(1).max(1) as f64;
+    Ok(entries.into_iter().enumerate().map(|(i, (position, color))| (position.unwrap_or(i as f64 / last), color)).collect())
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Stop>, String>
+{
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate()
+    {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+        {
+            continue;
+        }
+        let fields: Result<Vec<f64>, _> = line.split(',').map(|field| field.trim().parse::<f64>()).collect();
+        let fields = match fields
+        {
+            Ok(fields) => fields,
+            //A header such as "position,r,g,b" may come before the first stop.
+            Err(_) if entries.is_empty() && line.chars().any(|c| c.is_ascii_alphabetic()) => continue,
+            Err(_) => return Err(format!("line {}: expected numbers separated by commas", index + 1)),
+        };
+        let in_line = |err: String| format!("line {}: {}", index + 1, err);
+        let (position, rgb) = match fields.len()
+        {
+            3 => (None, &fields[..]),
+            4 => (Some(fields[0]), &fields[1..]),
+            n => return Err(format!("line {}: expected R,G,B or POSITION,R,G,B, found {} fields", index + 1, n)),
+        };
+        let color = [channel(rgb[0]).map_err(in_line)?, channel(rgb[1]).map_err(in_line)?, channel(rgb[2]).map_err(in_line)?];
+        entries.push((position, color));
+    }
+    place(entries)
+}
+
+/// Just enough JSON for palette files.
+#[derive(Debug, Clone, PartialEq)]
+enum Json
+{
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+    Other, //true, false, and null; never valid in a palette, but they should not be syntax errors.
+}
+
+struct JsonParser<'a>
+{
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_>
+{
+    fn error(&self, message: &str) -> String
+    {
+        format!("invalid JSON at byte {}: {}", self.pos, message)
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool
+    {
+        self.skip_whitespace();
+        if self.text[self.pos..].starts_with(c)
+        {
+            self.pos += 1;
+            true
+        }
+        else
+        {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String>
+    {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        if self.eat('[')
+        {
+            let mut items = Vec::new();
+            if self.eat(']')
+            {
+                return Ok(Json::Array(items));
+            }
+            loop
+            {
+                items.push(self.value()?);
+                if self.eat(']') { return Ok(Json::Array(items)); }
+                if !self.eat(',') { return Err(self.error("expected ',' or ']'")); }
+            }
+        }
+        if self.eat('{')
+        {
+            let mut members = Vec::new();
+            if self.eat('}')
+            {
+                return Ok(Json::Object(members));
+            }
+            loop
+            {
+                self.skip_whitespace();
+                let key = match self.value()?
+                {
+                    Json::String(key) => key,
+                    _ => return Err(self.error("expected a string key")),
+                };
+                if !self.eat(':') { return Err(self.error("expected ':'")); }
+                members.push((key, self.value()?));
+                if self.eat('}') { return Ok(Json::Object(members)); }
+                if !self.eat(',') { return Err(self.error("expected ',' or '}'")); }
+            }
+        }
+        if let Some(body) = rest.strip_prefix('"')
+        {
+            //Palette strings are color codes and names, so escapes other than \" and \\ are not needed.
+            let mut out = String::new();
+            let mut chars = body.char_indices();
+            while let Some((i, c)) = chars.next()
+            {
+                match c
+                {
+                    '"' =>
+                    {
+                        self.pos += 1 + i + 1;
+                        return Ok(Json::String(out));
+                    }
+                    '\\' => match chars.next()
+                    {
+                        Some((_, escaped @ ('"' | '\\' | '/'))) => out.push(escaped),
+                        _ => return Err(self.error("unsupported string escape")),
+                    },
+                    _ => out.push(c),
+                }
+            }
+            return Err(self.error("unterminated string"));
+        }
+        for (word, value) in [("true", Json::Other), ("false", Json::Other), ("null", Json::Other)]
+        {
+            if rest.starts_with(word)
+            {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        let length = rest.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(rest.len());
+        match rest[..length].parse::<f64>()
+        {
+            Ok(number) if length > 0 =>
+            {
+                self.pos += length;
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+}
+
+fn json_color(value: &Json) -> Result<[u8; 3], String>
+{
+    match value
+    {
+        Json::String(s) => hex_color(s),
+        Json::Array(items) if items.len() == 3 => json_numbers(items)?.into_iter().map(channel)
+            .collect::<Result<Vec<u8>, String>>().map(|rgb| [rgb[0], rgb[1], rgb[2]]),
+        _ => Err("expected a color: [R,G,B] or \"#rrggbb\"".to_string()),
+    }
+}
+
+fn json_numbers(items: &[Json]) -> Result<Vec<f64>, String>
+{
+    items.iter().map(|item| match item { Json::Number(n) => Ok(*n), _ => Err("expected a number".to_string()) }).collect()
+}
+
+fn parse_json(text: &str) -> Result<Vec<Stop>, String>
+{
+    let mut parser = JsonParser{text, pos: 0};
+    let document = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != text.len()
+    {
+        return Err(parser.error("unexpected text after the palette"));
+    }
+    let stops = match document
+    {
+        Json::Array(stops) => stops,
+        Json::Object(members) => match members.into_iter().find(|(key, _)| key == "stops")
+        {
+            Some((_, Json::Array(stops))) => stops,
+            _ => return Err("expected a \"stops\" array".to_string()),
+        },
+        _ => return Err("expected an array of stops".to_string()),
+    };
+    let mut entries = Vec::new();
+    for (index, stop) in stops.iter().enumerate()
+    {
+        let in_stop = |err: String| format!("stop {}: {}", index + 1, err);
+        let entry = match stop
+        {
+            Json::Array(items) if items.len() == 4 =>
+            {
+                let numbers = json_numbers(items).map_err(in_stop)?;
+                (Some(numbers[0]), json_color(&Json::Array(items[1..].to_vec())).map_err(in_stop)?)
+            }
+            Json::Object(members) =>
+            {
+                let field = |names: &[&str]| members.iter().find(|(key, _)| names.contains(&key.as_str())).map(|(_, value)| value);
+                let position = match field(&["position", "pos"])
+                {
+                    Some(Json::Number(n)) => Some(*n),
+                    None => None,
+                    Some(_) => return Err(in_stop("position must be a number".to_string())),
+                };
+                let color = field(&["color", "colour"]).ok_or_else(|| in_stop("missing \"color\"".to_string()))?;
+                (position, json_color(color).map_err(in_stop)?)
+            }
+            _ => (None, json_color(stop).map_err(in_stop)?),
+        };
+        entries.push(entry);
+    }
+    place(entries)
+}
+
+fn parse_ggr(text: &str) -> Result<Vec<Stop>, String>
+{
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+    if lines.next().map(|(_, line)| line) != Some("GIMP Gradient")
+    {
+        return Err("missing \"GIMP Gradient\" header".to_string());
+    }
+    let (mut number, mut line) = lines.next().ok_or("missing segment count")?;
+    if line.starts_with("Name:")
+    {
+        (number, line) = lines.next().ok_or("missing segment count")?;
+    }
+    let count: usize = line.parse().map_err(|_| format!("line {}: expected the segment count", number))?;
+    let mut stops = Vec::with_capacity(count * 3);
+    for _ in 0..count
+    {
+        let (number, line) = lines.next().ok_or_else(|| format!("expected {} segments", count))?;
+        let fields: Vec<f64> = line.split_whitespace().map(|field| field.parse::<f64>()).collect::<Result<_, _>>()
+            .map_err(|_| format!("line {}: expected numbers", number))?;
+        if fields.len() < 11
+        {
+            return Err(format!("line {}: a segment needs at least 11 fields, found {}", number, fields.len()));
+        }
+        let rgb = |i: usize| -> Result<[u8; 3], String>
+        {
+            let unit = |value: f64| channel(value * 255.0).map_err(|_| format!("line {}: color component {} is outside 0..1", number, value));
+            Ok([unit(fields[i])?, unit(fields[i + 1])?, unit(fields[i + 2])?])
+        };
+        let (left, middle, right) = (fields[0], fields[1], fields[2]);
+        let (left_color, right_color) = (rgb(3)?, rgb(7)?);
+        //Every GIMP blend function is halfway between the end colors at the middle point.
+        let mid_color = [0, 1, 2].map(|c| (left_color[c] as u16 + right_color[c] as u16).div_ceil(2) as u8);
+        stops.extend([(left, left_color), (middle, mid_color), (right, right_color)]);
+    }
+    Ok(stops)
+}
+
+fn parse_ugr(text: &str) -> Result<Vec<Stop>, String>
+{
+    let body = text.find("gradient:").map(|start| &text[start + "gradient:".len()..]).ok_or("no \"gradient:\" section")?;
+    //The section ends at the next "name:" line (usually "opacity:") or at the closing brace.
+    let end = body.lines()
+        .position(|line| { let line = line.trim(); line.starts_with('}') || (line.ends_with(':') && !line.contains('=')) })
+        .unwrap_or(usize::MAX);
+    let mut indexed: Vec<(f64, [u8; 3])> = Vec::new();
+    for line in body.lines().take(end)
+    {
+        let mut index = None;
+        let mut color = None;
+        for token in line.split_whitespace()
+        {
+            if let Some(value) = token.strip_prefix("index=")
+            {
+                index = Some(value.parse::<f64>().map_err(|_| format!("invalid index \"{}\"", value))?);
+            }
+            else if let Some(value) = token.strip_prefix("color=")
+            {
+                //UltraFractal stores colors as decimal integers in BGR order.
+                let bgr = value.parse::<u32>().map_err(|_| format!("invalid color \"{}\"", value))?;
+                color = Some([(bgr & 0xff) as u8, (bgr >> 8 & 0xff) as u8, (bgr >> 16 & 0xff) as u8]);
+            }
+        }
+        match (index, color)
+        {
+            (Some(index), Some(color)) => indexed.push((index.rem_euclid(400.0), color)),
+            (None, None) => {}
+            _ => return Err(format!("expected index= and color= together in \"{}\"", line.trim())),
+        }
+    }
+    indexed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (Some(&(first, first_color)), Some(&(last, last_color))) = (indexed.first(), indexed.last())
+    else
+    {
+        return Err("gradient has no colors".to_string());
+    };
+    //Close the loop: interpolate between the last and first colors across the 400 -> 0 wrap.
+    let gap = 400.0 - last + first;
+    let f = if gap > 0.0 { (400.0 - last) / gap } else { 0.0 };
+    let wrap = [0, 1, 2].map(|c| (last_color[c] as f64 + (first_color[c] as f64 - last_color[c] as f64) * f).round() as u8);
+    let mut stops = vec![(0.0, wrap)];
+    stops.extend(indexed.iter().map(|&(index, color)| (index / 400.0, color)));
+    stops.push((1.0, wrap));
+    Ok(stops)
+}
+
+#[test]
+fn test_csv_palettes()
+{
+    let even = parse("# sunset\nr,g,b\n0,0,0\n255, 128, 0\n\n255,255,255\n", Format::Csv, "sunset").unwrap();
+    assert_eq!(even.name, "sunset");
+    assert_eq!(even.stops, vec![(0.0, [0, 0, 0]), (0.5, [255, 128, 0]), (1.0, [255, 255, 255])]);
+    let placed = parse("0.25,10,20,30\n0,1,2,3\n", Format::Csv, "p").unwrap();
+    assert_eq!(placed.stops, vec![(0.0, [1, 2, 3]), (0.25, [10, 20, 30])]);
+    let single = parse("9,9,9", Format::Csv, "s").unwrap();
+    assert_eq!(single.color(0.7), [9, 9, 9]);
+    assert!(parse("0,0,0\n1,2\n", Format::Csv, "x").unwrap_err().starts_with("line 2:"));
+    assert!(parse("0,0,300\n", Format::Csv, "x").unwrap_err().contains("outside 0..255"));
+    assert!(parse("0.5,0,0,0\n0,0,0\n", Format::Csv, "x").is_err());
+    assert!(parse("# nothing\n", Format::Csv, "x").is_err());
+}
+
+#[test]
+fn test_json_palettes()
+{
+    let expected = vec![(0.0, [0, 0, 0]), (0.5, [255, 0, 0]), (1.0, [255, 255, 255])];
+    for text in [r##"[[0,0,0], "#ff0000", [255, 255, 255]]"##,
+                 r##"{"name": "x", "stops": [[0,0,0,0], [0.5,255,0,0], [1,255,255,255]]}"##,
+                 r##"[{"position": 0, "color": "#000000"}, {"pos": 0.5, "color": [255,0,0]}, {"position": 1.0, "color": "#FFFFFF"}]"##]
+    {
+        assert_eq!(parse(text, Format::Json, "j").unwrap().stops, expected, "{}", text);
+    }
+    assert!(parse("[[0,0,0], ", Format::Json, "j").unwrap_err().contains("invalid JSON"));
+    assert!(parse(r#"["red"]"#, Format::Json, "j").unwrap_err().contains("#rrggbb"));
+    assert!(parse("[[0,0,0]] x", Format::Json, "j").is_err());
+}
+
+#[test]
+fn test_ggr_palette()
+{
+    let text = "GIMP Gradient\nName: Two part\n2\n\
+                0.000000 0.250000 0.500000 0 0 0 1 1 0 0 1 0 0\n\
+                0.500000 0.750000 1.000000 1 0 0 1 1 1 1 1 0 0\n";
+    let palette = parse(text, Format::Ggr, "two").unwrap();
+    assert_eq!(palette.stops.len(), 6);
+    assert_eq!(palette.color(0.25), [128, 0, 0]);
+    assert_eq!(palette.color(0.5), [255, 0, 0]);
+    assert_eq!(palette.color(1.0), [255, 255, 255]);
+    assert!(parse("GIMP Gradient\n3\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n", Format::Ggr, "x").is_err());
+    assert!(parse("Not a gradient\n", Format::Ggr, "x").is_err());
+}
+
+#[test]
+fn test_ugr_palette()
+{
+    let text = "Test {\ngradient:\n  title=\"Test\" smooth=no\n  index=0 color=255\n  index=200 color=16711680\nopacity:\n  smooth=no index=0 opacity=255\n}\n";
+    let palette = parse(text, Format::Ugr, "test").unwrap();
+    assert_eq!(palette.color(0.0), [255, 0, 0]);
+    assert_eq!(palette.color(0.5), [0, 0, 255]);
+    assert_eq!(palette.color(0.25), [128, 0, 128]);
+    assert_eq!(palette.color(1.0), [255, 0, 0]); //Wraps back to the first color.
+    //A gradient that starts after index 0 wraps smoothly through position 0.
+    let shifted = parse("g {\ngradient:\n index=100 color=0\n index=300 color=16777215\n}\n", Format::Ugr, "g").unwrap();
+    assert_eq!(shifted.color(0.0), [128, 128, 128]);
+    assert!(parse("g {\ngradient:\n index=5\n}\n", Format::Ugr, "g").is_err());
+}
+
+#[test]
+fn test_format_from_path()
+{
+    assert_eq!(Format::from_path(Path::new("grads/Sunset.GGR")), Some(Format::Ggr));
+    assert_eq!(Format::from_path(Path::new("a.json")), Some(Format::Json));
+    assert_eq!(Format::from_path(Path::new("a.png")), None);
+    assert_eq!(Format::from_path(Path::new("noext")), None);
+}
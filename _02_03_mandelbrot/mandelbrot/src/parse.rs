@@ -0,0 +1,578 @@
+//! Parsing of the command-line coordinate and geometry formats.
+//!
+//! Grammar accepted by `parse_tuple::<T, N>` (SEP is the separator passed in, e.g. ',' or 'x'):
+//!
+//! ```text
+//! tuple   := ws* value ws* (SEP ws* value ws*){N-1}
+//! value   := anything T::from_str accepts, containing no SEP
+//! ws      := ' ' | '\t'
+//! ```
+//!
+//! `parse_pair` is the N = 2 case. Exactly N-1 separators are allowed, so "1,2,3" is rejected as a pair
+//! instead of silently parsing "2,3" as the second value.
+//!
+//! Geometry helpers built on top of it:
+//!
+//! ```text
+//! size    := uint 'x' uint ('@' number 'x'?)?      e.g. "1920x1080", "800x600@2x"
+//! percent := number '%'                           e.g. "10%", "2.5 %"
+//! polar   := number ',' number                    radius, angle in degrees, e.g. "0.7885,90"
+//! age     := number ('s' | 'm' | 'h' | 'd' | 'w')  e.g. "30d", "1.5h"
+//! bytes   := number ('K' | 'M' | 'G' | 'T')?       powers of 1024, e.g. "512M", "1.5G"
+//! ```
+//!
+//! Before parsing, `InputStyle` normalizes what people paste from websites: typographic minus signs become '-',
+//! and with a ';' pair separator, decimal commas become '.'. parse_complex takes "−0,75; 0,1" as well as "-0.75,0.1".
+//!
+//! Every error carries the byte span of the offending part of the input, so the CLI can point at the typo
+//! rather than just saying "error parsing".
+
+use num::Complex;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// What went wrong while parsing. The span in ParseError says where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind
+{
+    Empty,                  //The input was empty (or only whitespace).
+    MissingSeparator(char), //No separator was found at all.
+    ExtraSeparator(char),   //More than one separator was found.
+    EmptyComponent,         //One side of the separator is empty, e.g. "10,".
+    InvalidNumber,          //One side of the separator could not be parsed by T::from_str.
+    OutOfRange,             //The value parsed, but is not allowed here (e.g. a zero width).
+    MissingSuffix(char),    //A required suffix such as '%' is absent.
+    Expected(&'static str), //Something specific was required here, e.g. the "mandel://" prefix.
+}
+
+/// A parse failure together with the byte range of the input it refers to.
+/// Range<usize> is the type of `a..b` expressions; here it indexes into the original string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError
+{
+    pub kind: ParseErrorKind,
+    pub span: Range<usize>,
+}
+
+impl ParseError
+{
+    pub(crate) fn new(kind: ParseErrorKind, span: Range<usize>) -> ParseError
+    {
+        ParseError{kind, span}
+    }
+
+    /// Render the input with a row of carets under the span, for error messages:
+    ///
+    /// ```text
+    /// 10,2o
+    ///    ^^
+    /// ```
+    pub fn underline(&self, input: &str) -> String
+    {
+        let start = input[..self.span.start].chars().count();
+        let len = input[self.span.clone()].chars().count().max(1);
+        format!("{}\n{}{}", input, " ".repeat(start), "^".repeat(len))
+    }
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match &self.kind
+        {
+            ParseErrorKind::Empty => write!(f, "input is empty"),
+            ParseErrorKind::MissingSeparator(sep) => write!(f, "too few values; expected them separated by '{}'", sep),
+            ParseErrorKind::ExtraSeparator(sep) => write!(f, "unexpected extra '{}' at byte {}", sep, self.span.start),
+            ParseErrorKind::EmptyComponent => write!(f, "missing value at byte {}", self.span.start),
+            ParseErrorKind::InvalidNumber => write!(f, "invalid number at bytes {}..{}", self.span.start, self.span.end),
+            ParseErrorKind::OutOfRange => write!(f, "value out of range at bytes {}..{}", self.span.start, self.span.end),
+            ParseErrorKind::MissingSuffix(suffix) => write!(f, "expected a value ending in '{}'", suffix),
+            ParseErrorKind::Expected(what) => write!(f, "expected {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Shrink the range s[range] so that it excludes leading and trailing whitespace, and return the new range.
+/// Working with ranges instead of trimmed slices keeps the byte offsets pointing into the original input.
+pub(crate) fn trim_span(s: &str, range: Range<usize>) -> Range<usize>
+{
+    let part = &s[range.clone()];
+    let start = range.start + (part.len() - part.trim_start_matches([' ', '\t']).len());
+    let end = range.end - (part.len() - part.trim_end_matches([' ', '\t']).len());
+    start..end.max(start)
+}
+
+/// Parse the single value in s[range] (after trimming whitespace) as a T.
+pub(crate) fn parse_value<T: FromStr>(s: &str, range: Range<usize>) -> Result<T, ParseError>
+{
+    let span = trim_span(s, range);
+    if span.is_empty()
+    {
+        return Err(ParseError::new(ParseErrorKind::EmptyComponent, span));
+    }
+    T::from_str(&s[span.clone()]).map_err(|_| ParseError::new(ParseErrorKind::InvalidNumber, span))
+}
+
+/// Parse the string 's' as N values separated by 'separator', like "1,2,3" for N = 3.
+/// The const generic N is part of the type, so parse_tuple::<f64, 3> returns exactly three numbers in a [f64; 3]
+/// and the "wrong number of values" case is an error rather than something the caller has to check.
+pub fn parse_tuple<T: FromStr, const N: usize>(s: &str, separator: char) -> Result<[T; N], ParseError>
+{
+    if s.trim_matches([' ', '\t']).is_empty()
+    {
+        return Err(ParseError::new(ParseErrorKind::Empty, 0..s.len()));
+    }
+    //Byte ranges of the N components, found by walking the separators.
+    let mut ranges = Vec::with_capacity(N);
+    let mut start = 0;
+    for (index, _) in s.match_indices(separator)
+    {
+        if ranges.len() + 1 == N
+        {
+            return Err(ParseError::new(ParseErrorKind::ExtraSeparator(separator), index..index + separator.len_utf8()));
+        }
+        ranges.push(start..index);
+        start = index + separator.len_utf8();
+    }
+    ranges.push(start..s.len());
+    if ranges.len() < N
+    {
+        return Err(ParseError::new(ParseErrorKind::MissingSeparator(separator), 0..s.len()));
+    }
+    let values = ranges.into_iter().map(|range| parse_value(s, range)).collect::<Result<Vec<T>, _>>()?;
+    match values.try_into()
+    {
+        Ok(array) => Ok(array),
+        Err(_) => unreachable!("exactly N ranges were collected"),
+    }
+}
+
+#[test]
+fn test_parse_tuple()
+{
+    assert_eq!(parse_tuple::<i32, 3>("1,2,3", ','), Ok([1, 2, 3]));
+    assert_eq!(parse_tuple::<f64, 1>(" 2.5 ", ','), Ok([2.5]));
+    assert_eq!(parse_tuple::<i32, 3>("1,2", ',').unwrap_err().kind, ParseErrorKind::MissingSeparator(','));
+    assert_eq!(parse_tuple::<i32, 3>("1,2,3,4", ','), Err(ParseError::new(ParseErrorKind::ExtraSeparator(','), 5..6)));
+    assert_eq!(parse_tuple::<i32, 3>("1,,3", ','), Err(ParseError::new(ParseErrorKind::EmptyComponent, 2..2)));
+}
+
+/// Parse the string 's' as a coordinate pair, like "400x600" or "1.0,0.5".
+/// You can read the clause <T: FromStr> aloud as "For any type T that implements FromStr trait".
+/// This effectively lets us define an entire family of functions at once: parse_pair::<i32>, parse_pair::<f64>.
+/// Result<(T, T), ParseError>: Ok((l, r)) on success, otherwise an error saying what is wrong and where.
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Result<(T, T), ParseError>
+{
+    let [l, r] = parse_tuple::<T, 2>(s, separator)?;
+    Ok((l, r))
+}
+
+//Test for parse_pair
+#[test]
+fn test_parse_pair()
+{
+    assert_eq!(parse_pair::<i32>("",        ',').unwrap_err().kind, ParseErrorKind::Empty);
+    assert_eq!(parse_pair::<i32>("10,",     ','), Err(ParseError::new(ParseErrorKind::EmptyComponent, 3..3)));
+    assert_eq!(parse_pair::<i32>(",10",     ','), Err(ParseError::new(ParseErrorKind::EmptyComponent, 0..0)));
+    assert_eq!(parse_pair::<i32>("10,20",   ','), Ok((10, 20)));
+    assert_eq!(parse_pair::<i32>("10,20xy", ','), Err(ParseError::new(ParseErrorKind::InvalidNumber, 3..7)));
+    assert_eq!(parse_pair::<f64>("0.5x",    'x').unwrap_err().kind, ParseErrorKind::EmptyComponent);
+    assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Ok((0.5, 1.5)));
+    assert_eq!(parse_pair::<i32>("1,2,3",   ','), Err(ParseError::new(ParseErrorKind::ExtraSeparator(','), 3..4)));
+    assert_eq!(parse_pair::<i32>("12",      ','), Err(ParseError::new(ParseErrorKind::MissingSeparator(','), 0..2)));
+    assert_eq!(parse_pair::<i32>(" 10 , 20 ", ','), Ok((10, 20)));
+}
+
+/// How forgiving to be about numbers pasted from websites and documents written for other locales.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStyle
+{
+    /// Accept typographic minus signs ('−' U+2212, '–' U+2013, '‒' U+2012) and no-break spaces.
+    pub unicode_minus: bool,
+    /// Accept ',' as the decimal separator. Only honored when the pair separator is something else, such as
+    /// ';' in "−0,75; 0,1" — with ',' as the pair separator there would be no way to tell the two apart.
+    pub decimal_comma: bool,
+}
+
+impl InputStyle
+{
+    /// Exactly what f64::from_str accepts, nothing else.
+    pub const STRICT: InputStyle = InputStyle{unicode_minus: false, decimal_comma: false};
+    /// Everything this module knows how to normalize.
+    pub const LENIENT: InputStyle = InputStyle{unicode_minus: true, decimal_comma: true};
+}
+
+impl Default for InputStyle
+{
+    /// Everything, by default: unicode minus signs are never ambiguous, and a decimal comma is only read as one
+    /// where something other than ',' separates the values, which people only write with decimal commas in mind.
+    fn default() -> InputStyle
+    {
+        InputStyle::LENIENT
+    }
+}
+
+/// Input rewritten into the ASCII form T::from_str understands, plus where each byte came from.
+/// origin[i] is the byte offset in the original input of byte i of 'text'; it has one extra entry for the end,
+/// so error spans found in 'text' can be mapped back onto what the user actually typed.
+struct Normalized
+{
+    text: String,
+    origin: Vec<usize>,
+}
+
+impl Normalized
+{
+    fn new(s: &str, style: InputStyle, separator: char) -> Normalized
+    {
+        let mut text = String::with_capacity(s.len());
+        let mut origin = Vec::with_capacity(s.len() + 1);
+        for (offset, ch) in s.char_indices()
+        {
+            let mapped = match ch
+            {
+                '\u{2212}' | '\u{2013}' | '\u{2012}' if style.unicode_minus => '-',
+                '\u{00a0}' | '\u{202f}' if style.unicode_minus => ' ',
+                ',' if style.decimal_comma && separator != ',' => '.',
+                other => other,
+            };
+            text.push(mapped);
+            origin.extend(std::iter::repeat_n(offset, mapped.len_utf8()));
+        }
+        origin.push(s.len());
+        Normalized{text, origin}
+    }
+
+    /// Translate an error about 'text' into one about the original input.
+    fn restore(&self, err: ParseError) -> ParseError
+    {
+        ParseError::new(err.kind, self.origin[err.span.start]..self.origin[err.span.end])
+    }
+}
+
+/// parse_tuple, after normalizing the input according to 'style'.
+pub fn parse_tuple_with<T: FromStr, const N: usize>(s: &str, separator: char, style: InputStyle) -> Result<[T; N], ParseError>
+{
+    let normalized = Normalized::new(s, style, separator);
+    parse_tuple(&normalized.text, separator).map_err(|err| normalized.restore(err))
+}
+
+#[test]
+fn test_parse_tuple_with()
+{
+    assert_eq!(parse_tuple_with::<f64, 2>("\u{2212}0.5,1", ',', InputStyle::default()), Ok([-0.5, 1.0]));
+    assert_eq!(parse_tuple_with::<f64, 2>("\u{2212}0.5,1", ',', InputStyle::STRICT).unwrap_err().span, 0..6);
+    assert_eq!(parse_tuple_with::<f64, 2>("\u{2212}0,5; 1,25", ';', InputStyle::LENIENT), Ok([-0.5, 1.25]));
+    //A decimal comma is not allowed when ',' separates the values.
+    assert_eq!(parse_tuple_with::<f64, 2>("0,5,1", ',', InputStyle::LENIENT).unwrap_err().kind, ParseErrorKind::ExtraSeparator(','));
+    //Spans still point into the original input, past the three-byte minus sign.
+    assert_eq!(parse_tuple_with::<f64, 2>("\u{2212}1;2,5q", ';', InputStyle::LENIENT), Err(ParseError::new(ParseErrorKind::InvalidNumber, 5..9)));
+}
+
+/// The following function uses the parse_pair function to parse a pair of floating point coordinates
+/// and return them as Complex<f64>. If parse_pair succeeds, destructure the tuple into re (real part)
+/// and im (imaginary part) and construct a Complex<f64> number with Complex { re, im }.
+pub fn parse_complex(s: &str) -> Result<Complex<f64>, ParseError>
+{
+    parse_complex_with(s, InputStyle::default())
+}
+
+/// What separates the real and imaginary parts of 's': when decimal commas are enabled and the input contains a
+/// ';', the ';' does ("−0,75; 0,1"); otherwise ',' does.
+pub fn complex_separator(s: &str, style: InputStyle) -> char
+{
+    if style.decimal_comma && s.contains(';') { ';' } else { ',' }
+}
+
+/// parse_complex with a chosen InputStyle (see complex_separator).
+pub fn parse_complex_with(s: &str, style: InputStyle) -> Result<Complex<f64>, ParseError>
+{
+    let [re, im] = parse_tuple_with(s, complex_separator(s, style), style)?;
+    Ok(Complex{re, im})
+}
+
+// Test for parse_complex
+#[test]
+fn test_parse_complex()
+{
+    assert_eq!(parse_complex("1.25, -0.0625"), Ok(Complex{re: 1.25, im: -0.0625}));
+    assert_eq!(parse_complex(",-0.0625"), Err(ParseError::new(ParseErrorKind::EmptyComponent, 0..0)));
+    assert_eq!(parse_complex("1.25,-0.O625").unwrap_err().span, 5..12);
+    assert_eq!(parse_complex("\u{2212}1.25,\u{2212}0.5"), Ok(Complex{re: -1.25, im: -0.5}));
+    assert_eq!(parse_complex_with("\u{2212}0,75; 0,1", InputStyle::LENIENT), Ok(Complex{re: -0.75, im: 0.1}));
+    assert_eq!(parse_complex_with("-0.75,0.1", InputStyle::LENIENT), Ok(Complex{re: -0.75, im: 0.1}));
+    assert_eq!(parse_complex("-0,75;0,1"), Ok(Complex{re: -0.75, im: 0.1}));
+    assert_eq!(parse_complex_with("-0,75;0,1", InputStyle::STRICT).unwrap_err().kind, ParseErrorKind::ExtraSeparator(','));
+}
+
+/// Parse a complex number given in polar form as "r,theta_degrees", e.g. "0.7885,90" for 0.7885i.
+/// Sweeping theta at a fixed r is the usual way to walk through the family of Julia sets, which is why the
+/// angle is in degrees rather than radians.
+pub fn parse_polar(s: &str) -> Result<Complex<f64>, ParseError>
+{
+    let [r, theta]: [f64; 2] = parse_tuple_with(s, ',', InputStyle::default())?;
+    Ok(Complex::from_polar(r, theta.to_radians()))
+}
+
+#[test]
+fn test_parse_polar()
+{
+    let c = parse_polar("0.7885, 90").unwrap();
+    assert!((c - Complex{re: 0.0, im: 0.7885}).norm() < 1e-12);
+    let c = parse_polar("2,\u{2212}180").unwrap();
+    assert!((c - Complex{re: -2.0, im: 0.0}).norm() < 1e-12);
+    assert_eq!(parse_polar("0.7885").unwrap_err().kind, ParseErrorKind::MissingSeparator(','));
+}
+
+/// The largest side a size may have after scaling: PNG stores sides as 31-bit numbers.
+pub const MAX_SIDE: usize = (1 << 31) - 1;
+
+/// The most pixels a size may have after scaling, so that even an RGB buffer of the image has a size memory can
+/// address.
+pub const MAX_PIXELS: u64 = isize::MAX as u64 / 3;
+
+/// An image size in pixels, optionally with a scale factor: "800x600@2x" means an 800x600 layout rendered at
+/// twice the resolution, i.e. 1600x1200 actual pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Size
+{
+    pub width: usize,
+    pub height: usize,
+    pub scale: f64,
+}
+
+impl Size
+{
+    /// The pixel dimensions after applying the scale factor, rounded to whole pixels (at least 1x1).
+    pub fn pixels(&self) -> (usize, usize)
+    {
+        let scaled = |n: usize| ((n as f64 * self.scale).round() as usize).max(1);
+        (scaled(self.width), scaled(self.height))
+    }
+}
+
+/// Parse a size like "1920x1080" or "800x600@2x" (see the grammar at the top of this file).
+pub fn parse_size(s: &str) -> Result<Size, ParseError>
+{
+    let (dims, scale, scale_span) = match s.find('@')
+    {
+        Some(at) =>
+        {
+            let span = trim_span(s, at + 1..s.len());
+            //The trailing 'x' in "@2x" is optional, so strip it before parsing the number.
+            let number = if s[span.clone()].ends_with(['x', 'X']) { span.start..span.end - 1 } else { span.clone() };
+            let scale: f64 = parse_value(s, number)?;
+            if !(scale.is_finite() && scale > 0.0)
+            {
+                return Err(ParseError::new(ParseErrorKind::OutOfRange, span));
+            }
+            (0..at, scale, Some(span))
+        }
+        None => (0..s.len(), 1.0, None),
+    };
+    let (width, height): (usize, usize) = parse_pair(&s[dims.clone()], 'x').map_err(|err| shift(err, dims.start))?;
+    for (value, side) in [(width, 0), (height, 1)]
+    {
+        if value == 0
+        {
+            let [w, h] = component_spans(&s[dims.clone()], 'x');
+            let span = if side == 0 { w } else { h };
+            return Err(ParseError::new(ParseErrorKind::OutOfRange, span));
+        }
+    }
+    //Checked in f64 before pixels() rounds to usize, where an absurd scale would saturate instead.
+    let (scaled_width, scaled_height) = ((width as f64 * scale).round(), (height as f64 * scale).round());
+    if scaled_width.max(scaled_height) > MAX_SIDE as f64 || scaled_width.max(1.0) * scaled_height.max(1.0) > MAX_PIXELS as f64
+    {
+        return Err(ParseError::new(ParseErrorKind::OutOfRange, scale_span.unwrap_or_else(|| trim_span(s, dims))));
+    }
+    Ok(Size{width, height, scale})
+}
+
+/// The trimmed spans of the two sides of a pair that is already known to parse.
+fn component_spans(s: &str, separator: char) -> [Range<usize>; 2]
+{
+    let index = s.find(separator).unwrap_or(s.len());
+    [trim_span(s, 0..index), trim_span(s, (index + separator.len_utf8()).min(s.len())..s.len())]
+}
+
+/// Move an error produced on a sub-slice so that its span indexes the full input again.
+fn shift(err: ParseError, offset: usize) -> ParseError
+{
+    ParseError::new(err.kind, err.span.start + offset..err.span.end + offset)
+}
+
+#[test]
+fn test_parse_size()
+{
+    assert_eq!(parse_size("1920x1080"), Ok(Size{width: 1920, height: 1080, scale: 1.0}));
+    assert_eq!(parse_size("800x600@2x").map(|size| size.pixels()), Ok((1600, 1200)));
+    assert_eq!(parse_size("800x600 @ 1.5").map(|size| size.pixels()), Ok((1200, 900)));
+    assert_eq!(parse_size("0x600"), Err(ParseError::new(ParseErrorKind::OutOfRange, 0..1)));
+    assert_eq!(parse_size("800x600@0x"), Err(ParseError::new(ParseErrorKind::OutOfRange, 8..10)));
+    assert_eq!(parse_size("800x6o0@2x"), Err(ParseError::new(ParseErrorKind::InvalidNumber, 4..7)));
+    assert_eq!(parse_size("800x600@x").unwrap_err().kind, ParseErrorKind::EmptyComponent);
+    //Too big to ever allocate or encode, however it got that way.
+    assert_eq!(parse_size("800x600@1e300"), Err(ParseError::new(ParseErrorKind::OutOfRange, 8..13)));
+    assert_eq!(parse_size("4000000000x1"), Err(ParseError::new(ParseErrorKind::OutOfRange, 0..12)));
+    assert_eq!(parse_size("99999999999999999999x1").unwrap_err().kind, ParseErrorKind::InvalidNumber);
+    assert_eq!(parse_size("2000000000x2000000000").unwrap_err().kind, ParseErrorKind::OutOfRange);
+    assert_eq!(parse_size("2147483647x1").map(|size| size.pixels()), Ok((2147483647, 1)));
+}
+
+/// Parse a percentage like "10%" into a fraction (0.1). Used for paddings and other relative geometry.
+pub fn parse_percent(s: &str) -> Result<f64, ParseError>
+{
+    let span = trim_span(s, 0..s.len());
+    if span.is_empty()
+    {
+        return Err(ParseError::new(ParseErrorKind::Empty, span));
+    }
+    if !s[span.clone()].ends_with('%')
+    {
+        return Err(ParseError::new(ParseErrorKind::MissingSuffix('%'), span));
+    }
+    let percent: f64 = parse_value(s, span.start..span.end - 1)?;
+    if !percent.is_finite()
+    {
+        return Err(ParseError::new(ParseErrorKind::OutOfRange, span));
+    }
+    Ok(percent / 100.0)
+}
+
+#[test]
+fn test_parse_percent()
+{
+    assert_eq!(parse_percent("10%"), Ok(0.1));
+    assert_eq!(parse_percent(" 2.5 % "), Ok(0.025));
+    assert_eq!(parse_percent("10"), Err(ParseError::new(ParseErrorKind::MissingSuffix('%'), 0..2)));
+    assert_eq!(parse_percent("%").unwrap_err().kind, ParseErrorKind::EmptyComponent);
+    assert_eq!(parse_percent("inf%").unwrap_err().kind, ParseErrorKind::OutOfRange);
+}
+
+/// Parse an age like "30d" or "1.5h" (seconds, minutes, hours, days or weeks), as used by `cache clean --older-than`.
+pub fn parse_age(s: &str) -> Result<Duration, ParseError>
+{
+    let span = trim_span(s, 0..s.len());
+    if span.is_empty()
+    {
+        return Err(ParseError::new(ParseErrorKind::Empty, span));
+    }
+    let suffix = s[span.clone()].chars().last().unwrap_or(' ');
+    let number = span.start..span.end - suffix.len_utf8();
+    let unit = match suffix
+    {
+        's' => 1.0,
+        'm' => 60.0,
+        'h' => 3600.0,
+        'd' => 86400.0,
+        'w' => 7.0 * 86400.0,
+        _ => return Err(ParseError::new(ParseErrorKind::Expected("an age ending in s, m, h, d or w"), number.end..span.end)),
+    };
+    let amount: f64 = parse_value(s, number)?;
+    Duration::try_from_secs_f64(amount * unit).map_err(|_| ParseError::new(ParseErrorKind::OutOfRange, span))
+}
+
+#[test]
+fn test_parse_age()
+{
+    assert_eq!(parse_age("30d"), Ok(Duration::from_secs(30 * 86400)));
+    assert_eq!(parse_age(" 1.5h "), Ok(Duration::from_secs(5400)));
+    assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 86400)));
+    assert_eq!(parse_age("90"), Err(ParseError::new(ParseErrorKind::Expected("an age ending in s, m, h, d or w"), 1..2)));
+    assert_eq!(parse_age("d").unwrap_err().kind, ParseErrorKind::EmptyComponent);
+    assert_eq!(parse_age("-1d").unwrap_err().kind, ParseErrorKind::OutOfRange);
+    assert_eq!(parse_age("").unwrap_err().kind, ParseErrorKind::Empty);
+}
+
+/// Parse a memory size like "512M" or "1.5G" (K, M, G and T are powers of 1024; no suffix means bytes).
+pub fn parse_bytes(s: &str) -> Result<u64, ParseError>
+{
+    let span = trim_span(s, 0..s.len());
+    if span.is_empty()
+    {
+        return Err(ParseError::new(ParseErrorKind::Empty, span));
+    }
+    let suffix = s[span.clone()].chars().last().unwrap_or(' ');
+    let (number, scale) = match suffix.to_ascii_uppercase()
+    {
+        'K' => (span.start..span.end - 1, 1u64 << 10),
+        'M' => (span.start..span.end - 1, 1 << 20),
+        'G' => (span.start..span.end - 1, 1 << 30),
+        'T' => (span.start..span.end - 1, 1 << 40),
+        _ => (span.clone(), 1),
+    };
+    let amount: f64 = parse_value(s, number)?;
+    let bytes = amount * scale as f64;
+    if !(bytes.is_finite() && bytes >= 1.0 && bytes < u64::MAX as f64)
+    {
+        return Err(ParseError::new(ParseErrorKind::OutOfRange, span));
+    }
+    Ok(bytes as u64)
+}
+
+#[test]
+fn test_parse_bytes()
+{
+    assert_eq!(parse_bytes("512M"), Ok(512 << 20));
+    assert_eq!(parse_bytes(" 1.5g "), Ok(3 << 29));
+    assert_eq!(parse_bytes("4096"), Ok(4096));
+    assert_eq!(parse_bytes("12Q").unwrap_err().kind, ParseErrorKind::InvalidNumber);
+    assert_eq!(parse_bytes("G").unwrap_err().kind, ParseErrorKind::EmptyComponent);
+    assert_eq!(parse_bytes("0M").unwrap_err().kind, ParseErrorKind::OutOfRange);
+}
+
+#[test]
+fn test_parse_error_underline()
+{
+    let input = "10,2o";
+    let err = parse_pair::<i32>(input, ',').unwrap_err();
+    assert_eq!(err.underline(input), "10,2o\n   ^^");
+}
+
+/// Property: anything we print with the separator in between parses back to the same values.
+#[test]
+fn test_parse_pair_round_trip()
+{
+    for a in (-1000..1000).step_by(37)
+    {
+        for b in (-1000..1000).step_by(41)
+        {
+            assert_eq!(parse_pair::<i32>(&format!("{}x{}", a, b), 'x'), Ok((a, b)));
+            let (re, im) = (a as f64 / 7.0, b as f64 * 1e-9);
+            assert_eq!(parse_complex(&format!("{},{}", re, im)), Ok(Complex{re, im}));
+        }
+    }
+}
+
+/// Property: arbitrary junk never panics, and every error span lies on char boundaries inside the input.
+/// This is the same check the cargo-fuzz target in fuzz/ runs, driven by a fixed xorshift sequence.
+#[test]
+fn test_parse_pair_junk()
+{
+    let alphabet: Vec<char> = "0123456789.,x-+eE \tinfNa−é".chars().collect();
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..20_000
+    {
+        let mut input = String::new();
+        state ^= state << 13; state ^= state >> 7; state ^= state << 17;
+        for k in 0..(state % 12)
+        {
+            input.push(alphabet[((state >> (k * 5)) % alphabet.len() as u64) as usize]);
+        }
+        for result in [parse_pair::<f64>(&input, ',').map(|_| ()), parse_pair::<i64>(&input, 'x').map(|_| ())]
+        {
+            if let Err(err) = result
+            {
+                assert!(err.span.start <= err.span.end && err.span.end <= input.len());
+                assert!(input.is_char_boundary(err.span.start) && input.is_char_boundary(err.span.end));
+                err.underline(&input);
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! Perturbation rendering: deep zooms at close to f64 speed.
+//!
+//! The exact deep path (see the deep module) does every step of every pixel in big fixed-point numbers. Here only
+//! one orbit gets that treatment: the reference orbit Z of a point in the view. Every other pixel is written as
+//! that point plus a small offset, z = Z + dz, and only the offset is iterated:
+//!
+//! ```text
+//! dz' = 2 Z dz + dz^2 + dc
+//! ```
+//!
+//! dz and dc are as small as the view, but f64's exponent reaches down to 1e-308, so they keep their full 53 bits
+//! of precision at any zoom short of that; only Z needed the extra digits, and it is computed once.
+//!
+//! Two things go wrong with a single reference, and both are handled by rebasing (Zhuoran's method): when z comes
+//! closer to 0 than to Z, dz stops being small compared to z and precision is lost (the "glitches" of older
+//! renderers), and when a pixel outlives the reference orbit there are no more Z to add. In either case the pixel
+//! continues as z = Z_0 + dz with dz = z - Z_0, from the start of the reference orbit. The reference is the
+//! longest-lived of a few candidate points, so that this happens rarely.
+//!
+//! Series approximation skips the first iterations altogether: while the view is small, dz after n steps is very
+//! nearly A_n d + B_n d^2 + C_n d^3 for the pixel's offset d from the reference (dc, or dz_0 for Julia sets), with
+//! coefficients that depend only on Z. Iterations are skipped for as long as the cubic term stays negligible
+//! across the whole view.
+
+use crate::deep::{self, DeepView};
+use crate::progress::Progress;
+use crate::{paint_escape_value, parallel_rows, Coloring, Settings};
+use num::bigint::BigInt;
+use num::Complex;
+
+/// The orbit every pixel is iterated relative to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference
+{
+    pub point: Complex<BigInt>,   //The reference point, in the view's fixed point.
+    pub orbit: Vec<Complex<f64>>, //Z_0, Z_1, ... for as long as the orbit hasn't escaped, up to the limit.
+}
+
+impl Reference
+{
+    /// The reference for a view: the point with the longest orbit among a 3x3 grid of points spread over the view
+    /// (the middle one being the center), since points in the set make the best references.
+    pub fn for_view(view: &DeepView, settings: &Settings) -> Reference
+    {
+        let mut best: Option<Reference> = None;
+        for (x, y) in [(1, 1), (0, 0), (1, 0), (2, 0), (0, 1), (2, 1), (0, 2), (1, 2), (2, 2)]
+        {
+            let pixel = (view.bounds.0 * (2 * x + 1) / 6, view.bounds.1 * (2 * y + 1) / 6);
+            let reference = Reference::new(view.pixel_to_point(pixel), view.bits, settings);
+            if best.as_ref().is_none_or(|best| reference.orbit.len() > best.orbit.len())
+            {
+                best = Some(reference);
+            }
+            if best.as_ref().is_some_and(|best| best.orbit.len() == settings.limit)
+            {
+                break;
+            }
+        }
+        best.expect("the candidate list is not empty")
+    }
+
+    /// The reference orbit of 'point', a fixed-point number with 'bits' fraction bits.
+    pub fn new(point: Complex<BigInt>, bits: usize, settings: &Settings) -> Reference
+    {
+        let mut orbit = Vec::new();
+        deep::iterate(&point, bits, settings, |z| orbit.push(Complex{re: deep::to_f64(&z.re, bits), im: deep::to_f64(&z.im, bits)}));
+        Reference{point, orbit}
+    }
+}
+
+/// The cubic approximation of dz after 'skip' iterations: A d + B d^2 + C d^3.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Series
+{
+    pub skip: usize,
+    pub a: Complex<f64>,
+    pub b: Complex<f64>,
+    pub c: Complex<f64>,
+}
+
+impl Series
+{
+    /// How far the orbits of a view whose offsets from the reference are at most 'radius' can be skipped ahead.
+    pub fn new(reference: &Reference, radius: f64, settings: &Settings) -> Series
+    {
+        let zero = Complex{re: 0.0, im: 0.0};
+        //In the Mandelbrot set d is dc, so dz_1 = dc; in a Julia set d is dz_0 itself.
+        let (a0, linear) = if settings.julia.is_some() { (Complex{re: 1.0, im: 0.0}, zero) } else { (zero, Complex{re: 1.0, im: 0.0}) };
+        let mut series = Series{skip: 0, a: a0, b: zero, c: zero};
+        //Stop one short of the end of the orbit, so the perturbation loop still has a Z to start from.
+        while series.skip + 2 < reference.orbit.len()
+        {
+            let z = reference.orbit[series.skip];
+            let next = Series
+            {
+                skip: series.skip + 1,
+                a: 2.0 * z * series.a + linear,
+                b: 2.0 * z * series.b + series.a * series.a,
+                c: 2.0 * z * series.c + 2.0 * series.a * series.b,
+            };
+            //The terms left out are about as much smaller than the cubic one as it is than the quadratic one; once
+            //the cubic term isn't negligible next to the linear one, the series is no longer trusted.
+            let (a, c) = (next.a.norm() * radius, next.c.norm() * radius.powi(3));
+            if !(c.is_finite() && c <= 1e-12 * a)
+            {
+                break;
+            }
+            series = next;
+        }
+        series
+    }
+
+    /// dz after 'skip' iterations for offset 'd'.
+    pub fn delta(&self, d: Complex<f64>) -> Complex<f64>
+    {
+        ((self.c * d + self.b) * d + self.a) * d
+    }
+}
+
+/// The escape value (as crate::escape_value gives it) of the pixel at offset 'd' from the reference point.
+pub fn escape_value(reference: &Reference, series: Option<&Series>, d: Complex<f64>, settings: &Settings) -> Option<f64>
+{
+    let smooth = settings.coloring == Coloring::Smooth;
+    let bailout = if smooth { 256.0 * 256.0 } else { 4.0 };
+    let orbit = &reference.orbit;
+    //d is dz_0 for Julia sets and dc otherwise.
+    let zero = Complex{re: 0.0, im: 0.0};
+    let (dz0, dc) = if settings.julia.is_some() { (d, zero) } else { (zero, d) };
+    let (mut n, mut dz) = match series
+    {
+        Some(series) => (series.skip, series.delta(d)),
+        None => (0, dz0),
+    };
+    let mut i = n;
+    while i < settings.limit
+    {
+        let z = orbit[n] + dz;
+        if z.norm_sqr() > bailout
+        {
+            return Some(if smooth { (i as f64 + 1.0 - z.norm().log2().ln() / 2f64.ln()).max(0.0) } else { i as f64 });
+        }
+        //Rebase when z is nearer 0 than Z, or the reference orbit is about to run out.
+        if n + 1 >= orbit.len() || z.norm_sqr() < dz.norm_sqr()
+        {
+            dz = z - orbit[0];
+            n = 0;
+        }
+        dz = 2.0 * orbit[n] * dz + dz * dz + dc;
+        n += 1;
+        i += 1;
+    }
+    None
+}
+
+/// deep::render_rows, by perturbation around one reference orbit, optionally skipping ahead by series approximation.
+pub fn render_rows(pixels: &mut [u8], view: &DeepView, settings: &Settings, threads: usize, series: bool,
+                   progress: &Progress)
+{
+    let channels = settings.color_type().channels();
+    assert!(pixels.len() == view.bounds.0 * view.bounds.1 * channels);
+    let reference = Reference::for_view(view, settings);
+    let offset = |pixel: (usize, usize)|
+    {
+        let point = view.pixel_to_point(pixel);
+        view.approximate(&Complex{re: point.re - &reference.point.re, im: point.im - &reference.point.im})
+    };
+    let radius = [(0, 0), (view.bounds.0, 0), (0, view.bounds.1), view.bounds].iter()
+        .map(|&corner| offset(corner).norm())
+        .fold(0.0, f64::max);
+    let series = series.then(|| Series::new(&reference, radius, settings));
+    parallel_rows(pixels, view.bounds.0 * channels, threads, progress, |top, row|
+    {
+        for (column, pixel) in row.chunks_mut(channels).enumerate()
+        {
+            //A reference that escapes at once (only possible for shallow Julia views) leaves nothing to perturb.
+            let value = if reference.orbit.len() < 2
+            {
+                deep::escape_value(&view.pixel_to_point((column, top)), view.bits, settings)
+            }
+            else
+            {
+                escape_value(&reference, series.as_ref(), offset((column, top)), settings)
+            };
+            paint_escape_value(value, view.approximate(&view.pixel_to_point((column, top))), settings, pixel);
+        }
+    });
+}
+
+#[cfg(test)]
+fn test_view(bounds: (usize, usize), upper_left: &str, lower_right: &str, bits: usize) -> DeepView
+{
+    let corner = |s| deep::parse_exact_complex(s).unwrap();
+    DeepView::from_corners(bounds, &corner(upper_left), &corner(lower_right), bits)
+}
+
+#[test]
+fn test_escape_value_matches_exact()
+{
+    //A view 1e-24 wide at a Misiurewicz point (i), where the exact path is still affordable to compare against.
+    let view = test_view((16, 12), "-0.0000000000000000000000005,1.0000000000000000000000004",
+                         "0.0000000000000000000000005,0.9999999999999999999999996", 128);
+    for settings in [Settings{limit: 400, ..Settings::default()},
+                     Settings{limit: 400, coloring: Coloring::Smooth, ..Settings::default()}]
+    {
+        let reference = Reference::for_view(&view, &settings);
+        let (mut same, mut total) = (0, 0);
+        for (column, row) in (0..16).flat_map(|column| (0..12).map(move |row| (column, row)))
+        {
+            let point = view.pixel_to_point((column, row));
+            let d = view.approximate(&Complex{re: &point.re - &reference.point.re, im: &point.im - &reference.point.im});
+            let exact = deep::escape_value(&point, view.bits, &settings);
+            let perturbed = escape_value(&reference, None, d, &settings);
+            assert_eq!(exact.is_some(), perturbed.is_some(), "({}, {})", column, row);
+            if let (Some(exact), Some(perturbed)) = (exact, perturbed)
+            {
+                assert!((exact - perturbed).abs() < 1e-6, "({}, {}): {} vs {}", column, row, exact, perturbed);
+                same += 1;
+            }
+            total += 1;
+        }
+        assert!(same > total / 2);
+    }
+}
+
+#[test]
+fn test_julia_and_rebasing()
+{
+    //A shallow Julia view, where pixels far from the reference need rebasing to stay accurate.
+    let view = test_view((20, 15), "-1.5,1.1", "1.5,-1.1", 64);
+    let settings = Settings{julia: Some(Complex{re: -0.8, im: 0.156}), limit: 300, ..Settings::default()};
+    let reference = Reference::for_view(&view, &settings);
+    for (column, row) in (0..20).flat_map(|column| (0..15).map(move |row| (column, row)))
+    {
+        let point = view.pixel_to_point((column, row));
+        let d = view.approximate(&Complex{re: &point.re - &reference.point.re, im: &point.im - &reference.point.im});
+        let expected = crate::escape_value(view.approximate(&point), &Settings{early_out: false, ..settings.clone()});
+        let perturbed = escape_value(&reference, None, d, &settings);
+        //Counts can differ by a step where the two round differently right at the escape radius.
+        assert!(match (expected, perturbed)
+                {
+                    (Some(a), Some(b)) => (a - b).abs() <= 1.0,
+                    (a, b) => a == b,
+                }, "({}, {}): {:?} vs {:?}", column, row, expected, perturbed);
+    }
+}
+
+#[test]
+fn test_series_skips_iterations()
+{
+    //Around the center of the period-3 "airship" minibrot a view 2e-30 wide follows the reference for a long
+    //time, and the series can jump most of the way.
+    let view = test_view((12, 9), "-1.754877666246692760049508896359,0.00000000000000000000000000000075",
+                         "-1.754877666246692760049508896357,-0.00000000000000000000000000000075", 160);
+    let settings = Settings{limit: 2000, ..Settings::default()};
+    let reference = Reference::for_view(&view, &settings);
+    let series = Series::new(&reference, 2e-30, &settings);
+    assert!(series.skip > 20, "{}", series.skip);
+    for (column, row) in (0..12).flat_map(|column| (0..9).map(move |row| (column, row)))
+    {
+        let point = view.pixel_to_point((column, row));
+        let d = view.approximate(&Complex{re: &point.re - &reference.point.re, im: &point.im - &reference.point.im});
+        assert_eq!(escape_value(&reference, Some(&series), d, &settings), escape_value(&reference, None, d, &settings));
+    }
+}
+
+#[test]
+fn test_render_rows()
+{
+    let view = test_view((16, 12), "-0.0000000000000000000000005,1.0000000000000000000000004",
+                         "0.0000000000000000000000005,0.9999999999999999999999996", 128);
+    let settings = Settings{limit: 400, coloring: Coloring::Smooth, palette: crate::palette::Palette::builtin("fire"), ..Settings::default()};
+    let mut exact = vec![0u8; 16 * 12 * 3];
+    deep::render_rows(&mut exact, &view, &settings, 2, &Progress::silent());
+    let mut perturbed = vec![0u8; 16 * 12 * 3];
+    render_rows(&mut perturbed, &view, &settings, 2, false, &Progress::silent());
+    let mut series = vec![0u8; 16 * 12 * 3];
+    render_rows(&mut series, &view, &settings, 3, true, &Progress::silent());
+    //Colors match to within rounding.
+    for other in [&perturbed, &series]
+    {
+        assert!(exact.iter().zip(other.iter()).all(|(a, b)| a.abs_diff(*b) <= 1));
+    }
+}
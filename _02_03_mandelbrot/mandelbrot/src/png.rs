@@ -0,0 +1,728 @@
+//! A small, dependency-free PNG encoder and decoder: 8-bit grayscale or RGB, non-interlaced.
+//!
+//! A PNG file is a signature followed by chunks (IHDR header, IDAT image data, IEND). The image data is each
+//! row prefixed with a filter byte, compressed with zlib (a deflate stream plus an Adler-32 checksum); each
+//! chunk ends with a CRC-32. The deflate part here uses LZ77 with the fixed Huffman code from RFC 1951, which
+//! compresses fractal images well enough without the complexity of building per-image Huffman tables.
+//!
+//! The decoder exists so that images can be compared (`mandelbrot compare`). It reads any deflate stream, since
+//! the files may come from other programs, but only the two pixel formats the encoder writes.
+
+use std::io::{self, Write};
+
+/// How many bytes make up one pixel, and how PNG should interpret them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType
+{
+    Gray, //One byte per pixel.
+    Rgb,  //Three bytes per pixel: red, green, blue.
+}
+
+impl ColorType
+{
+    pub fn channels(self) -> usize
+    {
+        match self
+        {
+            ColorType::Gray => 1,
+            ColorType::Rgb => 3,
+        }
+    }
+
+    /// The PNG "color type" field value.
+    fn code(self) -> u8
+    {
+        match self
+        {
+            ColorType::Gray => 0,
+            ColorType::Rgb => 2,
+        }
+    }
+}
+
+/// CRC-32 (the polynomial PNG and zip use), built at compile time by a const fn.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256]
+{
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256
+    {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8
+        {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn crc32(bytes: &[u8]) -> u32
+{
+    !bytes.iter().fold(!0u32, |c, &b| CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+pub fn adler32(bytes: &[u8]) -> u32
+{
+    let (mut a, mut b) = (1u32, 0u32);
+    //5552 is the largest block for which the sums cannot overflow a u32 before the modulo.
+    for block in bytes.chunks(5552)
+    {
+        for &byte in block
+        {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+/// Collects bits least-significant first, which is the order deflate packs them.
+struct BitWriter
+{
+    out: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter
+{
+    fn write(&mut self, bits: u32, count: u32)
+    {
+        self.buffer |= (bits as u64) << self.count;
+        self.count += count;
+        while self.count >= 8
+        {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most-significant bit first, so they go in reversed.
+    fn write_code(&mut self, code: u32, length: u32)
+    {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8>
+    {
+        if self.count > 0
+        {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Write literal/length symbol 'symbol' with the fixed Huffman code (RFC 1951 section 3.2.6).
+fn write_fixed_literal(bits: &mut BitWriter, symbol: u32)
+{
+    match symbol
+    {
+        0..=143 => bits.write_code(0x30 + symbol, 8),
+        144..=255 => bits.write_code(0x190 + symbol - 144, 9),
+        256..=279 => bits.write_code(symbol - 256, 7),
+        _ => bits.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(bits: &mut BitWriter, length: usize, distance: usize)
+{
+    let code = LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1;
+    write_fixed_literal(bits, 257 + code as u32);
+    bits.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    let code = DIST_BASE.partition_point(|&base| base as usize <= distance) - 1;
+    bits.write_code(code as u32, 5);
+    bits.write((distance - DIST_BASE[code] as usize) as u32, DIST_EXTRA[code] as u32);
+}
+
+/// Compress 'data' into a raw deflate stream: one final block, LZ77 matches found through hash chains.
+pub fn deflate(data: &[u8]) -> Vec<u8>
+{
+    const WINDOW: usize = 32768;
+    const HASH_BITS: u32 = 15;
+    const MAX_CHAIN: usize = 32;
+    let hash = |i: usize| ((data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32).wrapping_mul(2_654_435_761) >> (32 - HASH_BITS);
+
+    let mut bits = BitWriter{out: Vec::with_capacity(data.len() / 4), buffer: 0, count: 0};
+    bits.write(1, 1); //BFINAL: this is the last block.
+    bits.write(1, 2); //BTYPE 01: fixed Huffman codes.
+
+    //head[h] is the latest position whose next three bytes hash to h; prev[i % WINDOW] is the one before it.
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]|
+    {
+        if i + 2 < data.len()
+        {
+            let h = hash(i) as usize;
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len()
+    {
+        let mut best = (0, 0);
+        if i + 2 < data.len()
+        {
+            let mut candidate = head[hash(i) as usize];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN
+            {
+                let limit = (data.len() - i).min(258);
+                let length = (0..limit).take_while(|&k| data[candidate + k] == data[i + k]).count();
+                if length > best.0
+                {
+                    best = (length, i - candidate);
+                    if length == limit
+                    {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate
+                {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+        if best.0 >= 3
+        {
+            write_match(&mut bits, best.0, best.1);
+            for k in i..i + best.0
+            {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best.0;
+        }
+        else
+        {
+            write_fixed_literal(&mut bits, data[i] as u32);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    write_fixed_literal(&mut bits, 256); //End of block.
+    bits.finish()
+}
+
+/// A zlib stream: two header bytes, the deflate data, and the Adler-32 of the uncompressed input.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8>
+{
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Reads bits least-significant first, the reverse of BitWriter.
+struct BitReader<'a>
+{
+    bytes: &'a [u8],
+    position: usize, //The next byte to load into 'buffer'.
+    buffer: u64,
+    count: u32,
+}
+
+impl BitReader<'_>
+{
+    fn read(&mut self, count: u32) -> Result<u32, String>
+    {
+        while self.count < count
+        {
+            let &byte = self.bytes.get(self.position).ok_or("compressed data ends early")?;
+            self.buffer |= (byte as u64) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+        let bits = (self.buffer & ((1 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(bits)
+    }
+
+    /// Drop the rest of the current byte; stored blocks start on a byte boundary.
+    fn align(&mut self)
+    {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+}
+
+/// A canonical Huffman code, as deflate describes one: only the code length of each symbol.
+struct Huffman
+{
+    counts: [u16; 16],  //How many codes have each length.
+    symbols: Vec<u16>,  //Symbols ordered by code (shorter codes first, then by symbol).
+}
+
+impl Huffman
+{
+    fn new(lengths: &[u8]) -> Huffman
+    {
+        let mut counts = [0u16; 16];
+        for &length in lengths
+        {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..15
+        {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate().filter(|(_, &length)| length != 0)
+        {
+            symbols[offsets[length as usize] as usize] = symbol as u16;
+            offsets[length as usize] += 1;
+        }
+        Huffman{counts, symbols}
+    }
+
+    /// Read one symbol a bit at a time: the codes of each length are consecutive numbers, starting at 'first'.
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, String>
+    {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..]
+        {
+            code |= bits.read(1)? as usize;
+            if code < first + count as usize
+            {
+                return Ok(self.symbols[index + code - first]);
+            }
+            index += count as usize;
+            first = (first + count as usize) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+/// Decompress a raw deflate stream (RFC 1951): stored, fixed-code and dynamic-code blocks. Stops with an error
+/// as soon as the output would be longer than 'limit' bytes, so a small stream can't expand to fill memory.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, String>
+{
+    let mut bits = BitReader{bytes: data, position: 0, buffer: 0, count: 0};
+    let mut out = Vec::with_capacity(data.len().saturating_mul(4).min(limit));
+    loop
+    {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)?
+        {
+            0 =>
+            {
+                bits.align();
+                let length = bits.read(16)?;
+                if bits.read(16)? != !length & 0xffff
+                {
+                    return Err("corrupt stored block length".to_string());
+                }
+                if out.len() + length as usize > limit
+                {
+                    return Err(too_long(limit));
+                }
+                for _ in 0..length
+                {
+                    out.push(bits.read(8)? as u8);
+                }
+            }
+            1 =>
+            {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(&mut bits, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]), limit)?;
+            }
+            2 =>
+            {
+                let (literals, distances) = read_dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances, limit)?;
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last
+        {
+            return Ok(out);
+        }
+    }
+}
+
+/// The error for output past inflate()'s limit.
+fn too_long(limit: usize) -> String
+{
+    format!("the data decompresses to more than the {} bytes expected", limit)
+}
+
+/// The literal/length and distance codes at the start of a dynamic block, themselves Huffman coded.
+fn read_dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), String>
+{
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let length_count = bits.read(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &ORDER[..length_count]
+    {
+        code_lengths[symbol] = bits.read(3)? as u8;
+    }
+    let lengths_code = Huffman::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count
+    {
+        let (value, repeat) = match lengths_code.decode(bits)?
+        {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("length repeat with nothing to repeat")?, 3 + bits.read(2)?),
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count
+    {
+        return Err("code lengths overrun".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+/// Decode one block's symbols until end-of-block, appending to 'out' (which holds the back-reference window) but
+/// not past 'limit' bytes.
+fn inflate_block(bits: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman, limit: usize)
+                 -> Result<(), String>
+{
+    loop
+    {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol
+        {
+            0..=255 if out.len() >= limit => return Err(too_long(limit)),
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 =>
+            {
+                let code = symbol - 257;
+                let length = LENGTH_BASE[code] as usize + bits.read(LENGTH_EXTRA[code] as u32)? as usize;
+                let code = distances.decode(bits)? as usize;
+                if code >= DIST_BASE.len()
+                {
+                    return Err("invalid distance code".to_string());
+                }
+                let distance = DIST_BASE[code] as usize + bits.read(DIST_EXTRA[code] as u32)? as usize;
+                if distance > out.len()
+                {
+                    return Err("distance reaches before the start of the data".to_string());
+                }
+                if out.len() + length > limit
+                {
+                    return Err(too_long(limit));
+                }
+                //Byte by byte: a match may overlap the bytes it is producing.
+                for _ in 0..length
+                {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+            _ => return Err("invalid length code".to_string()),
+        }
+    }
+}
+
+/// Decompress a zlib stream of at most 'limit' bytes, checking its header and Adler-32.
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String>
+{
+    if data.len() < 6 || data[0] & 0x0f != 8 || !u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31) || data[1] & 0x20 != 0
+    {
+        return Err("not a zlib stream".to_string());
+    }
+    let out = inflate(&data[2..data.len() - 4], limit)?;
+    if adler32(&out).to_be_bytes() != data[data.len() - 4..]
+    {
+        return Err("checksum mismatch".to_string());
+    }
+    Ok(out)
+}
+
+/// The Paeth predictor from the PNG spec: whichever of left, up, upper-left is closest to left + up - upper-left.
+fn paeth(a: u8, b: u8, c: u8) -> u8
+{
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/// Prefix every row with the filter that makes it smallest (by the usual sum-of-absolute-values heuristic).
+fn filter_rows(pixels: &[u8], stride: usize, bpp: usize) -> Vec<u8>
+{
+    let mut out = Vec::with_capacity(pixels.len() + pixels.len() / stride.max(1));
+    let zeros = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+    for (y, row) in pixels.chunks(stride).enumerate()
+    {
+        let up = if y == 0 { &zeros[..] } else { &pixels[(y - 1) * stride..y * stride] };
+        let mut best_score = u64::MAX;
+        let mut best_filter = 0;
+        for filter in 0..5u8
+        {
+            for x in 0..stride
+            {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let c = if x >= bpp { up[x - bpp] } else { 0 };
+                let predicted = match filter
+                {
+                    0 => 0,
+                    1 => a,
+                    2 => up[x],
+                    3 => ((a as u16 + up[x] as u16) / 2) as u8,
+                    _ => paeth(a, up[x], c),
+                };
+                candidate[x] = row[x].wrapping_sub(predicted);
+            }
+            let score: u64 = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+            if score < best_score
+            {
+                best_score = score;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        out.push(best_filter);
+        out.extend_from_slice(&best);
+    }
+    out
+}
+
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()>
+{
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut crc_input = Vec::with_capacity(4 + data.len());
+    crc_input.extend_from_slice(kind);
+    crc_input.extend_from_slice(data);
+    out.write_all(&crc32(&crc_input).to_be_bytes())
+}
+
+/// Encode 'pixels' (rows top to bottom, 'color' channels per pixel) as a PNG of size 'bounds'.
+pub fn encode<W: Write>(out: &mut W, pixels: &[u8], bounds: (usize, usize), color: ColorType) -> io::Result<()>
+{
+    let stride = bounds.0 * color.channels();
+    assert_eq!(pixels.len(), stride * bounds.1, "pixel buffer does not match the image size");
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = Vec::with_capacity(13);
+    header.extend((bounds.0 as u32).to_be_bytes());
+    header.extend((bounds.1 as u32).to_be_bytes());
+    header.extend([8, color.code(), 0, 0, 0]); //Bit depth 8, color type, deflate, adaptive filtering, no interlace.
+    write_chunk(out, b"IHDR", &header)?;
+    let compressed = zlib_compress(&filter_rows(pixels, stride, color.channels()));
+    //Large images are split over several IDAT chunks; readers concatenate them.
+    for part in compressed.chunks(1 << 20)
+    {
+        write_chunk(out, b"IDAT", part)?;
+    }
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Undo filter_rows: 'filtered' is each row prefixed with its filter type byte.
+fn unfilter_rows(filtered: &[u8], stride: usize, bpp: usize, height: usize) -> Result<Vec<u8>, String>
+{
+    if filtered.len() != (stride + 1) * height
+    {
+        return Err("image data is the wrong size".to_string());
+    }
+    let mut pixels = vec![0u8; stride * height];
+    for (y, row) in filtered.chunks(stride + 1).enumerate()
+    {
+        let (done, rest) = pixels.split_at_mut(y * stride);
+        let up = if y == 0 { &[][..] } else { &done[(y - 1) * stride..] };
+        let current = &mut rest[..stride];
+        for x in 0..stride
+        {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = up.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp { up.get(x - bpp).copied().unwrap_or(0) } else { 0 };
+            let prediction = match row[0]
+            {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("unknown filter type {}", row[0])),
+            };
+            current[x] = row[x + 1].wrapping_add(prediction);
+        }
+    }
+    Ok(pixels)
+}
+
+/// A decoded image: its pixels as encode() takes them, its size, and its color type.
+pub type Image = (Vec<u8>, (usize, usize), ColorType);
+
+/// Decode a PNG written by encode(), or any other 8-bit grayscale or RGB PNG without interlacing.
+pub fn decode(file: &[u8]) -> Result<Image, String>
+{
+    let mut rest = file.strip_prefix(b"\x89PNG\r\n\x1a\n").ok_or("not a PNG file")?;
+    let mut header = None;
+    let mut compressed = Vec::new();
+    while rest.len() >= 12
+    {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        if rest.len() < 12 + length
+        {
+            break;
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+        if crc32(&rest[4..8 + length]).to_be_bytes() != rest[8 + length..12 + length]
+        {
+            return Err(format!("bad checksum in {} chunk", String::from_utf8_lossy(kind)));
+        }
+        match kind
+        {
+            b"IHDR" if length == 13 => header = Some(data),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = &rest[12 + length..];
+    }
+    let header = header.ok_or("missing IHDR chunk")?;
+    let bounds = (u32::from_be_bytes(header[..4].try_into().unwrap()) as usize,
+                  u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize);
+    let color = match (header[8], header[9], header[12])
+    {
+        (8, 0, 0) => ColorType::Gray,
+        (8, 2, 0) => ColorType::Rgb,
+        (depth, kind, 0) => return Err(format!("unsupported PNG format (bit depth {}, color type {}); only 8-bit gray and RGB are read", depth, kind)),
+        _ => return Err("interlaced PNGs are not supported".to_string()),
+    };
+    //The header is untrusted: check the size it claims before working anything out from it.
+    if bounds.0 == 0 || bounds.1 == 0
+    {
+        return Err(format!("the header gives a size of {}x{} pixels", bounds.0, bounds.1));
+    }
+    let stride = bounds.0.checked_mul(color.channels());
+    let Some(expected) = stride.and_then(|stride| stride.checked_add(1)).and_then(|row| row.checked_mul(bounds.1)) else
+    {
+        return Err(format!("the image is too large to read ({}x{} pixels)", bounds.0, bounds.1));
+    };
+    //Each row is a filter byte and its pixels; a stream decompressing to more than that is cut off early.
+    let filtered = zlib_decompress(&compressed, expected)?;
+    let pixels = unfilter_rows(&filtered, bounds.0 * color.channels(), color.channels(), bounds.1)?;
+    Ok((pixels, bounds, color))
+}
+
+/// The most bytes encode() can write for an image of size 'bounds': every byte stored as a 9-bit literal, which is
+/// as large as the fixed Huffman code gets. Real fractal images come out far smaller.
+pub fn max_encoded_len(bounds: (usize, usize), color: ColorType) -> u64
+{
+    //Saturates rather than wrapping for sizes no disk could hold, so preflight reports them as not fitting.
+    let filtered = (bounds.0 as u64).checked_mul(color.channels() as u64).and_then(|row| (row + 1).checked_mul(bounds.1 as u64));
+    let Some(zlib) = filtered.and_then(|filtered| filtered.checked_mul(9)).map(|bits| 2 + (bits + 3 + 7).div_ceil(8) + 4)
+    else { return u64::MAX };
+    let chunks = zlib.div_ceil(1 << 20).max(1);
+    8 + 25 + zlib + 12 * chunks + 12
+}
+
+#[test]
+fn test_max_encoded_len()
+{
+    //Noise doesn't compress; the bound holds for it and isn't far off.
+    let mut state = 1u32;
+    let noise: Vec<u8> = (0..300 * 200 * 3).map(|_| { state = state.wrapping_mul(1_103_515_245).wrapping_add(12345); (state >> 24) as u8 }).collect();
+    let mut out = Vec::new();
+    encode(&mut out, &noise, (300, 200), ColorType::Rgb).unwrap();
+    let bound = max_encoded_len((300, 200), ColorType::Rgb);
+    assert!(out.len() as u64 <= bound && out.len() as u64 > bound * 3 / 4, "{} vs {}", out.len(), bound);
+    assert_eq!(max_encoded_len((usize::MAX, 2), ColorType::Rgb), u64::MAX);
+}
+
+#[test]
+fn test_checksums()
+{
+    assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+}
+
+#[test]
+fn test_encode_header()
+{
+    let mut out = Vec::new();
+    encode(&mut out, &[0, 128, 255, 64, 32, 16], (3, 2), ColorType::Gray).unwrap();
+    assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&out[12..16], b"IHDR");
+    assert_eq!(&out[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+    assert_eq!(&out[out.len() - 8..out.len() - 4], b"IEND");
+}
+
+#[test]
+fn test_inflate()
+{
+    //zlib's own output: a dynamic-code block, then a stored block written by hand.
+    let dynamic: Vec<u8> = (0..52).map(|i| u8::from_str_radix(&"78da15c6310e00200803c0aff443cece061a61a103fe3fea72b94958484d1c61eb7b82185221eb3d1b4e5b4eac72b82ea91b10ff"[2 * i..2 * i + 2], 16).unwrap()).collect();
+    assert_eq!(zlib_decompress(&dynamic, 100).unwrap(), b"We choose to go to the Moon in this decade and do");
+    assert_eq!(inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'], 3).unwrap(), b"abc");
+    assert!(inflate(&[0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'], 2).unwrap_err().contains("more than the 2 bytes"));
+    assert!(inflate(&[0x01, 0x03, 0x00, 0x00, 0x00], 3).is_err());
+    let data: Vec<u8> = (0..5000u32).map(|i| (i * i / 7 % 251) as u8).collect();
+    assert_eq!(zlib_decompress(&zlib_compress(&data), data.len()).unwrap(), data);
+    assert!(zlib_decompress(&zlib_compress(&data)[..100], data.len()).is_err());
+    //A long run compresses to a few bytes of back-references; the limit stops it expanding all the way.
+    let bomb = zlib_compress(&vec![0; 1 << 20]);
+    assert!(bomb.len() < 10_000);
+    assert!(zlib_decompress(&bomb, 1000).unwrap_err().contains("more than the 1000 bytes"));
+}
+
+#[test]
+fn test_decode_round_trip()
+{
+    let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 37 % 256) as u8).collect();
+    for (pixels, color) in [(&rgb[..], ColorType::Rgb), (&rgb[..35], ColorType::Gray)]
+    {
+        let mut file = Vec::new();
+        encode(&mut file, pixels, (7, 5), color).unwrap();
+        assert_eq!(decode(&file), Ok((pixels.to_vec(), (7, 5), color)));
+        file[20] ^= 1;
+        assert!(decode(&file).unwrap_err().contains("checksum"));
+    }
+    assert!(decode(b"GIF89a").is_err());
+    //Sizes from a crafted header are errors, not overflows or empty images.
+    for (width, height) in [(u32::MAX, u32::MAX), (0, 5)]
+    {
+        let mut header = [0u8; 13];
+        header[..4].copy_from_slice(&width.to_be_bytes());
+        header[4..8].copy_from_slice(&height.to_be_bytes());
+        header[8..10].copy_from_slice(&[8, 2]);
+        let mut file = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_chunk(&mut file, b"IHDR", &header).unwrap();
+        write_chunk(&mut file, b"IDAT", &zlib_compress(&[0; 16])).unwrap();
+        write_chunk(&mut file, b"IEND", &[]).unwrap();
+        assert!(decode(&file).unwrap_err().contains(&format!("{}x{}", width, height)));
+    }
+    //A small image whose data expands far past its size fails once it passes (1 + 4) * 4 bytes.
+    let mut file = Vec::new();
+    encode(&mut file, &[0; 16], (4, 4), ColorType::Gray).unwrap();
+    let mut bomb = file[..33].to_vec();
+    write_chunk(&mut bomb, b"IDAT", &zlib_compress(&vec![0; 1 << 20])).unwrap();
+    write_chunk(&mut bomb, b"IEND", &[]).unwrap();
+    assert!(decode(&bomb).unwrap_err().contains("more than the 20 bytes"));
+}
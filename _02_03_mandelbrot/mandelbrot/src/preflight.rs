@@ -0,0 +1,114 @@
+//! Checking there is room for a render's output before spending hours on it.
+//!
+//! Output sizes are worst-case estimates (see png::max_encoded_len), summed per filesystem, since two outputs in
+//! different directories may still compete for the same disk. Free space comes from POSIX `df -P`, because std
+//! has no portable call for it; where df isn't available (Windows) or its output doesn't parse, the check is
+//! skipped rather than blocking the render. Small outputs skip it too: spawning df costs more than they do.
+
+use crate::units::NumberFormat;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Outputs smaller than this in total are not checked.
+pub const MIN_CHECKED: u64 = 64 << 20;
+
+/// The filesystem (by mount point) holding 'dir' and the bytes available on it, from `df -P`.
+pub fn free_space(dir: &Path) -> Option<(String, u64)>
+{
+    let output = Command::new("df").arg("-Pk").arg(dir).output().ok()?;
+    if !output.status.success()
+    {
+        return None;
+    }
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parse `df -Pk` output: a header line, then "FILESYSTEM BLOCKS USED AVAILABLE CAPACITY MOUNTPOINT" with
+/// 1024-byte blocks. Mount points may contain spaces, so everything after the fifth field is the mount point.
+pub fn parse_df(output: &str) -> Option<(String, u64)>
+{
+    let line = output.lines().nth(1)?;
+    let mut fields = line.split_whitespace();
+    let available: u64 = fields.nth(3)?.parse().ok()?;
+    fields.next()?;
+    let mount = fields.collect::<Vec<_>>().join(" ");
+    (!mount.is_empty()).then(|| (mount, available.saturating_mul(1024)))
+}
+
+/// Fail with a message, its sizes written in 'numbers', if the outputs (path and most bytes it may take) won't fit
+/// where they are going, using 'free' to find each directory's filesystem and free space.
+pub fn check_with(outputs: &[(PathBuf, u64)], numbers: NumberFormat, free: impl Fn(&Path) -> Option<(String, u64)>) -> Result<(), String>
+{
+    if outputs.iter().map(|(_, size)| size).sum::<u64>() < MIN_CHECKED
+    {
+        return Ok(());
+    }
+    //(mount point, bytes free, bytes needed, the outputs going there)
+    let mut filesystems: Vec<(String, u64, u64, Vec<&Path>)> = Vec::new();
+    for (path, size) in outputs
+    {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let Some((mount, available)) = free(dir) else { continue };
+        match filesystems.iter_mut().find(|filesystem| filesystem.0 == mount)
+        {
+            Some(filesystem) =>
+            {
+                filesystem.2 += size;
+                filesystem.3.push(path);
+            }
+            None => filesystems.push((mount, available, *size, vec![path])),
+        }
+    }
+    for (mount, available, needed, paths) in filesystems
+    {
+        if needed > available
+        {
+            let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+            return Err(format!("not enough disk space on {} for {}: the output may take up to {}, and {} is free",
+                               mount, names.join(" and "), numbers.bytes(needed), numbers.bytes(available)));
+        }
+    }
+    Ok(())
+}
+
+/// check_with, asking df.
+pub fn check(outputs: &[(PathBuf, u64)], numbers: NumberFormat) -> Result<(), String>
+{
+    check_with(outputs, numbers, free_space)
+}
+
+#[test]
+fn test_parse_df()
+{
+    let linux = "Filesystem     1024-blocks     Used Available Capacity Mounted on\n/dev/sda1         41152832 21938924  17100420      57% /\n";
+    assert_eq!(parse_df(linux), Some(("/".to_string(), 17100420 * 1024)));
+    let spaced = "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/disk3 100 50 50 50% /Volumes/My Disk\n";
+    assert_eq!(parse_df(spaced), Some(("/Volumes/My Disk".to_string(), 50 * 1024)));
+    assert_eq!(parse_df("Filesystem\n"), None);
+    assert_eq!(parse_df(""), None);
+}
+
+#[test]
+fn test_check_with()
+{
+    let gib = 1 << 30;
+    //Two outputs on a disk with 3 GiB free, a third on another disk with 1 GiB free.
+    let free = |dir: &Path| match dir.to_str()
+    {
+        Some("/big") | Some("/big/sub") => Some(("/big".to_string(), 3 * gib)),
+        Some("/small") => Some(("/small".to_string(), gib)),
+        _ => None,
+    };
+    let outputs = |sizes: [u64; 3]| vec![(PathBuf::from("/big/a.png"), sizes[0]), (PathBuf::from("/big/sub/b.png"), sizes[1]),
+                                         (PathBuf::from("/small/c.png"), sizes[2])];
+    assert_eq!(check_with(&outputs([gib, gib, gib / 2]), NumberFormat::HUMAN, free), Ok(()));
+    let err = check_with(&outputs([2 * gib, 2 * gib, 0]), NumberFormat::HUMAN, free).unwrap_err();
+    assert!(err.contains("/big/a.png and /big/sub/b.png"), "{}", err);
+    assert!(err.contains("up to 4.00 GiB"), "{}", err);
+    let err = check_with(&outputs([2 * gib, 2 * gib, 0]), NumberFormat::MACHINE, free).unwrap_err();
+    assert!(err.contains("up to 4294967296, and 3221225472 is free"), "{}", err);
+    assert!(check_with(&outputs([0, 0, 2 * gib]), NumberFormat::HUMAN, free).unwrap_err().contains("on /small for /small/c.png"));
+    //Unknown free space and small outputs aren't checked.
+    assert_eq!(check_with(&[(PathBuf::from("/elsewhere/x.png"), 100 * gib)], NumberFormat::HUMAN, free), Ok(()));
+    assert_eq!(check_with(&[(PathBuf::from("/small/x.png"), MIN_CHECKED - 1)], NumberFormat::HUMAN, |_| Some(("/".to_string(), 0))), Ok(()));
+}
@@ -0,0 +1,280 @@
+//! Progress reports while a long render runs.
+//!
+//! On a terminal, a render draws a progress bar on stderr, redrawn in place:
+//!
+//! ```text
+//! render [##############----------------]  47%  3.21 Mpx/s  ETA 0:06
+//! ```
+//!
+//! The speed is in pixels for phases that count image rows (see start_rows), and the ETA is worked out as for
+//! JSON below. `--quiet` turns the bar off, as does stderr being a file or pipe, so logs don't fill with it.
+//!
+//! With `--progress json`, every report is a JSON object on its own line of stderr, so GUIs and CI wrappers can
+//! follow a render without scraping text meant for people:
+//!
+//! ```text
+//! {"event":"start","phase":"render","backend":"series","done":0,"total":1080,"elapsed":0.000,"eta":null}
+//! {"event":"progress","phase":"render","backend":"series","done":212,"total":1080,"elapsed":1.503,"eta":6.154}
+//! {"event":"end","phase":"render","backend":"series","done":1080,"total":1080,"elapsed":7.650,"eta":0.000}
+//! ```
+//!
+//! A run goes through phases one after another ("render", "depth", "write", ...), each counting its own units of
+//! work: image rows for renders, samples for the Buddhabrot. "backend" is what does the work, such as an
+//! Algorithm name. "elapsed" and "eta" are seconds within the phase; the ETA assumes the rest of the phase goes at
+//! its average speed so far, and is null until there is a speed to go by. "progress" events come at most every
+//! quarter second; "start" and "end" always come. Warnings and errors stay plain text on the same stream, so
+//! readers should pass over lines that don't start with '{'.
+
+use crate::units::NumberFormat;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat
+{
+    None, //No reports.
+    Bar,  //A progress bar for people, the default on a terminal.
+    Json, //Newline-delimited JSON events.
+}
+
+impl std::str::FromStr for ProgressFormat
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "none" => Ok(ProgressFormat::None),
+            "bar" => Ok(ProgressFormat::Bar),
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(format!("unknown progress format '{}' (expected none, bar or json)", s)),
+        }
+    }
+}
+
+/// A progress reporter shared by the threads doing a phase's work; they call advance() as units finish.
+pub struct Progress
+{
+    format: ProgressFormat,
+    interval: Duration, //The least time between two "progress" events.
+    numbers: NumberFormat,
+    done: AtomicU64,
+    phase: Mutex<Phase>,
+}
+
+/// The current phase, and where its reports go.
+struct Phase
+{
+    out: Box<dyn Write + Send>,
+    name: &'static str,
+    backend: &'static str,
+    total: u64,
+    pixels_per_unit: u64, //Zero when the units aren't image rows.
+    started: Instant,
+    reported: Instant,
+}
+
+impl Progress
+{
+    /// Reports in 'format' on stderr, with speeds written in 'numbers'.
+    pub fn new(format: ProgressFormat, numbers: NumberFormat) -> Progress
+    {
+        Progress{numbers, ..Progress::with_output(format, Box::new(io::stderr()), Duration::from_millis(250))}
+    }
+
+    /// No reports at all, for callers that don't want them.
+    pub fn silent() -> Progress
+    {
+        Progress::new(ProgressFormat::None, NumberFormat::HUMAN)
+    }
+
+    /// Reports in 'format' written to 'out', no more often than every 'interval'.
+    pub fn with_output(format: ProgressFormat, out: Box<dyn Write + Send>, interval: Duration) -> Progress
+    {
+        let now = Instant::now();
+        let phase = Phase{out, name: "", backend: "", total: 0, pixels_per_unit: 0, started: now, reported: now};
+        Progress{format, interval, numbers: NumberFormat::HUMAN, done: AtomicU64::new(0), phase: Mutex::new(phase)}
+    }
+
+    /// Begin a phase of 'total' units of work, done by 'backend'.
+    pub fn start(&self, name: &'static str, backend: &'static str, total: u64)
+    {
+        self.begin(name, backend, total, 0);
+    }
+
+    /// Begin a phase that works through 'rows' image rows 'width' pixels wide, so speeds can be given in pixels.
+    pub fn start_rows(&self, name: &'static str, backend: &'static str, rows: usize, width: usize)
+    {
+        self.begin(name, backend, rows as u64, width as u64);
+    }
+
+    fn begin(&self, name: &'static str, backend: &'static str, total: u64, pixels_per_unit: u64)
+    {
+        self.done.store(0, Ordering::Relaxed);
+        let mut phase = self.phase.lock().unwrap();
+        (phase.name, phase.backend, phase.total, phase.pixels_per_unit) = (name, backend, total, pixels_per_unit);
+        phase.started = Instant::now();
+        phase.reported = phase.started;
+        self.report(&mut phase, "start", 0);
+    }
+
+    /// Record that 'units' more units of work are done.
+    pub fn advance(&self, units: u64)
+    {
+        let done = self.done.fetch_add(units, Ordering::Relaxed) + units;
+        if self.format == ProgressFormat::None
+        {
+            return;
+        }
+        //Threads finishing at the same moment needn't wait for each other just to find it isn't time to report.
+        let Ok(mut phase) = self.phase.try_lock() else { return };
+        if phase.reported.elapsed() >= self.interval && done < phase.total
+        {
+            phase.reported = Instant::now();
+            self.report(&mut phase, "progress", done);
+        }
+    }
+
+    /// End the current phase.
+    pub fn finish(&self)
+    {
+        let done = self.done.load(Ordering::Relaxed);
+        let mut phase = self.phase.lock().unwrap();
+        self.report(&mut phase, "end", done);
+    }
+
+    fn report(&self, phase: &mut Phase, event: &str, done: u64)
+    {
+        let elapsed = phase.started.elapsed().as_secs_f64();
+        let eta = (done > 0).then(|| elapsed * phase.total.saturating_sub(done) as f64 / done as f64);
+        let line = match self.format
+        {
+            ProgressFormat::None => return,
+            ProgressFormat::Bar => self.bar(phase, event, done, elapsed, eta),
+            ProgressFormat::Json => json(phase, event, done, elapsed, eta),
+        };
+        //A closed stderr is no reason to stop rendering.
+        let _ = phase.out.write_all(line.as_bytes()).and_then(|_| phase.out.flush());
+    }
+
+    /// The bar, drawn over the last one; the end of a phase leaves its bar in place and moves to a new line.
+    fn bar(&self, phase: &Phase, event: &str, done: u64, elapsed: f64, eta: Option<f64>) -> String
+    {
+        const WIDTH: usize = 30;
+        let fraction = if phase.total == 0 { 1.0 } else { (done as f64 / phase.total as f64).min(1.0) };
+        let filled = (fraction * WIDTH as f64) as usize;
+        let mut line = format!("\r{} [{}{}] {:3.0}%", phase.name, "#".repeat(filled), "-".repeat(WIDTH - filled), fraction * 100.0);
+        if phase.pixels_per_unit > 0 && done > 0
+        {
+            let rate = (done * phase.pixels_per_unit) as f64 / elapsed;
+            line += &format!("  {}/s", self.numbers.count(rate as u64, "px"));
+        }
+        match (event, eta)
+        {
+            ("end", _) => line += &format!("  in {}\x1b[K\n", clock(elapsed)),
+            (_, Some(eta)) => line += &format!("  ETA {}\x1b[K", clock(eta)),
+            (_, None) => line += "\x1b[K",
+        }
+        line
+    }
+}
+
+fn json(phase: &Phase, event: &str, done: u64, elapsed: f64, eta: Option<f64>) -> String
+{
+    let eta = eta.map_or("null".to_string(), |eta| format!("{:.3}", eta));
+    format!("{{\"event\":\"{}\",\"phase\":\"{}\",\"backend\":\"{}\",\"done\":{},\"total\":{},\"elapsed\":{:.3},\"eta\":{}}}\n",
+            event, phase.name, phase.backend, done, phase.total, elapsed, eta)
+}
+
+/// Seconds as a clock reading: 0:06, 12:34 or 1:02:03.
+fn clock(seconds: f64) -> String
+{
+    let seconds = seconds.round() as u64;
+    match seconds / 3600
+    {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// A buffer that keeps what a Progress writes to it, for tests to read back.
+#[cfg(test)]
+#[derive(Clone)]
+struct Shared(std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Shared
+{
+    fn text(&self) -> String
+    {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for Shared
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// A Progress reporting in 'format' with no rate limit, and the buffer its reports go to.
+#[cfg(test)]
+fn capture(format: ProgressFormat) -> (Progress, Shared)
+{
+    let shared = Shared(Default::default());
+    (Progress::with_output(format, Box::new(shared.clone()), Duration::ZERO), shared)
+}
+
+#[test]
+fn test_progress_json()
+{
+    let (progress, shared) = capture(ProgressFormat::Json);
+    progress.start("render", "f64", 4);
+    progress.advance(1);
+    progress.advance(3);
+    progress.finish();
+    progress.start("write", "png", 1);
+    progress.finish();
+    let output = shared.text();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 5, "{}", output);
+    assert!(lines[0].starts_with(r#"{"event":"start","phase":"render","backend":"f64","done":0,"total":4,"#));
+    assert!(lines[0].ends_with(r#""eta":null}"#));
+    assert!(lines[1].starts_with(r#"{"event":"progress","phase":"render","backend":"f64","done":1,"total":4,"#));
+    //The last unit finishes the phase, so it is reported by "end" rather than by a "progress" event.
+    assert!(lines[2].starts_with(r#"{"event":"end","phase":"render","backend":"f64","done":4,"total":4,"#));
+    assert!(lines[2].ends_with(r#""eta":0.000}"#));
+    assert!(lines[4].starts_with(r#"{"event":"end","phase":"write","backend":"png","done":0,"#));
+
+    assert_eq!("json".parse(), Ok(ProgressFormat::Json));
+    assert!("xml".parse::<ProgressFormat>().is_err());
+}
+
+#[test]
+fn test_progress_bar()
+{
+    let (progress, shared) = capture(ProgressFormat::Bar);
+    progress.start_rows("render", "f64", 4, 1000);
+    progress.advance(1);
+    progress.advance(3);
+    progress.finish();
+    let output = shared.text();
+    let bars: Vec<&str> = output.split('\r').skip(1).collect();
+    assert_eq!(bars.len(), 3, "{:?}", output);
+    assert!(bars[0].starts_with("render [------------------------------]   0%"), "{:?}", bars[0]);
+    assert!(bars[1].starts_with("render [#######-----------------------]  25%"), "{:?}", bars[1]);
+    assert!(bars[1].contains("px/s  ETA "), "{:?}", bars[1]);
+    assert!(bars[2].starts_with("render [##############################] 100%") && bars[2].ends_with("\n"), "{:?}", bars[2]);
+    assert_eq!((clock(6.4), clock(754.0), clock(3723.0)), ("0:06".to_string(), "12:34".to_string(), "1:02:03".to_string()));
+}
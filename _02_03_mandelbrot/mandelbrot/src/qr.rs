@@ -0,0 +1,527 @@
+//! A minimal QR code encoder, enough to stamp share links onto rendered images.
+//!
+//! Supports byte mode at error correction level M, versions 1 through 10 (up to 213 bytes), which covers any
+//! mandel:// link. The steps are the ones in ISO/IEC 18004: build the data bit stream, split it into blocks and
+//! append Reed-Solomon error correction to each, interleave the blocks, draw the fixed patterns, lay the bits out
+//! in the zigzag order, and pick the data mask with the lowest penalty score.
+
+/// Per-version layout at level M: (error correction codewords per block, [(number of blocks, data codewords each)]).
+const BLOCKS_M: [(usize, [(usize, usize); 2]); 10] = [
+    (10, [(1, 16), (0, 0)]),
+    (16, [(1, 28), (0, 0)]),
+    (26, [(1, 44), (0, 0)]),
+    (18, [(2, 32), (0, 0)]),
+    (24, [(2, 43), (0, 0)]),
+    (16, [(4, 27), (0, 0)]),
+    (18, [(4, 31), (0, 0)]),
+    (22, [(2, 38), (2, 39)]),
+    (22, [(3, 36), (2, 37)]),
+    (26, [(4, 43), (1, 44)]),
+];
+
+/// Centers of the alignment patterns for versions 2..=10 (version 1 has none).
+const ALIGNMENT: [&[usize]; 10] = [
+    &[], &[6, 18], &[6, 22], &[6, 26], &[6, 30], &[6, 34], &[6, 22, 38], &[6, 24, 42], &[6, 26, 46], &[6, 28, 50],
+];
+
+/// A square grid of modules; true is dark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrCode
+{
+    pub size: usize,
+    modules: Vec<bool>,
+}
+
+impl QrCode
+{
+    pub fn get(&self, x: usize, y: usize) -> bool
+    {
+        self.modules[y * self.size + x]
+    }
+
+    /// Encode 'data' in byte mode, choosing the smallest version that fits. Returns None if it is too long.
+    pub fn encode(data: &[u8]) -> Option<QrCode>
+    {
+        let version = (1..=10).find(|&v| data_capacity(v) * 8 >= 4 + count_bits(v) + data.len() * 8)?;
+        let codewords = add_error_correction(&data_codewords(data, version), version);
+        let mut builder = Builder::new(version);
+        builder.draw_function_patterns();
+        builder.draw_codewords(&codewords);
+        //Try every mask and keep the one the penalty rules like best.
+        let best = (0..8)
+            .map(|mask|
+            {
+                let mut candidate = builder.clone();
+                candidate.apply_mask(mask);
+                candidate.draw_format(mask);
+                (candidate.penalty(), candidate)
+            })
+            .min_by_key(|(penalty, _)| *penalty)
+            .map(|(_, candidate)| candidate)?;
+        Some(QrCode{size: best.size, modules: best.modules})
+    }
+}
+
+fn data_capacity(version: usize) -> usize
+{
+    let (_, groups) = BLOCKS_M[version - 1];
+    groups.iter().map(|(blocks, data)| blocks * data).sum()
+}
+
+/// Width of the byte-mode character count field.
+fn count_bits(version: usize) -> usize
+{
+    if version < 10 { 8 } else { 16 }
+}
+
+/// Mode indicator, length, data, terminator, and the 0xEC/0x11 padding, packed into codewords.
+fn data_codewords(data: &[u8], version: usize) -> Vec<u8>
+{
+    let capacity = data_capacity(version);
+    let mut bits: Vec<bool> = Vec::with_capacity(capacity * 8);
+    let mut push = |value: usize, count: usize|
+    {
+        for i in (0..count).rev()
+        {
+            bits.push((value >> i) & 1 == 1);
+        }
+    };
+    push(0b0100, 4); //Byte mode.
+    push(data.len(), count_bits(version));
+    for &byte in data
+    {
+        push(byte as usize, 8);
+    }
+    let terminator = (capacity * 8 - bits.len()).min(4);
+    bits.extend(std::iter::repeat_n(false, terminator));
+    while !bits.len().is_multiple_of(8)
+    {
+        bits.push(false);
+    }
+    let mut codewords: Vec<u8> = bits.chunks(8).map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | bit as u8)).collect();
+    for pad in [0xec, 0x11].into_iter().cycle()
+    {
+        if codewords.len() >= capacity
+        {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+/// Multiplication in GF(256) with the QR polynomial x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_mul(mut a: u8, mut b: u8) -> u8
+{
+    let mut product = 0u8;
+    while b != 0
+    {
+        if b & 1 != 0
+        {
+            product ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1d } else { 0 };
+        b >>= 1;
+    }
+    product
+}
+
+/// Reed-Solomon error correction codewords for one block: the remainder of data(x) * x^n divided by the
+/// generator polynomial (x - 1)(x - α)...(x - α^(n-1)).
+pub fn reed_solomon(data: &[u8], n: usize) -> Vec<u8>
+{
+    let mut generator = vec![1u8];
+    let mut root = 1u8;
+    for _ in 0..n
+    {
+        let mut next = vec![0u8; generator.len() + 1];
+        for (i, &g) in generator.iter().enumerate()
+        {
+            next[i] ^= g;
+            next[i + 1] ^= gf_mul(g, root);
+        }
+        generator = next;
+        root = gf_mul(root, 2);
+    }
+    let mut remainder = vec![0u8; n];
+    for &byte in data
+    {
+        let factor = byte ^ remainder[0];
+        remainder.remove(0);
+        remainder.push(0);
+        for i in 0..n
+        {
+            remainder[i] ^= gf_mul(generator[i + 1], factor);
+        }
+    }
+    remainder
+}
+
+/// Split into blocks, append each block's error correction, and interleave as the standard requires.
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8>
+{
+    let (ec_len, groups) = BLOCKS_M[version - 1];
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    for (count, len) in groups
+    {
+        for _ in 0..count
+        {
+            blocks.push(&data[offset..offset + len]);
+            offset += len;
+        }
+    }
+    let ec: Vec<Vec<u8>> = blocks.iter().map(|block| reed_solomon(block, ec_len)).collect();
+    let longest = blocks.iter().map(|block| block.len()).max().unwrap_or(0);
+    let mut out = Vec::new();
+    for i in 0..longest
+    {
+        out.extend(blocks.iter().filter_map(|block| block.get(i)));
+    }
+    for i in 0..ec_len
+    {
+        out.extend(ec.iter().map(|block| block[i]));
+    }
+    out
+}
+
+/// BCH-encode 'value' with the given generator: value shifted left by the generator's degree, plus the remainder.
+fn bch(value: u32, generator: u32) -> u32
+{
+    let degree = 31 - generator.leading_zeros();
+    let mut remainder = value << degree;
+    while remainder.leading_zeros() <= generator.leading_zeros()
+    {
+        remainder ^= generator << (generator.leading_zeros() - remainder.leading_zeros());
+    }
+    value << degree | remainder
+}
+
+/// The 15 format bits for level M and 'mask', already XORed with the standard 0x5412 pattern.
+pub fn format_bits(mask: u8) -> u32
+{
+    bch(mask as u32, 0x537) ^ 0x5412 //Level M is 00, so the level bits contribute nothing.
+}
+
+/// The 18 version information bits (only drawn for version 7 and up).
+pub fn version_bits(version: usize) -> u32
+{
+    bch(version as u32, 0x1f25)
+}
+
+#[derive(Clone)]
+struct Builder
+{
+    version: usize,
+    size: usize,
+    modules: Vec<bool>,
+    reserved: Vec<bool>, //Function pattern modules, which data and masks must not touch.
+}
+
+impl Builder
+{
+    fn new(version: usize) -> Builder
+    {
+        let size = 17 + 4 * version;
+        Builder{version, size, modules: vec![false; size * size], reserved: vec![false; size * size]}
+    }
+
+    fn set(&mut self, x: usize, y: usize, dark: bool)
+    {
+        self.modules[y * self.size + x] = dark;
+        self.reserved[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self)
+    {
+        let size = self.size;
+        //Finder patterns with their white separators, in three corners.
+        for (cx, cy) in [(3, 3), (size - 4, 3), (3, size - 4)]
+        {
+            for dy in -4i32..=4
+            {
+                for dx in -4i32..=4
+                {
+                    let (x, y) = (cx as i32 + dx, cy as i32 + dy);
+                    if x < 0 || y < 0 || x >= size as i32 || y >= size as i32
+                    {
+                        continue;
+                    }
+                    let ring = dx.abs().max(dy.abs());
+                    self.set(x as usize, y as usize, ring != 2 && ring != 4);
+                }
+            }
+        }
+        //Timing patterns.
+        for i in 8..size - 8
+        {
+            self.set(i, 6, i % 2 == 0);
+            self.set(6, i, i % 2 == 0);
+        }
+        //Alignment patterns, except where they would overlap a finder.
+        let centers = ALIGNMENT[self.version - 1];
+        for &cy in centers
+        {
+            for &cx in centers
+            {
+                //The three corners already hold finder patterns.
+                if cx.min(cy) == 6 && (cx.max(cy) == 6 || cx.max(cy) == size - 7)
+                {
+                    continue;
+                }
+                for dy in -2i32..=2
+                {
+                    for dx in -2i32..=2
+                    {
+                        self.set((cx as i32 + dx) as usize, (cy as i32 + dy) as usize, dx.abs().max(dy.abs()) != 1);
+                    }
+                }
+            }
+        }
+        //Reserve the format areas (filled in per mask later) and the always-dark module.
+        self.draw_format(0);
+        self.set(8, size - 8, true);
+        //Version information blocks.
+        if self.version >= 7
+        {
+            let bits = version_bits(self.version);
+            for i in 0..18
+            {
+                let dark = (bits >> i) & 1 == 1;
+                let (a, b) = (i / 3, size - 11 + i % 3);
+                self.set(a, b, dark);
+                self.set(b, a, dark);
+            }
+        }
+    }
+
+    /// Draw both copies of the format information for 'mask'.
+    fn draw_format(&mut self, mask: u8)
+    {
+        let bits = format_bits(mask);
+        let size = self.size;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+        //First copy, around the top-left finder.
+        for i in 0..6
+        {
+            self.set(8, i, bit(i));
+        }
+        self.set(8, 7, bit(6));
+        self.set(8, 8, bit(7));
+        self.set(7, 8, bit(8));
+        for i in 9..15
+        {
+            self.set(14 - i, 8, bit(i));
+        }
+        //Second copy, split between the other two finders.
+        for i in 0..8
+        {
+            self.set(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15
+        {
+            self.set(8, size - 15 + i, bit(i));
+        }
+    }
+
+    /// The data module positions in placement order: two-module-wide columns from the right edge leftwards,
+    /// alternately upwards and downwards, skipping the vertical timing pattern and reserved modules.
+    fn zigzag(&self) -> Vec<(usize, usize)>
+    {
+        let size = self.size;
+        let mut order = Vec::new();
+        let mut right = size - 1;
+        loop
+        {
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size
+            {
+                let y = if upward { size - 1 - vertical } else { vertical };
+                for x in [right, right - 1]
+                {
+                    if !self.reserved[y * size + x]
+                    {
+                        order.push((x, y));
+                    }
+                }
+            }
+            match right
+            {
+                1 => return order,
+                8 => right = 5, //Step over the timing column.
+                _ => right -= 2,
+            }
+        }
+    }
+
+    fn draw_codewords(&mut self, codewords: &[u8])
+    {
+        //Any modules left over after the last codeword are remainder bits, which stay light.
+        for (bit, (x, y)) in self.zigzag().into_iter().take(codewords.len() * 8).enumerate()
+        {
+            self.modules[y * self.size + x] = (codewords[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u8)
+    {
+        for y in 0..self.size
+        {
+            for x in 0..self.size
+            {
+                let flip = match mask
+                {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if flip && !self.reserved[y * self.size + x]
+                {
+                    self.modules[y * self.size + x] ^= true;
+                }
+            }
+        }
+    }
+
+    /// The four penalty rules from the standard: long runs, 2x2 blocks, finder-like patterns, and dark/light balance.
+    fn penalty(&self) -> usize
+    {
+        let size = self.size;
+        let at = |x: usize, y: usize| self.modules[y * size + x];
+        let mut score = 0;
+        for transpose in [false, true]
+        {
+            for a in 0..size
+            {
+                let line: Vec<bool> = (0..size).map(|b| if transpose { at(a, b) } else { at(b, a) }).collect();
+                let mut run = 1;
+                for b in 1..=size
+                {
+                    if b < size && line[b] == line[b - 1]
+                    {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5
+                    {
+                        score += run - 2;
+                    }
+                    run = 1;
+                }
+                for window in line.windows(11)
+                {
+                    let pattern = [true, false, true, true, true, false, true, false, false, false, false];
+                    let reversed = [false, false, false, false, true, false, true, true, true, false, true];
+                    if window == pattern || window == reversed
+                    {
+                        score += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1
+        {
+            for x in 0..size - 1
+            {
+                let c = at(x, y);
+                if at(x + 1, y) == c && at(x, y + 1) == c && at(x + 1, y + 1) == c
+                {
+                    score += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&m| m).count();
+        let percent = dark * 100 / self.modules.len();
+        score + percent.abs_diff(50) / 5 * 10
+    }
+}
+
+/// Draw 'code' into an image buffer with 'channels' bytes per pixel, at pixel (left, top), each module 'scale'
+/// pixels wide and surrounded by the 4-module white quiet zone scanners need. Parts outside the image are clipped.
+pub fn stamp(pixels: &mut [u8], bounds: (usize, usize), channels: usize, code: &QrCode, left: usize, top: usize, scale: usize)
+{
+    let extent = (code.size + 8) * scale;
+    for y in top..(top + extent).min(bounds.1)
+    {
+        for x in left..(left + extent).min(bounds.0)
+        {
+            let (mx, my) = ((x - left) / scale, (y - top) / scale);
+            let dark = (4..code.size + 4).contains(&mx) && (4..code.size + 4).contains(&my) && code.get(mx - 4, my - 4);
+            let index = (y * bounds.0 + x) * channels;
+            pixels[index..index + channels].fill(if dark { 0 } else { 255 });
+        }
+    }
+}
+
+#[test]
+fn test_reed_solomon_known_vector()
+{
+    //The "HELLO WORLD" 1-M example data codewords and their published error correction codewords.
+    let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+    assert_eq!(reed_solomon(&data, 10), vec![196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+}
+
+#[test]
+fn test_format_and_version_bits()
+{
+    assert_eq!(format_bits(0), 0b101010000010010);
+    assert_eq!(format_bits(5), 0b100000011001110);
+    assert_eq!(format_bits(7), 0b100101010100000);
+    assert_eq!(version_bits(7), 0x07c94);
+    assert_eq!(version_bits(10), 0x0a4d3);
+}
+
+#[test]
+fn test_encode_sizes()
+{
+    assert_eq!(QrCode::encode(b"mandel://-0.5/0/1e0/255").unwrap().size, 25);
+    let long = vec![b'x'; 150];
+    assert_eq!(QrCode::encode(&long).unwrap().size, 17 + 4 * 8);
+    assert!(QrCode::encode(&[b'x'; 300]).is_none());
+}
+
+/// Read the codewords back out of encoded symbols, undoing whichever mask their format bits name, and check
+/// they are exactly the interleaved data and error correction codewords.
+#[test]
+fn test_encode_reads_back()
+{
+    for text in ["mandel://-0.743643887037151/0.13182590420533/1.5e7/5000/fire", "x", &"y".repeat(120), &"z".repeat(200)]
+    {
+        let code = QrCode::encode(text.as_bytes()).unwrap();
+        let version = (code.size - 17) / 4;
+        let mask = (0..8)
+            .find(|&mask|
+            {
+                let mut format = Builder::new(version);
+                format.draw_format(mask);
+                (0..code.size * code.size).all(|i| !format.reserved[i] || format.modules[i] == code.modules[i])
+            })
+            .expect("format bits name one of the masks");
+        let mut reader = Builder::new(version);
+        reader.draw_function_patterns();
+        reader.modules = code.modules.clone();
+        reader.apply_mask(mask);
+        let bits: Vec<bool> = reader.zigzag().iter().map(|&(x, y)| reader.modules[y * code.size + x]).collect();
+        let codewords: Vec<u8> = bits.chunks_exact(8).map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | bit as u8)).collect();
+        assert_eq!(codewords, add_error_correction(&data_codewords(text.as_bytes(), version), version));
+    }
+}
+
+#[test]
+fn test_stamp()
+{
+    let code = QrCode::encode(b"mandel://0/0/1e0/100").unwrap();
+    let bounds = (100, 100);
+    let mut pixels = vec![128u8; bounds.0 * bounds.1 * 3];
+    stamp(&mut pixels, bounds, 3, &code, 40, 50, 2);
+    let at = |x: usize, y: usize| &pixels[(y * bounds.0 + x) * 3..(y * bounds.0 + x) * 3 + 3];
+    assert_eq!(at(39, 50), [128, 128, 128]); //Left of the stamp.
+    assert_eq!(at(40, 50), [255, 255, 255]); //Quiet zone.
+    assert_eq!(at(48, 58), [0, 0, 0]);       //Corner of the top-left finder pattern.
+    assert_eq!(at(99, 99), [255, 255, 255]); //Clipped quiet zone at the image edge.
+}
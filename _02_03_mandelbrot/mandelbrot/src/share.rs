@@ -0,0 +1,232 @@
+//! Compact share links for a view of the set: `mandel://RE/IM/ZOOM/MAXITER[/PALETTE][?PARAMETERS]`.
+//!
+//! RE and IM are the center point, ZOOM is 4 divided by the width of the view on the real axis (so zoom 1
+//! shows the classic -2..2 range), MAXITER is the iteration limit, and PALETTE optionally names a color palette.
+//! Anything that is not a plain Mandelbrot view goes in `key=value` parameters after a '?', separated by '&', so
+//! older links stay valid as new modes are added. `julia=RE,IM` selects the Julia set for that constant,
+//! `power=D` the exponent of z = z^D + c, `fractal=NAME` a formula other than the Mandelbrot set's, and
+//! `newton=POLYNOMIAL` the Newton fractal of a polynomial such as z^3-1.
+//! Links are short enough to paste in chat and round-trip exactly, because f64's Display prints the shortest
+//! decimal that parses back to the same number.
+
+use crate::parse::{parse_value, ParseError, ParseErrorKind};
+use crate::viewport::Viewport;
+use crate::newton::{parse_polynomial, Polynomial};
+use crate::Fractal;
+use num::Complex;
+use std::fmt;
+use std::ops::Range;
+
+pub const SCHEME: &str = "mandel://";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShareLink
+{
+    pub center: Complex<f64>,
+    pub zoom: f64,
+    pub max_iter: usize,
+    pub palette: Option<String>,
+    pub julia: Option<Complex<f64>>,
+    pub power: f64,
+    pub fractal: Fractal,
+    pub newton: Option<Polynomial>,
+}
+
+impl ShareLink
+{
+    /// Describe an existing view. The aspect ratio is not part of the link; the receiver picks an image size.
+    pub fn from_viewport(view: &Viewport, max_iter: usize, palette: Option<String>) -> ShareLink
+    {
+        ShareLink{center: view.center, zoom: 4.0 / view.width, max_iter, palette, julia: None, power: 2.0,
+                  fractal: Fractal::Mandelbrot, newton: None}
+    }
+
+    /// The view this link describes at a given image size, with square pixels.
+    pub fn viewport(&self, bounds: (usize, usize)) -> Viewport
+    {
+        Viewport::from_zoom(bounds, self.center, self.zoom)
+    }
+}
+
+impl fmt::Display for ShareLink
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}{}/{}/{:e}/{}", SCHEME, self.center.re, self.center.im, self.zoom, self.max_iter)?;
+        if let Some(palette) = &self.palette
+        {
+            write!(f, "/{}", palette)?;
+        }
+        let mut parameters = Vec::new();
+        if let Some(c) = self.julia
+        {
+            parameters.push(format!("julia={},{}", c.re, c.im));
+        }
+        if self.power != 2.0
+        {
+            parameters.push(format!("power={}", self.power));
+        }
+        if self.fractal != Fractal::Mandelbrot
+        {
+            parameters.push(format!("fractal={}", self.fractal.name()));
+        }
+        if let Some(polynomial) = &self.newton
+        {
+            parameters.push(format!("newton={}", polynomial));
+        }
+        if !parameters.is_empty()
+        {
+            write!(f, "?{}", parameters.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a share link. Errors carry spans into 's' like the other parsers in crate::parse.
+pub fn parse_share_link(s: &str) -> Result<ShareLink, ParseError>
+{
+    let (path, query) = match s.find('?')
+    {
+        Some(index) => (&s[..index], Some(index + 1..s.len())),
+        None => (s, None),
+    };
+    let trimmed = path.trim_end_matches('/');
+    if !trimmed.starts_with(SCHEME)
+    {
+        return Err(ParseError::new(ParseErrorKind::Expected("a link starting with mandel://"), 0..s.len().min(SCHEME.len())));
+    }
+    let mut ranges = Vec::new();
+    let mut start = SCHEME.len();
+    for (index, _) in trimmed[SCHEME.len()..].match_indices('/')
+    {
+        ranges.push(start..SCHEME.len() + index);
+        start = SCHEME.len() + index + 1;
+    }
+    ranges.push(start..trimmed.len());
+    if ranges.len() < 4
+    {
+        return Err(ParseError::new(ParseErrorKind::MissingSeparator('/'), SCHEME.len()..trimmed.len()));
+    }
+    if ranges.len() > 5
+    {
+        let extra = ranges[5].start - 1;
+        return Err(ParseError::new(ParseErrorKind::ExtraSeparator('/'), extra..extra + 1));
+    }
+    let re: f64 = parse_value(s, ranges[0].clone())?;
+    let im: f64 = parse_value(s, ranges[1].clone())?;
+    let zoom: f64 = parse_value(s, ranges[2].clone())?;
+    if !(zoom.is_finite() && zoom > 0.0)
+    {
+        return Err(ParseError::new(ParseErrorKind::OutOfRange, ranges[2].clone()));
+    }
+    let max_iter: usize = parse_value(s, ranges[3].clone())?;
+    if max_iter == 0
+    {
+        return Err(ParseError::new(ParseErrorKind::OutOfRange, ranges[3].clone()));
+    }
+    let palette = ranges.get(4).map(|range| s[range.clone()].to_string()).filter(|name| !name.is_empty());
+    let mut link = ShareLink{center: Complex{re, im}, zoom, max_iter, palette, julia: None, power: 2.0,
+                             fractal: Fractal::Mandelbrot, newton: None};
+    if let Some(query) = query
+    {
+        parse_parameters(s, query, &mut link)?;
+    }
+    Ok(link)
+}
+
+/// Parse the `key=value&...` parameters in s[query] into 'link'.
+fn parse_parameters(s: &str, query: Range<usize>, link: &mut ShareLink) -> Result<(), ParseError>
+{
+    let mut start = query.start;
+    for parameter in s[query].split('&')
+    {
+        let span = start..start + parameter.len();
+        start = span.end + 1;
+        let (key, value) = match parameter.find('=')
+        {
+            Some(index) => (&parameter[..index], span.start + index + 1..span.end),
+            None => return Err(ParseError::new(ParseErrorKind::MissingSeparator('='), span)),
+        };
+        match key
+        {
+            "julia" =>
+            {
+                let comma = s[value.clone()].find(',')
+                    .ok_or_else(|| ParseError::new(ParseErrorKind::MissingSeparator(','), value.clone()))?;
+                let re = parse_value(s, value.start..value.start + comma)?;
+                let im = parse_value(s, value.start + comma + 1..value.end)?;
+                link.julia = Some(Complex{re, im});
+            }
+            "power" =>
+            {
+                link.power = parse_value(s, value.clone())?;
+                if !(link.power.is_finite() && link.power > 1.0)
+                {
+                    return Err(ParseError::new(ParseErrorKind::OutOfRange, value));
+                }
+            }
+            "fractal" =>
+            {
+                link.fractal = s[value.clone()].parse().map_err(|_| ParseError::new(ParseErrorKind::Expected("a fractal name"), value))?;
+            }
+            "newton" =>
+            {
+                //Errors from the polynomial parser point into the value; shift them to point into the whole link.
+                let polynomial = parse_polynomial(&s[value.clone()])
+                    .map_err(|err| ParseError::new(err.kind, value.start + err.span.start..value.start + err.span.end))?;
+                link.newton = Some(polynomial);
+            }
+            _ => return Err(ParseError::new(ParseErrorKind::Expected("a known link parameter (julia, power, fractal, newton)"),
+                                            span.start..span.start + key.len())),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_share_link_round_trip()
+{
+    let link = ShareLink{center: Complex{re: -0.743643887037151, im: 0.13182590420533}, zoom: 1.5e7, max_iter: 5000,
+                         palette: Some("fire".to_string()), julia: None, power: 2.0, fractal: Fractal::Mandelbrot, newton: None};
+    let text = link.to_string();
+    assert_eq!(text, "mandel://-0.743643887037151/0.13182590420533/1.5e7/5000/fire");
+    assert_eq!(parse_share_link(&text), Ok(link.clone()));
+    let plain = parse_share_link("mandel://-0.5/0/1/255/").unwrap();
+    assert_eq!(plain.palette, None);
+    assert_eq!(plain.to_string(), "mandel://-0.5/0/1e0/255");
+    let julia = ShareLink{julia: Some(Complex{re: -0.8, im: 0.156}), palette: None, ..link};
+    assert_eq!(julia.to_string(), "mandel://-0.743643887037151/0.13182590420533/1.5e7/5000?julia=-0.8,0.156");
+    assert_eq!(parse_share_link(&julia.to_string()), Ok(julia.clone()));
+    let cubic = ShareLink{power: 3.0, fractal: Fractal::Tricorn, ..julia};
+    assert_eq!(cubic.to_string(), "mandel://-0.743643887037151/0.13182590420533/1.5e7/5000?julia=-0.8,0.156&power=3&fractal=tricorn");
+    assert_eq!(parse_share_link(&cubic.to_string()), Ok(cubic));
+    let newton = ShareLink{newton: Some(parse_polynomial("z^4 - 2i*z + 1").unwrap()), ..plain};
+    assert_eq!(newton.to_string(), "mandel://-0.5/0/1e0/255?newton=z^4-2i*z+1");
+    assert_eq!(parse_share_link(&newton.to_string()), Ok(newton));
+}
+
+#[test]
+fn test_share_link_errors()
+{
+    assert_eq!(parse_share_link("http://x").unwrap_err().kind, ParseErrorKind::Expected("a link starting with mandel://"));
+    assert_eq!(parse_share_link("mandel://1/2/3").unwrap_err().kind, ParseErrorKind::MissingSeparator('/'));
+    assert_eq!(parse_share_link("mandel://1/2/3/4/a/b").unwrap_err().span, 18..19);
+    assert_eq!(parse_share_link("mandel://1/2/0/4").unwrap_err(), ParseError::new(ParseErrorKind::OutOfRange, 13..14));
+    assert_eq!(parse_share_link("mandel://1/x/1/4").unwrap_err(), ParseError::new(ParseErrorKind::InvalidNumber, 11..12));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?julia=0.5").unwrap_err(), ParseError::new(ParseErrorKind::MissingSeparator(','), 23..26));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?julia=0,x").unwrap_err(), ParseError::new(ParseErrorKind::InvalidNumber, 25..26));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?zoom=3").unwrap_err().span, 17..21);
+    assert_eq!(parse_share_link("mandel://1/2/1/4?julia").unwrap_err().kind, ParseErrorKind::MissingSeparator('='));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?power=1").unwrap_err(), ParseError::new(ParseErrorKind::OutOfRange, 23..24));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?newton=z^3+x").unwrap_err(), ParseError::new(ParseErrorKind::Expected("a term like 3z^2"), 28..29));
+    assert_eq!(parse_share_link("mandel://1/2/1/4?fractal=x").unwrap_err(), ParseError::new(ParseErrorKind::Expected("a fractal name"), 25..26));
+}
+
+#[test]
+fn test_share_link_viewport()
+{
+    let view = parse_share_link("mandel://-0.5/0/2/100").unwrap().viewport((400, 200));
+    assert_eq!((view.width, view.height), (2.0, 1.0));
+    let back = ShareLink::from_viewport(&view, 100, None);
+    assert_eq!(back.zoom, 2.0);
+}
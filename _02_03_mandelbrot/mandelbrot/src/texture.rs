@@ -0,0 +1,175 @@
+//! Seamlessly tileable textures, for game and engine materials.
+//!
+//! Two ways of hiding the seams are offered. Mirror tiling renders the region at half size and reflects it into
+//! the four quadrants, so opposite edges are copies of each other; it is exact but makes the texture
+//! symmetric. Offset blending keeps the render as it is and crossfades it with a copy shifted by half its size
+//! (wrapping around), weighted so the shifted copy takes over at the edges. The shifted copy's edges meet in the
+//! middle of the original, where neighbouring pixels already match, so the result wraps without a visible seam.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileMode
+{
+    Mirror, //Reflect a half-size render into four quadrants.
+    Blend,  //Crossfade with a half-offset copy near the edges.
+}
+
+impl std::str::FromStr for TileMode
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TileMode, String>
+    {
+        match s
+        {
+            "mirror" => Ok(TileMode::Mirror),
+            "blend" => Ok(TileMode::Blend),
+            _ => Err(format!("unknown tiling mode '{}' (expected mirror or blend)", s)),
+        }
+    }
+}
+
+/// The size to render for a texture of 'bounds': half size (rounded up) for mirroring, full size for blending.
+pub fn render_bounds(mode: TileMode, bounds: (usize, usize)) -> (usize, usize)
+{
+    match mode
+    {
+        TileMode::Mirror => (bounds.0.div_ceil(2), bounds.1.div_ceil(2)),
+        TileMode::Blend => bounds,
+    }
+}
+
+/// Make a tileable texture of size 'bounds' from 'pixels', a render of size render_bounds(mode, bounds) with
+/// 'channels' bytes per pixel.
+pub fn make_tileable(mode: TileMode, pixels: &[u8], bounds: (usize, usize), channels: usize) -> Vec<u8>
+{
+    match mode
+    {
+        TileMode::Mirror => mirror(pixels, render_bounds(mode, bounds), bounds, channels),
+        TileMode::Blend => blend(pixels, bounds, channels),
+    }
+}
+
+/// Reflect 'quadrant' into all four quadrants of a 'bounds'-sized image. Opposite edges come from the same row
+/// or column of the quadrant.
+fn mirror(quadrant: &[u8], quadrant_bounds: (usize, usize), bounds: (usize, usize), channels: usize) -> Vec<u8>
+{
+    let mut out = vec![0; bounds.0 * bounds.1 * channels];
+    for y in 0..bounds.1
+    {
+        let qy = y.min(bounds.1 - 1 - y);
+        for x in 0..bounds.0
+        {
+            let qx = x.min(bounds.0 - 1 - x);
+            let from = (qy * quadrant_bounds.0 + qx) * channels;
+            let to = (y * bounds.0 + x) * channels;
+            out[to..to + channels].copy_from_slice(&quadrant[from..from + channels]);
+        }
+    }
+    out
+}
+
+/// How much of the original image to keep at position 'i' of 'n': 1 in the middle, falling to 0 at both edges.
+fn edge_weight(i: usize, n: usize) -> f64
+{
+    if n < 2
+    {
+        return 1.0;
+    }
+    let half = (n - 1) as f64 / 2.0;
+    (i.min(n - 1 - i) as f64 / half).min(1.0)
+}
+
+/// Crossfade the image with copies of itself shifted by half its width, half its height, and both, using
+/// bilinear weights that hand the edges over entirely to the shifted copies.
+fn blend(pixels: &[u8], bounds: (usize, usize), channels: usize) -> Vec<u8>
+{
+    let (w, h) = bounds;
+    let at = |x: usize, y: usize, c: usize| pixels[((y % h) * w + x % w) * channels + c] as f64;
+    let mut out = vec![0; w * h * channels];
+    for y in 0..h
+    {
+        let wy = edge_weight(y, h);
+        for x in 0..w
+        {
+            let wx = edge_weight(x, w);
+            for c in 0..channels
+            {
+                let value = wx * wy * at(x, y, c)
+                          + (1.0 - wx) * wy * at(x + w / 2, y, c)
+                          + wx * (1.0 - wy) * at(x, y + h / 2, c)
+                          + (1.0 - wx) * (1.0 - wy) * at(x + w / 2, y + h / 2, c);
+                out[(y * w + x) * channels + c] = value.round() as u8;
+            }
+        }
+    }
+    out
+}
+
+/// A 2x2 tiling of the texture, to check by eye that it repeats without seams.
+pub fn tile_preview(pixels: &[u8], bounds: (usize, usize), channels: usize) -> Vec<u8>
+{
+    let row = bounds.0 * channels;
+    let mut out = Vec::with_capacity(pixels.len() * 4);
+    for _ in 0..2
+    {
+        for line in pixels.chunks(row)
+        {
+            out.extend_from_slice(line);
+            out.extend_from_slice(line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+fn gradient(bounds: (usize, usize)) -> Vec<u8>
+{
+    (0..bounds.1).flat_map(|y| (0..bounds.0).map(move |x| (x * 10 + y * 3) as u8)).collect()
+}
+
+#[test]
+fn test_mirror_edges_match()
+{
+    let bounds = (7, 6);
+    let quadrant_bounds = render_bounds(TileMode::Mirror, bounds);
+    assert_eq!(quadrant_bounds, (4, 3));
+    let out = make_tileable(TileMode::Mirror, &gradient(quadrant_bounds), bounds, 1);
+    for y in 0..bounds.1
+    {
+        assert_eq!(out[y * bounds.0], out[y * bounds.0 + bounds.0 - 1]);
+    }
+    assert_eq!(&out[..bounds.0], &out[(bounds.1 - 1) * bounds.0..]);
+    assert_eq!(&out[..bounds.0], &[0, 10, 20, 30, 20, 10, 0]);
+}
+
+#[test]
+fn test_blend_wraps_smoothly()
+{
+    let bounds = (20, 12);
+    let out = make_tileable(TileMode::Blend, &gradient(bounds), bounds, 1);
+    let at = |x: usize, y: usize| out[y * bounds.0 + x] as i32;
+    //Across the wrap, neighbours differ no more than neighbours in the original (10 across, 3 down).
+    for y in 0..bounds.1
+    {
+        assert!((at(0, y) - at(bounds.0 - 1, y)).abs() <= 10, "row {}", y);
+    }
+    for x in 0..bounds.0
+    {
+        assert!((at(x, 0) - at(x, bounds.1 - 1)).abs() <= 10, "column {}", x);
+    }
+    //Weights sum to one, so flat areas stay flat.
+    assert!(make_tileable(TileMode::Blend, &[77; 60], (5, 4), 3).iter().all(|&p| p == 77));
+}
+
+#[test]
+fn test_tile_preview()
+{
+    let bounds = (3, 2);
+    let rgb: Vec<u8> = (0..18).collect();
+    let preview = tile_preview(&rgb, bounds, 3);
+    assert_eq!(preview.len(), 72);
+    assert_eq!(&preview[..9], &rgb[..9]);
+    assert_eq!(&preview[9..18], &rgb[..9]);
+    assert_eq!(&preview[18..27], &rgb[9..18]);
+    assert_eq!(&preview[36..72], &preview[..36]);
+}
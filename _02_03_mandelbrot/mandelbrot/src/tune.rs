@@ -0,0 +1,218 @@
+//! Finding the cheapest settings that still look right, before spending hours on a poster.
+//!
+//! `mandelbrot tune` takes the command line of the render to be tuned. It crops a small probe out of the image at
+//! full resolution, the busiest of nine candidate windows, since flat regions look the same at any setting.
+//! It renders the probe once with generous settings as a reference, then with cheaper candidates, and scores each
+//! against the reference by SSIM (see the compare module). Of the candidates reaching the target, the one that
+//! rendered the probe fastest wins.
+//!
+//! The candidates pair iteration limits from an eighth of the requested limit to four times it with each --ssaa
+//! grid in SSAA, against a reference at eight times the limit and the finest grid. Colors are spread over the
+//! iteration limit, so a candidate and the reference are both painted with the candidate's colors: what is compared
+//! is the pixels the candidate gave up on too early and the edges it sampled too coarsely, which is what a low
+//! limit or a small grid gets wrong. --aa is not tuned; its cost depends on the image rather than on a setting.
+
+use crate::compare;
+use crate::progress::Progress;
+use crate::{escape_value, paint_escape_value, parallel_rows, pixel_to_point, subpixel_to_point, Coloring, Settings};
+use num::Complex;
+use std::time::Instant;
+
+/// The largest probe side, in pixels.
+pub const PROBE: usize = 128;
+
+/// The --ssaa grids to try; the reference uses the last.
+pub const SSAA: [usize; 4] = [1, 2, 3, 4];
+
+/// One candidate's settings and how it did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trial
+{
+    pub limit: usize,
+    pub ssaa: usize,
+    pub ssim: f64,
+    pub psnr: f64,
+    pub seconds: f64, //Time to render the probe.
+}
+
+/// The corners and size of the probe for an image of size 'bounds': of the nine windows on a 3x3 grid, the one
+/// whose render has the most variation in brightness.
+pub fn probe(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+             threads: usize) -> (Complex<f64>, Complex<f64>, (usize, usize))
+{
+    let size = (bounds.0.min(PROBE), bounds.1.min(PROBE));
+    if size == bounds
+    {
+        return (upper_left, lower_right, size);
+    }
+    let mut best = (f64::NEG_INFINITY, upper_left, lower_right);
+    for row in 0..3
+    {
+        for column in 0..3
+        {
+            let left = (bounds.0 - size.0) * column / 2;
+            let top = (bounds.1 - size.1) * row / 2;
+            let corners = (pixel_to_point(bounds, (left, top), upper_left, lower_right),
+                           pixel_to_point(bounds, (left + size.0, top + size.1), upper_left, lower_right));
+            let luma = compare::luma(&render(size, corners, settings, 1, threads), settings.color_type().channels());
+            let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+            let variance = luma.iter().map(|v| (v - mean).powi(2)).sum::<f64>();
+            if variance > best.0
+            {
+                best = (variance, corners.0, corners.1);
+            }
+        }
+    }
+    (best.1, best.2, size)
+}
+
+/// Whether tune can handle 'settings': those that color each sample by the escape value of one point. Any --ssaa
+/// is accepted, since the sweep tries its own.
+pub fn supports(settings: &Settings) -> bool
+{
+    settings.newton.is_none() && settings.line_art.is_none() && matches!(settings.coloring, Coloring::EscapeTime | Coloring::Smooth)
+}
+
+/// Sample 'i' of a row from escape_values(), where the renderer would take it: 'grid'² samples per pixel, laid out
+/// as render_supersampled() does, or the pixel's corner without supersampling.
+fn sample_point(bounds: (usize, usize), (i, row): (usize, usize), grid: usize,
+                (upper_left, lower_right): (Complex<f64>, Complex<f64>)) -> Complex<f64>
+{
+    let samples = grid * grid;
+    match grid
+    {
+        1 => pixel_to_point(bounds, (i, row), upper_left, lower_right),
+        _ => subpixel_to_point(bounds, (i / samples, row), (i % grid, i % samples / grid), grid, upper_left, lower_right),
+    }
+}
+
+/// The escape value of every sample, 'grid'² per pixel, pixel by pixel.
+fn escape_values(bounds: (usize, usize), corners: (Complex<f64>, Complex<f64>), settings: &Settings, grid: usize,
+                 threads: usize) -> Vec<Option<f64>>
+{
+    let mut values = vec![None; bounds.0 * bounds.1 * grid * grid];
+    parallel_rows(&mut values, bounds.0 * grid * grid, threads, &Progress::silent(), |top, row|
+    {
+        for (i, value) in row.iter_mut().enumerate()
+        {
+            *value = escape_value(sample_point(bounds, (i, top), grid, corners), settings);
+        }
+    });
+    values
+}
+
+/// Escape values painted as the renderer would paint them with 'settings', averaging each pixel's samples.
+fn paint(values: &[Option<f64>], bounds: (usize, usize), corners: (Complex<f64>, Complex<f64>), settings: &Settings,
+         grid: usize) -> Vec<u8>
+{
+    let channels = settings.color_type().channels();
+    let samples = grid * grid;
+    let mut pixels = vec![0; bounds.0 * bounds.1 * channels];
+    let mut sample = [0u8; 3];
+    for (i, pixel) in pixels.chunks_mut(channels).enumerate()
+    {
+        let (row, first) = (i / bounds.0, i % bounds.0 * samples);
+        let mut sums = [0usize; 3];
+        for k in 0..samples
+        {
+            let sample = &mut sample[..channels];
+            paint_escape_value(values[i * samples + k], sample_point(bounds, (first + k, row), grid, corners), settings, sample);
+            for (sum, &value) in sums.iter_mut().zip(sample.iter())
+            {
+                *sum += value as usize;
+            }
+        }
+        for (value, sum) in pixel.iter_mut().zip(sums)
+        {
+            *value = ((sum + samples / 2) / samples) as u8;
+        }
+    }
+    pixels
+}
+
+fn render(bounds: (usize, usize), corners: (Complex<f64>, Complex<f64>), settings: &Settings, grid: usize,
+          threads: usize) -> Vec<u8>
+{
+    paint(&escape_values(bounds, corners, settings, grid, threads), bounds, corners, settings, grid)
+}
+
+/// The iteration limits to try for a requested 'limit', and the reference limit.
+pub fn candidate_limits(limit: usize) -> (Vec<usize>, usize)
+{
+    let mut limits: Vec<usize> = [limit / 8, limit / 4, limit / 2, limit, limit * 2, limit * 4].iter()
+        .map(|&candidate| candidate.max(1))
+        .collect();
+    limits.dedup();
+    (limits, limit.saturating_mul(8))
+}
+
+/// Render the probe with each candidate limit at each grid in SSAA and score it against the reference, which uses
+/// 'reference_limit' and the finest grid. The trials come grid by grid, each in the order of 'limits'.
+pub fn sweep(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>, settings: &Settings,
+             threads: usize, limits: &[usize], reference_limit: usize) -> Vec<Trial>
+{
+    let corners = (upper_left, lower_right);
+    let fine = SSAA[SSAA.len() - 1];
+    let reference = escape_values(bounds, corners, &Settings{limit: reference_limit, ..settings.clone()}, fine, threads);
+    let channels = settings.color_type().channels();
+    let expected: Vec<Vec<u8>> = limits.iter()
+        .map(|&limit| paint(&reference, bounds, corners, &Settings{limit, ..settings.clone()}, fine))
+        .collect();
+    SSAA.iter().flat_map(|&ssaa| limits.iter().zip(&expected).map(move |(&limit, expected)| (limit, ssaa, expected)))
+        .map(|(limit, ssaa, expected)|
+        {
+            let settings = Settings{limit, ssaa, ..settings.clone()};
+            let start = Instant::now();
+            let values = escape_values(bounds, corners, &settings, ssaa, threads);
+            let seconds = start.elapsed().as_secs_f64();
+            let pixels = paint(&values, bounds, corners, &settings, ssaa);
+            Trial{limit, ssaa, ssim: compare::ssim(expected, &pixels, bounds, channels), psnr: compare::psnr(expected, &pixels), seconds}
+        })
+        .collect()
+}
+
+/// The fastest trial reaching 'target' SSIM, if any does.
+pub fn recommend(trials: &[Trial], target: f64) -> Option<&Trial>
+{
+    trials.iter().filter(|trial| trial.ssim >= target).min_by(|a, b| a.seconds.total_cmp(&b.seconds))
+}
+
+#[test]
+fn test_candidate_limits()
+{
+    assert_eq!(candidate_limits(1000), (vec![125, 250, 500, 1000, 2000, 4000], 8000));
+    assert_eq!(candidate_limits(4), (vec![1, 2, 4, 8, 16], 32));
+}
+
+#[test]
+fn test_tune()
+{
+    use crate::palette::Palette;
+    use crate::Coloring;
+    let settings = Settings{limit: 200, coloring: Coloring::Smooth, palette: Palette::builtin("classic"), ..Settings::default()};
+    let (upper_left, lower_right) = (Complex{re: -2.0, im: 1.2}, Complex{re: 0.6, im: -1.2});
+    //A small image is its own probe; in a larger one, the probe is on the set's boundary, not in the empty corner.
+    let (probe_upper_left, probe_lower_right, size) = probe((120, 110), upper_left, lower_right, &settings, 2);
+    assert_eq!(size, (120, 110));
+    assert_eq!((probe_upper_left, probe_lower_right), (upper_left, lower_right));
+    let (corner, _, size) = probe((400, 300), upper_left, lower_right, &settings, 2);
+    assert_eq!(size, (PROBE, PROBE));
+    assert!(corner != upper_left, "{}", corner);
+
+    let (limits, reference) = candidate_limits(settings.limit);
+    let trials = sweep((48, 40), Complex{re: -0.76, im: 0.12}, Complex{re: -0.73, im: 0.095}, &settings, 2, &limits, reference);
+    assert_eq!(trials.len(), limits.len() * SSAA.len());
+    assert_eq!((trials[0].limit, trials[0].ssaa), (limits[0], 1));
+    //More iterations, and then more samples, get closer to the reference.
+    let last = |ssaa| trials.iter().rfind(|trial| trial.ssaa == ssaa).unwrap();
+    assert!(trials[0].ssim < last(1).ssim && last(1).ssim < last(4).ssim, "{:?}", trials);
+    let fastest = trials.iter().min_by(|a, b| a.seconds.total_cmp(&b.seconds));
+    assert_eq!(recommend(&trials, 0.0), fastest);
+    assert_eq!(recommend(&trials, 2.0), None);
+    //The fastest passing trial wins, whatever its limit and grid.
+    let timed = |limit, ssaa, ssim, seconds| Trial{limit, ssaa, ssim, psnr: 0.0, seconds};
+    let trials = [timed(100, 1, 0.95, 0.001), timed(200, 2, 0.995, 0.004), timed(400, 1, 0.991, 0.002)];
+    assert_eq!(recommend(&trials, 0.99).map(|trial| (trial.limit, trial.ssaa)), Some((400, 1)));
+    assert!(supports(&settings) && supports(&Settings{ssaa: 3, ..settings.clone()}));
+    assert!(!supports(&Settings{coloring: Coloring::Distance, ..settings}));
+}
@@ -0,0 +1,129 @@
+//! Human-readable formatting of the big numbers renders produce: iteration counts, pixel counts, and bytes.
+//!
+//! Reports go through a NumberFormat, so the same call prints "1.23 G" for a person or "1234567890" for a
+//! script (the machine-readable mode), and digit grouping follows the user's locale when asked to.
+
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat
+{
+    pub machine_readable: bool, //Print raw integers, no units or grouping.
+    pub grouping: Option<char>, //Thousands separator for grouped(), e.g. ',' or '.'.
+    pub decimal: char,          //Decimal point used in scaled values.
+}
+
+impl NumberFormat
+{
+    /// Raw values only, for scripts.
+    pub const MACHINE: NumberFormat = NumberFormat{machine_readable: true, grouping: None, decimal: '.'};
+    /// Units with English-style separators.
+    pub const HUMAN: NumberFormat = NumberFormat{machine_readable: false, grouping: Some(','), decimal: '.'};
+
+    /// Separators for a locale name such as "de_DE.UTF-8" or "fr". Unknown locales get the HUMAN defaults.
+    pub fn for_locale(locale: &str) -> NumberFormat
+    {
+        let language = locale.split(['_', '-', '.']).next().unwrap_or("").to_ascii_lowercase();
+        let (grouping, decimal) = match language.as_str()
+        {
+            "de" | "nl" | "it" | "es" | "pt" | "da" | "id" | "tr" => ('.', ','),
+            "fr" | "ru" | "pl" | "cs" | "sv" | "fi" | "nb" | "uk" => ('\u{202f}', ','),
+            _ => (',', '.'),
+        };
+        NumberFormat{machine_readable: false, grouping: Some(grouping), decimal}
+    }
+
+    /// The locale from LC_ALL, LC_NUMERIC, or LANG (the usual POSIX precedence).
+    pub fn from_env() -> NumberFormat
+    {
+        ["LC_ALL", "LC_NUMERIC", "LANG"].iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map(|locale| NumberFormat::for_locale(&locale))
+            .unwrap_or(NumberFormat::HUMAN)
+    }
+
+    /// An integer with thousands separators: 1234567 -> "1,234,567".
+    pub fn grouped(&self, n: u64) -> String
+    {
+        let digits = n.to_string();
+        let separator = match (self.machine_readable, self.grouping)
+        {
+            (false, Some(separator)) => separator,
+            _ => return digits,
+        };
+        let mut out = String::with_capacity(digits.len() * 2);
+        for (i, digit) in digits.chars().enumerate()
+        {
+            if i > 0 && (digits.len() - i).is_multiple_of(3)
+            {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        out
+    }
+
+    /// A count scaled by powers of 1000: 1234567890 -> "1.23 G". 'unit' is appended to the prefix, e.g. "px" gives "Mpx".
+    pub fn count(&self, n: u64, unit: &str) -> String
+    {
+        self.scaled(n, 1000.0, &["", "k", "M", "G", "T", "P", "E"], unit)
+    }
+
+    /// A byte count scaled by powers of 1024: 1536 -> "1.50 KiB".
+    pub fn bytes(&self, n: u64) -> String
+    {
+        self.scaled(n, 1024.0, &["", "Ki", "Mi", "Gi", "Ti", "Pi", "Ei"], "B")
+    }
+
+    fn scaled(&self, n: u64, base: f64, prefixes: &[&str], unit: &str) -> String
+    {
+        if self.machine_readable
+        {
+            return n.to_string();
+        }
+        let mut value = n as f64;
+        let mut prefix = 0;
+        while value >= base && prefix + 1 < prefixes.len()
+        {
+            value /= base;
+            prefix += 1;
+        }
+        let number = if prefix == 0
+        {
+            self.grouped(n)
+        }
+        else
+        {
+            //Three significant digits are enough to read at a glance.
+            let decimals = if value < 10.0 { 2 } else if value < 100.0 { 1 } else { 0 };
+            format!("{:.*}", decimals, value).replace('.', &self.decimal.to_string())
+        };
+        let suffix = format!("{}{}", prefixes[prefix], unit);
+        if suffix.is_empty() { number } else { format!("{} {}", number, suffix) }
+    }
+}
+
+#[test]
+fn test_grouped()
+{
+    assert_eq!(NumberFormat::HUMAN.grouped(0), "0");
+    assert_eq!(NumberFormat::HUMAN.grouped(999), "999");
+    assert_eq!(NumberFormat::HUMAN.grouped(1234567), "1,234,567");
+    assert_eq!(NumberFormat::for_locale("de_DE.UTF-8").grouped(1234567), "1.234.567");
+    assert_eq!(NumberFormat::MACHINE.grouped(1234567), "1234567");
+}
+
+#[test]
+fn test_count_and_bytes()
+{
+    let human = NumberFormat::HUMAN;
+    assert_eq!(human.count(1234567890, ""), "1.23 G");
+    assert_eq!(human.count(2_073_600, "px"), "2.07 Mpx");
+    assert_eq!(human.count(512, ""), "512");
+    assert_eq!(human.bytes(1536), "1.50 KiB");
+    assert_eq!(human.bytes(3 * 1024 * 1024 * 1024), "3.00 GiB");
+    assert_eq!(NumberFormat::for_locale("fr_FR").bytes(1536), "1,50 KiB");
+    assert_eq!(NumberFormat::MACHINE.bytes(1536), "1536");
+    assert_eq!(NumberFormat::MACHINE.count(1234567890, "it"), "1234567890");
+}
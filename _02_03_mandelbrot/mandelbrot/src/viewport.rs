@@ -0,0 +1,191 @@
+//! The rectangle of the complex plane an image shows, and the usual ways of moving it around.
+//!
+//! A Viewport is stored as a center plus a width and height in complex units, together with the image size in
+//! pixels. Corner pairs (the form the command line and pixel_to_point use) are derived from it on demand, so
+//! zooming and panning never have to redo corner arithmetic by hand.
+
+use num::Complex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport
+{
+    pub bounds: (usize, usize), //Image size in pixels, as in pixel_to_point.
+    pub center: Complex<f64>,
+    pub width: f64,             //Extent along the real axis.
+    pub height: f64,            //Extent along the imaginary axis.
+}
+
+impl Viewport
+{
+    /// Build a viewport from the corner pair used on the command line.
+    pub fn from_corners(bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>) -> Viewport
+    {
+        Viewport
+        {
+            bounds,
+            center: (upper_left + lower_right) / 2.0,
+            width: lower_right.re - upper_left.re,
+            height: upper_left.im - lower_right.im,
+        }
+    }
+
+    /// The view centered on 'center' at magnification 'zoom', with square pixels. Zoom 1 is 4 units wide, the
+    /// classic -2..2 range, and each doubling of the zoom halves the width.
+    pub fn from_zoom(bounds: (usize, usize), center: Complex<f64>, zoom: f64) -> Viewport
+    {
+        let width = 4.0 / zoom;
+        Viewport{bounds, center, width, height: width * bounds.1 as f64 / bounds.0 as f64}
+    }
+
+    /// The (upper_left, lower_right) corner pair of this viewport.
+    pub fn corners(&self) -> (Complex<f64>, Complex<f64>)
+    {
+        let half = Complex{re: self.width / 2.0, im: self.height / 2.0};
+        (Complex{re: self.center.re - half.re, im: self.center.im + half.im},
+         Complex{re: self.center.re + half.re, im: self.center.im - half.im})
+    }
+
+    /// The complex point at the top-left corner of 'pixel' (same convention as pixel_to_point).
+    pub fn pixel_to_point(&self, pixel: (usize, usize)) -> Complex<f64>
+    {
+        let (upper_left, lower_right) = self.corners();
+        crate::pixel_to_point(self.bounds, pixel, upper_left, lower_right)
+    }
+
+    /// Like pixel_to_point, but for fractional pixel positions: (0.5, 0.5) is the center of the first pixel.
+    pub fn point_at(&self, pixel: (f64, f64)) -> Complex<f64>
+    {
+        let (upper_left, _) = self.corners();
+        Complex
+        {
+            re: upper_left.re + pixel.0 * self.width / self.bounds.0 as f64,
+            im: upper_left.im - pixel.1 * self.height / self.bounds.1 as f64,
+        }
+    }
+
+    /// The inverse of pixel_to_point, in fractional pixels. Points outside the viewport give coordinates
+    /// outside 0..bounds.
+    pub fn point_to_pixel(&self, point: Complex<f64>) -> (f64, f64)
+    {
+        let (upper_left, _) = self.corners();
+        ((point.re - upper_left.re) / self.width * self.bounds.0 as f64,
+         (upper_left.im - point.im) / self.height * self.bounds.1 as f64)
+    }
+
+    /// Zoom in by 'factor' (values below 1 zoom out) while keeping 'point' at the same place in the image.
+    /// This is what cursor-anchored zooming wants; zooming about self.center is the special case.
+    pub fn zoom_about(&self, point: Complex<f64>, factor: f64) -> Viewport
+    {
+        Viewport
+        {
+            center: point + (self.center - point) / factor,
+            width: self.width / factor,
+            height: self.height / factor,
+            ..*self
+        }
+    }
+
+    /// Move the view by a number of pixels: positive x pans right, positive y pans down, the same directions
+    /// as pixel coordinates.
+    pub fn pan_by(&self, pixels: (f64, f64)) -> Viewport
+    {
+        let shift = Complex
+        {
+            re: pixels.0 * self.width / self.bounds.0 as f64,
+            im: -pixels.1 * self.height / self.bounds.1 as f64,
+        };
+        Viewport{center: self.center + shift, ..*self}
+    }
+
+    /// The smallest viewport of the given pixel size that contains all 'points', with square pixels.
+    /// Returns None for an empty list; a single point gets a tiny but non-degenerate view around it.
+    pub fn fit(bounds: (usize, usize), points: &[Complex<f64>]) -> Option<Viewport>
+    {
+        let first = *points.first()?;
+        let (mut lo, mut hi) = (first, first);
+        for p in points
+        {
+            lo = Complex{re: lo.re.min(p.re), im: lo.im.min(p.im)};
+            hi = Complex{re: hi.re.max(p.re), im: hi.im.max(p.im)};
+        }
+        //Pick the pixel size that fits the tighter axis, then derive the other extent from the aspect ratio.
+        let per_pixel = ((hi.re - lo.re) / bounds.0 as f64)
+            .max((hi.im - lo.im) / bounds.1 as f64)
+            .max(f64::EPSILON * first.norm().max(1.0));
+        Some(Viewport
+        {
+            bounds,
+            center: (lo + hi) / 2.0,
+            width: per_pixel * bounds.0 as f64,
+            height: per_pixel * bounds.1 as f64,
+        })
+    }
+
+    /// Grow the viewport by 'fraction' of its size on every side (0.1 is a 10% margin), keeping the center.
+    pub fn padded(&self, fraction: f64) -> Viewport
+    {
+        Viewport{width: self.width * (1.0 + 2.0 * fraction), height: self.height * (1.0 + 2.0 * fraction), ..*self}
+    }
+}
+
+#[cfg(test)]
+fn assert_close(a: Complex<f64>, b: Complex<f64>)
+{
+    assert!((a - b).norm() < 1e-12, "{} != {}", a, b);
+}
+
+#[test]
+fn test_viewport_corners_round_trip()
+{
+    let (ul, lr) = (Complex{re: -1.20, im: 0.35}, Complex{re: -1.0, im: 0.20});
+    let view = Viewport::from_corners((1000, 750), ul, lr);
+    let (ul2, lr2) = view.corners();
+    assert_close(ul, ul2);
+    assert_close(lr, lr2);
+    assert_eq!(view.pixel_to_point((25, 175)), crate::pixel_to_point((1000, 750), (25, 175), ul2, lr2));
+    assert_close(view.point_at((25.0, 175.0)), view.pixel_to_point((25, 175)));
+    let (x, y) = view.point_to_pixel(view.pixel_to_point((25, 175)));
+    assert!((x - 25.0).abs() < 1e-9 && (y - 175.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_viewport_zoom_about_keeps_point_fixed()
+{
+    let view = Viewport::from_corners((800, 600), Complex{re: -2.0, im: 1.5}, Complex{re: 2.0, im: -1.5});
+    let anchor = view.pixel_to_point((200, 450));
+    let zoomed = view.zoom_about(anchor, 8.0);
+    assert_close(zoomed.pixel_to_point((200, 450)), anchor);
+    assert!((zoomed.width - 0.5).abs() < 1e-12);
+    assert_close(view.zoom_about(anchor, 8.0).zoom_about(anchor, 0.125).center, view.center);
+}
+
+#[test]
+fn test_viewport_pan_by()
+{
+    let view = Viewport::from_corners((100, 100), Complex{re: 0.0, im: 1.0}, Complex{re: 1.0, im: 0.0});
+    let moved = view.pan_by((10.0, 20.0));
+    assert_close(moved.center, Complex{re: 0.6, im: 0.3});
+}
+
+#[test]
+fn test_viewport_fit()
+{
+    let points = [Complex{re: -2.0, im: 0.0}, Complex{re: 0.5, im: 1.0}, Complex{re: 0.0, im: -1.0}];
+    let view = Viewport::fit((400, 400), &points).unwrap();
+    for p in points
+    {
+        let (x, y) = view.point_to_pixel(p);
+        assert!((-1e-9..=400.0 + 1e-9).contains(&x) && (-1e-9..=400.0 + 1e-9).contains(&y));
+    }
+    assert!((view.width - 2.5).abs() < 1e-12 && (view.height - 2.5).abs() < 1e-12);
+    assert_eq!(Viewport::fit((400, 400), &[]), None);
+}
+
+#[test]
+fn test_viewport_padded()
+{
+    let view = Viewport::from_corners((100, 100), Complex{re: -1.0, im: 1.0}, Complex{re: 1.0, im: -1.0}).padded(0.25);
+    let (ul, lr) = view.corners();
+    assert_close(ul, Complex{re: -1.5, im: 1.5});
+    assert_close(lr, Complex{re: 1.5, im: -1.5});
+}